@@ -0,0 +1,13 @@
+-- Generic OpenID Connect providers are configured by name,
+-- `oidc_provider` holds that config key for rows with the 'oidc' provider
+alter type social_provider add value if not exists 'oidc';
+
+alter table social_login
+add column oidc_provider text;
+
+-- Subject ids are only unique within a single provider
+alter table social_login
+drop constraint social_login_provider_user_id_key;
+
+create unique index social_login_provider_user_key
+on social_login (provider, coalesce(oidc_provider, ''), provider_user_id);
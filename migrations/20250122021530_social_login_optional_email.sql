@@ -0,0 +1,4 @@
+-- Providers linked from the account page may not share an email
+-- with the user, or may not return one at all
+alter table social_login
+alter column email_id drop not null;
@@ -0,0 +1,5 @@
+create type locale as enum ('en', 'mn');
+
+-- Preferred language, used for emails
+alter table "user"
+    add column locale locale not null default 'en';
@@ -0,0 +1,2 @@
+-- New enum values can't be used in the transaction that adds them
+alter type app_permission add value 'email.manage';
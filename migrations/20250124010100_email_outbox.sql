@@ -0,0 +1,32 @@
+-- `sending` while claimed by a worker, `next_attempt_at` is when the claim runs out
+create type email_status as enum ('queued', 'sending', 'sent', 'failed');
+
+-- Emails are written here in the same transaction as the change that
+-- triggers them, and delivered by the background worker
+create table email_outbox
+(
+    email_outbox_id     uuid primary key default uuid_generate_v1mc(),
+    sender              text not null,
+    recipient           text not null,
+    template            text not null,
+    locale              locale not null default 'en',
+    -- Holds tokens and codes, cleared once the email is sent or suppressed
+    data                jsonb,
+    status              email_status not null default 'queued',
+    attempts            int not null default 0,
+    last_error          text,
+    next_attempt_at     timestamptz not null default now(),
+    sent_at             timestamptz,
+    created_at          timestamptz not null default now(),
+    updated_at          timestamptz
+);
+
+select trigger_updated_at('email_outbox');
+
+-- Emails of a worker that stopped while sending are due again once their claim runs out
+create index email_outbox_due_idx on email_outbox (next_attempt_at) where status in ('queued', 'sending');
+create index email_outbox_status_idx on email_outbox (status, created_at desc);
+
+insert into role_permission (role, permission)
+values
+    ('root', 'email.manage');
@@ -0,0 +1,24 @@
+create type suppression_reason as enum ('bounce', 'complaint', 'transient');
+
+-- Addresses we must stop sending to, filled from SES bounce/complaint notifications
+create table email_suppression
+(
+    email_suppression_id    uuid primary key default uuid_generate_v1mc(),
+    email                   text collate "case_insensitive" unique not null,
+    reason                  suppression_reason not null,
+    -- Bounce sub type or complaint feedback type reported by SES
+    detail                  text,
+    -- Soft bounces only suppress the address until then,
+    -- bounces and complaints stay until an admin lifts them
+    expires_at              timestamptz,
+    created_at              timestamptz not null default now(),
+    updated_at              timestamptz
+);
+
+select trigger_updated_at('email_suppression');
+
+-- Shown to the user so they can replace the address
+alter table email
+    add column delivery_issue suppression_reason;
+
+alter type email_status add value 'suppressed';
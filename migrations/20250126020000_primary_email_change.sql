@@ -0,0 +1,25 @@
+create type email_change_status as enum ('pending', 'applied', 'cancelled');
+
+-- History of primary email changes. A change waits until `effective_at`
+-- so the owner of the old address can cancel it with the emailed link.
+create table primary_email_change
+(
+    primary_email_change_id uuid primary key default uuid_generate_v1mc(),
+    user_id                 uuid not null references "user" (user_id) on delete cascade,
+    old_email               text not null,
+    new_email_id            uuid references email (email_id) on delete set null,
+    new_email               text not null,
+    cancel_token_hash       text not null unique,
+    status                  email_change_status not null default 'pending',
+    effective_at            timestamptz not null,
+    resolved_at             timestamptz,
+    created_at              timestamptz not null default now(),
+    updated_at              timestamptz
+);
+
+select trigger_updated_at('primary_email_change');
+
+-- Only one change can be waiting per user
+create unique index primary_email_change_pending_idx on primary_email_change (user_id) where status = 'pending';
+create index primary_email_change_due_idx on primary_email_change (effective_at) where status = 'pending';
+create index primary_email_change_user_idx on primary_email_change (user_id, created_at desc);
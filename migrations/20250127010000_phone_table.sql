@@ -0,0 +1,23 @@
+-- Phone numbers are stored in E.164 format, e.g. +97699112233
+create table phone
+(
+    phone_id                uuid primary key default uuid_generate_v1mc(),
+    user_id                 uuid not null references "user" (user_id) on delete cascade,
+
+    phone                   text not null,
+    verified                boolean not null default false,
+    is_primary              boolean not null default false,
+    confirmation_sent_at    timestamptz,
+
+    created_at              timestamptz not null default now(),
+    updated_at              timestamptz,
+
+    unique (user_id, phone)
+);
+
+-- Only one account can verify a number, unverified ones don't block it for others
+create unique index phone_verified_phone_key on phone (phone) where verified;
+
+select trigger_updated_at('phone');
+
+create index phone_user_id_idx on phone (user_id);
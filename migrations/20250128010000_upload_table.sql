@@ -0,0 +1,25 @@
+-- Matches `S3Path`, the first segment of the key
+create type s3_path as enum ('profile');
+
+create type upload_status as enum ('pending', 'confirmed');
+
+-- Files handed a presigned upload, confirmed once the object is checked in storage
+create table upload
+(
+    upload_id       uuid primary key default uuid_generate_v1mc(),
+    user_id         uuid not null references "user" (user_id) on delete cascade,
+
+    path            s3_path not null,
+    key             text unique not null,
+    mime_type       text not null,
+    size            bigint not null,
+    status          upload_status not null default 'pending',
+    confirmed_at    timestamptz,
+
+    created_at      timestamptz not null default now(),
+    updated_at      timestamptz
+);
+
+select trigger_updated_at('upload');
+
+create index upload_user_id_idx on upload (user_id);
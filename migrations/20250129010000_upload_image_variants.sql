@@ -0,0 +1,12 @@
+-- Set by `ImageWorker` once the variants of an image upload are stored
+alter table upload
+add column processed_at timestamptz,
+add column processing_error text,
+add column variants_size bigint not null default 0,
+-- `ImageWorker` claims an upload for `images.lease_secs` from `processing_started_at`,
+-- and gives up on it after `images.max_attempts`
+add column processing_started_at timestamptz,
+add column processing_attempts int not null default 0;
+
+create index upload_unprocessed_idx on upload (confirmed_at)
+where status = 'confirmed' and processed_at is null;
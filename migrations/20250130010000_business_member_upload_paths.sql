@@ -0,0 +1,28 @@
+create type business_role as enum ('owner', 'manager');
+
+-- Users managing a business, e.g. uploading its logo and documents
+create table business_member
+(
+    business_id     uuid not null references business (business_id) on delete cascade,
+    user_id         uuid not null references "user" (user_id) on delete cascade,
+    role            business_role not null default 'manager',
+
+    created_at      timestamptz not null default now(),
+    updated_at      timestamptz,
+    primary key (business_id, user_id)
+);
+
+select trigger_updated_at('business_member');
+
+create index business_member_user_id_idx on business_member (user_id);
+
+-- Business files are stored under `business/{business_id}/...`
+alter type s3_path add value 'business_logo';
+alter type s3_path add value 'business_gallery';
+alter type s3_path add value 'business_document';
+
+-- Set for business paths, `user_id` is then the member who uploaded it
+alter table upload
+add column business_id uuid references business (business_id) on delete cascade;
+
+create index upload_business_id_idx on upload (business_id);
@@ -0,0 +1,18 @@
+-- Who was handed a download URL of which file
+create table file_access_log
+(
+    file_access_log_id  uuid primary key default uuid_generate_v1mc(),
+    upload_id           uuid references upload (upload_id) on delete set null,
+    key                 text not null,
+    user_id             uuid references "user" (user_id) on delete set null,
+    created_at          timestamptz not null default now()
+);
+
+create index file_access_log_key_idx on file_access_log (key, created_at desc);
+create index file_access_log_user_id_idx on file_access_log (user_id);
+
+-- Reviewing the business verification documents
+insert into role_permission (role, permission)
+values
+    ('root', 'business.verify'),
+    ('moderator', 'business.verify');
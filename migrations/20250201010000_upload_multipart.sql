@@ -0,0 +1,9 @@
+alter type s3_path add value 'business_menu';
+alter type s3_path add value 'business_video';
+
+-- Set while the file is uploaded in parts, cleared once completed
+alter table upload
+add column multipart_id text,
+add column multipart_started_at timestamptz;
+
+create index upload_multipart_idx on upload (multipart_started_at) where multipart_id is not null;
@@ -0,0 +1,6 @@
+-- Replaced profile images are found by the uploads no user refers to
+create index user_image_idx on "user" (image) where image is not null;
+
+-- Reviewing the orphaned objects report
+insert into role_permission (role, permission)
+values ('root', 'storage.manage');
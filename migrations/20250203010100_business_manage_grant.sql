@@ -0,0 +1,3 @@
+-- Creating and editing businesses from the admin
+insert into role_permission (role, permission)
+values ('root', 'business.manage');
@@ -0,0 +1,6 @@
+-- `line_2` is optional, only check the translation when it is set
+alter table address drop constraint chk_l10n_address_line_2_en_not_null;
+
+alter table address
+add constraint chk_l10n_address_line_2_en_not_null
+check (line_2 is null or (line_2 ? 'en' and line_2->'en' is not null));
@@ -0,0 +1,12 @@
+-- Only published businesses are listed publicly
+alter table business add column published_at timestamptz;
+
+create index business_published_idx on business (created_at desc, business_id desc)
+where published_at is not null;
+
+-- Area the address is in, e.g. its district
+alter table address
+add column area_id uuid references address_area (area_id) on delete set null;
+
+create index address_area_id_idx on address (area_id);
+create index address_business_id_idx on address (business_id);
@@ -0,0 +1,2 @@
+-- Nearby search measures in meters on the geography, the index has to be on the same expression
+create index address_geom_geography_idx on address using gist ((geom::geography));
@@ -0,0 +1,32 @@
+create extension if not exists pg_trgm;
+
+-- Mongolian Cyrillic in Latin letters the way people type it, so "Сакура" and "Sakura" both become "sakura".
+-- Keep in sync with `transliterate` in src/app/utils/transliterate.rs
+create or replace function mn_latin(input text) returns text as $$
+    select replace(
+        translate(
+            replace(replace(replace(replace(replace(replace(replace(
+                lower(input),
+                'ё', 'yo'), 'ц', 'ts'), 'ч', 'ch'), 'ш', 'sh'), 'щ', 'sh'), 'ю', 'yu'), 'я', 'ya'),
+            'абвгдежзийклмнопрстуфхыьэөүöüъ',
+            'abvgdejziiklmnoprstufhiieuuuu'
+        ),
+        'kh', 'h'
+    )
+$$ language sql immutable strict parallel safe;
+
+-- Every locale of a localized text, transliterated
+create or replace function localized_search_text(texts hstore) returns text as $$
+    select mn_latin(array_to_string(avals(texts), ' '))
+$$ language sql immutable strict parallel safe;
+
+alter table business
+add column search_name text generated always as (localized_search_text(name)) stored;
+
+create index business_search_name_trgm_idx on business using gin (search_name gin_trgm_ops);
+create index business_search_name_tsv_idx on business using gin (to_tsvector('simple', search_name));
+
+alter table category
+add column search_name text generated always as (localized_search_text(name)) stored;
+
+create index category_search_name_trgm_idx on category using gin (search_name gin_trgm_ops);
@@ -0,0 +1,58 @@
+-- Managing and importing address areas from the admin
+insert into role_permission (role, permission)
+values ('root', 'area.manage');
+
+-- Imports match areas by code, e.g. the ISO 3166-2 code
+alter table address_area add column code text unique;
+
+update address_area set code = 'MN' where name->'en' = 'Mongolia' and type = 'Country';
+update address_area set code = 'MN-1' where name->'en' = 'Ulaanbaatar' and type = 'Province';
+update address_area
+set code = 'MN-1-' || lower(replace(name->'en', ' ', '-'))
+where type = 'District'
+    and parent_id = (select area_id from address_area where code = 'MN-1');
+
+alter table address_area add column boundary geometry(multipolygon, 4326);
+
+create index address_area_boundary_idx on address_area using gist (boundary);
+create index address_area_parent_id_idx on address_area (parent_id);
+
+-- Most specific area with a boundary covering the point, e.g. its district
+create function find_address_area(point geometry) returns uuid as $$
+    select area_id
+    from address_area
+    where boundary is not null and st_covers(boundary, point)
+    order by type desc, st_area(boundary)
+    limit 1
+$$ language sql stable;
+
+-- Addresses with a location but without an area are put in the area they're in.
+-- Moving an address puts it in the area it's moved to, unless the same statement
+-- also sets the area
+create function assign_address_area() returns trigger as $$
+declare
+    found_area uuid;
+begin
+    if new.geom is null then
+        return new;
+    end if;
+
+    if tg_op = 'INSERT' then
+        if new.area_id is null then
+            new.area_id := find_address_area(new.geom);
+        end if;
+    elsif new.geom is distinct from old.geom and new.area_id is not distinct from old.area_id then
+        -- Outside of every boundary the area can only have been picked by hand, keep it
+        found_area := find_address_area(new.geom);
+        new.area_id := coalesce(found_area, new.area_id);
+    elsif new.area_id is null then
+        new.area_id := find_address_area(new.geom);
+    end if;
+
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger assign_address_area
+before insert or update of geom, area_id on address
+for each row execute function assign_address_area();
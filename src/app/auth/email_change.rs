@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use base32::encode;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::config::PrimaryEmailChangeConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "email_change_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EmailChangeStatus {
+    /// Waiting for the grace period, can still be cancelled
+    Pending,
+    Applied,
+    /// Cancelled from the old address, or the new email was removed
+    Cancelled,
+}
+
+/// Random token for the cancel link sent to the old address
+pub fn generate_cancel_token() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes)
+}
+
+/// Only the hash of the cancel token is stored
+pub fn hash_cancel_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token);
+
+    hex::encode(hasher.finalize())
+}
+
+/// Applies the primary email changes whose grace period is over.
+#[derive(Clone)]
+pub struct EmailChangeWorker {
+    db_pool: Arc<PgPool>,
+    config: PrimaryEmailChangeConfig,
+}
+
+impl EmailChangeWorker {
+    pub fn new(db_pool: Arc<PgPool>, config: PrimaryEmailChangeConfig) -> Self {
+        Self { db_pool, config }
+    }
+
+    /// Applies the due changes every `poll_interval_secs`, never returns
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.poll_interval_secs,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.apply_due().await {
+                tracing::error!("failed to apply primary email changes: {:?}", e);
+            }
+        }
+    }
+
+    /// Switches the primary email of every due change, returns how many were resolved.
+    ///
+    /// A change is cancelled instead when the new email was removed in the meantime.
+    #[tracing::instrument(name = "Apply primary email changes", skip_all)]
+    pub async fn apply_due(&self) -> anyhow::Result<usize> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let due = sqlx::query!(
+            r#"
+                select
+                    c.primary_email_change_id,
+                    c.user_id,
+                    c.new_email_id,
+                    coalesce(e.verified, false) as "verified!"
+                from primary_email_change c
+                left join email e on e.email_id = c.new_email_id and e.user_id = c.user_id
+                where c.status = 'pending' and c.effective_at <= now()
+                order by c.effective_at
+                limit 100
+                for update of c skip locked
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("failed to fetch due email changes")?;
+
+        for change in &due {
+            let status = match change.new_email_id {
+                Some(new_email_id) if change.verified => {
+                    sqlx::query!(
+                        r#"
+                            update email
+                            set is_primary = (email_id = $2)
+                            where user_id = $1 and (is_primary or email_id = $2)
+                        "#,
+                        change.user_id,
+                        new_email_id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+
+                    EmailChangeStatus::Applied
+                }
+                _ => EmailChangeStatus::Cancelled,
+            };
+
+            sqlx::query!(
+                r#"
+                    update primary_email_change
+                    set status = $2, resolved_at = now()
+                    where primary_email_change_id = $1
+                "#,
+                change.primary_email_change_id,
+                status as EmailChangeStatus
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            tracing::info!(
+                "primary email change {} of user {} {:?}",
+                change.primary_email_change_id,
+                change.user_id,
+                status
+            );
+        }
+
+        tx.commit().await?;
+
+        Ok(due.len())
+    }
+}
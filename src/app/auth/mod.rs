@@ -1,3 +1,4 @@
+pub mod email_change;
 pub mod password;
 pub mod scope;
 pub mod session;
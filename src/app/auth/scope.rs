@@ -18,12 +18,32 @@ pub enum AppPermission {
     #[sqlx(rename = "user.view")]
     #[serde(rename = "user.view")]
     UserView,
+    #[sqlx(rename = "email.manage")]
+    #[serde(rename = "email.manage")]
+    EmailManage,
+    #[sqlx(rename = "business.verify")]
+    #[serde(rename = "business.verify")]
+    BusinessVerify,
+    #[sqlx(rename = "storage.manage")]
+    #[serde(rename = "storage.manage")]
+    StorageManage,
+    #[sqlx(rename = "business.manage")]
+    #[serde(rename = "business.manage")]
+    BusinessManage,
+    #[sqlx(rename = "area.manage")]
+    #[serde(rename = "area.manage")]
+    AreaManage,
 }
 
 impl std::fmt::Display for AppPermission {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let scope_str = match self {
             AppPermission::UserView => "user.view",
+            AppPermission::EmailManage => "email.manage",
+            AppPermission::BusinessVerify => "business.verify",
+            AppPermission::StorageManage => "storage.manage",
+            AppPermission::BusinessManage => "business.manage",
+            AppPermission::AreaManage => "area.manage",
         };
         write!(f, "{}", scope_str)
     }
@@ -47,6 +67,11 @@ impl FromStr for AppPermission {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "user.view" => Ok(Self::UserView),
+            "email.manage" => Ok(Self::EmailManage),
+            "business.verify" => Ok(Self::BusinessVerify),
+            "storage.manage" => Ok(Self::StorageManage),
+            "business.manage" => Ok(Self::BusinessManage),
+            "area.manage" => Ok(Self::AreaManage),
             _ => Err(format!("Unknown permission: {}", s)),
         }
     }
@@ -0,0 +1,273 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::app::{error::AppError, utils::types::LocalizedText};
+
+/// Areas nest from country to district, in this order
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema, sqlx::Type,
+)]
+#[sqlx(type_name = "addr_area_type")]
+#[serde(rename_all = "lowercase")]
+pub enum AreaType {
+    Country,
+    Province,
+    City,
+    District,
+}
+
+/// Only a country has no parent, any other area is inside a broader one
+pub fn valid_parent(area_type: AreaType, parent_type: Option<AreaType>) -> bool {
+    match parent_type {
+        None => area_type == AreaType::Country,
+        Some(parent_type) => parent_type < area_type,
+    }
+}
+
+/// Boundaries are GeoJSON polygons or multipolygons
+pub fn valid_boundary(boundary: &serde_json::Value) -> bool {
+    matches!(
+        boundary["type"].as_str(),
+        Some("Polygon") | Some("MultiPolygon")
+    ) && boundary["coordinates"].is_array()
+}
+
+/// Area of an import file, areas are matched by `code` and created or updated
+#[derive(Debug, Deserialize)]
+pub struct AreaRecord {
+    pub code: String,
+    /// Has to be imported before or already exist
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub parent_code: Option<String>,
+    #[serde(rename = "type")]
+    pub area_type: AreaType,
+    pub name_en: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub name_mn: Option<String>,
+    /// GeoJSON geometry, a JSON string in CSV files
+    #[serde(skip)]
+    pub boundary: Option<serde_json::Value>,
+}
+
+impl AreaRecord {
+    pub fn name(&self) -> LocalizedText {
+        LocalizedText {
+            en: self.name_en.clone(),
+            mn: self.name_mn.clone(),
+        }
+    }
+}
+
+fn empty_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    Ok(value.filter(|v| !v.trim().is_empty()))
+}
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    properties: serde_json::Value,
+    geometry: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    code: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    parent_code: Option<String>,
+    #[serde(rename = "type")]
+    area_type: AreaType,
+    name_en: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    name_mn: Option<String>,
+    #[serde(default)]
+    boundary: String,
+}
+
+fn row_error(row: usize, code: &'static str) -> AppError {
+    AppError::unprocessable_entity([(Cow::Owned(format!("rows[{}]", row)), code)])
+}
+
+/// Reads a GeoJSON `FeatureCollection`, the area fields are the feature properties
+pub fn parse_geojson(bytes: &[u8]) -> Result<Vec<AreaRecord>, AppError> {
+    let collection: FeatureCollection = serde_json::from_slice(bytes)
+        .map_err(|_| AppError::unprocessable_entity([("file", "invalid_geojson")]))?;
+
+    collection
+        .features
+        .into_iter()
+        .enumerate()
+        .map(|(i, feature)| {
+            let mut record: AreaRecord =
+                serde_json::from_value(feature.properties).map_err(|_| row_error(i, "invalid"))?;
+            record.boundary = feature.geometry;
+            Ok(record)
+        })
+        .collect()
+}
+
+/// Reads a CSV with a header of `code,parent_code,type,name_en,name_mn,boundary`,
+/// the boundary is optional and written as GeoJSON.
+pub fn parse_csv(bytes: &[u8]) -> Result<Vec<AreaRecord>, AppError> {
+    csv::Reader::from_reader(bytes)
+        .deserialize::<CsvRow>()
+        .enumerate()
+        .map(|(i, row)| {
+            let row = row.map_err(|_| row_error(i, "invalid"))?;
+
+            let boundary = if row.boundary.trim().is_empty() {
+                None
+            } else {
+                let boundary = serde_json::from_str(&row.boundary)
+                    .map_err(|_| row_error(i, "invalid_boundary"))?;
+                Some(boundary)
+            };
+
+            Ok(AreaRecord {
+                code: row.code,
+                parent_code: row.parent_code,
+                area_type: row.area_type,
+                name_en: row.name_en,
+                name_mn: row.name_mn,
+                boundary,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct AreaImportSummary {
+    pub created: u64,
+    pub updated: u64,
+    /// Addresses put in one of the imported areas by their location
+    pub assigned_addresses: u64,
+}
+
+/// Creates or updates the areas in file order, nothing is imported when a row is invalid.
+#[tracing::instrument(name = "Import address areas", skip_all, fields(count = records.len()))]
+pub async fn import_areas(
+    records: &[AreaRecord],
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<AreaImportSummary, AppError> {
+    let mut summary = AreaImportSummary::default();
+
+    for (i, record) in records.iter().enumerate() {
+        if record.code.trim().is_empty() || record.name_en.trim().is_empty() {
+            return Err(row_error(i, "invalid"));
+        }
+
+        if record.boundary.as_ref().is_some_and(|b| !valid_boundary(b)) {
+            return Err(row_error(i, "invalid_boundary"));
+        }
+
+        let parent = match &record.parent_code {
+            Some(parent_code) => Some(
+                sqlx::query!(
+                    r#"
+                        select area_id, type as "area_type: AreaType"
+                        from address_area
+                        where code = $1
+                    "#,
+                    parent_code
+                )
+                .fetch_optional(&mut **tx)
+                .await?
+                .ok_or_else(|| row_error(i, "parent_not_found"))?,
+            ),
+            None => None,
+        };
+
+        if !valid_parent(record.area_type, parent.as_ref().map(|p| p.area_type)) {
+            return Err(row_error(i, "invalid_parent"));
+        }
+
+        let area = sqlx::query!(
+            r#"
+                insert into address_area (code, name, type, parent_id, boundary)
+                values (
+                    $1, $2, $3, $4,
+                    st_multi(st_setsrid(st_geomfromgeojson($5), 4326))
+                )
+                on conflict (code) do update
+                set name = excluded.name,
+                    type = excluded.type,
+                    parent_id = excluded.parent_id,
+                    boundary = coalesce(excluded.boundary, address_area.boundary)
+                returning area_id, xmax = 0 as "inserted!"
+            "#,
+            record.code,
+            record.name().to_hstore() as _,
+            record.area_type as AreaType,
+            parent.map(|p| p.area_id),
+            record.boundary.as_ref().map(|b| b.to_string())
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if !children_allow_type(&area.area_id, record.area_type, &mut **tx).await? {
+            return Err(row_error(i, "invalid_type"));
+        }
+
+        if area.inserted {
+            summary.created += 1;
+        } else {
+            summary.updated += 1;
+        }
+    }
+
+    summary.assigned_addresses = assign_address_areas(&mut **tx).await?;
+
+    Ok(summary)
+}
+
+/// Every child of the area has to stay more specific than it, which also keeps the tree free of cycles
+pub async fn children_allow_type(
+    area_id: &Uuid,
+    area_type: AreaType,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, sqlx::Error> {
+    let invalid = sqlx::query_scalar!(
+        r#"
+            select exists (
+                select 1 from address_area where parent_id = $1 and type <= $2
+            ) as "exists!"
+        "#,
+        area_id,
+        area_type as AreaType
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(!invalid)
+}
+
+/// Puts the addresses that have a location but no area in the most specific area covering them,
+/// returns how many were assigned.
+///
+/// New addresses are assigned on insert, this is for the ones added before the boundary.
+pub async fn assign_address_areas(executor: impl PgExecutor<'_>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            update address
+            set area_id = find_address_area(geom)
+            where geom is not null
+                and area_id is null
+                and find_address_area(geom) is not null
+        "#
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "business_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BusinessRole {
+    Owner,
+    Manager,
+}
+
+/// Role of the user in the business, `None` when not a member
+#[tracing::instrument(name = "Find business role", skip(executor))]
+pub async fn find_business_role(
+    business_id: &Uuid,
+    user_id: &Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<BusinessRole>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            select role as "role: BusinessRole"
+            from business_member
+            where business_id = $1 and user_id = $2
+        "#,
+        business_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
@@ -1,21 +1,25 @@
-use crate::app::email::template::{
-    EmailTemplates, EmailVerifyData, PasswordChangedData, PasswordResetData,
-};
-use aws_config::SdkConfig;
-use aws_sdk_sesv2::{
-    types::{Destination, EmailContent, Template},
-    Client,
+use std::sync::Arc;
+
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::app::{
+    email::{
+        queue::queue_email,
+        template::{
+            EmailContent, EmailTemplates, EmailVerifyData, PasswordChangedData, PasswordResetData,
+            PrimaryEmailChangeData, SocialLinkConfirmData,
+        },
+        transport::{EmailMessage, EmailTransport},
+    },
+    utils::types::Locale,
 };
 
 #[derive(Clone)]
 pub struct EmailClient {
-    ses_client: Client,
+    transport: Arc<dyn EmailTransport>,
     verified_email: String,
     frontend_url: String,
-
-    /// Temp solution
-    /// Should probably change later
-    should_mock: bool,
 }
 
 impl EmailClient {
@@ -23,18 +27,14 @@ impl EmailClient {
     ///
     /// It should only be called once, and shared
     pub fn new(
-        sdk_config: &SdkConfig,
+        transport: Arc<dyn EmailTransport>,
         verified_email: &str,
         frontend_url: &str,
-        should_mock: bool,
     ) -> Self {
-        let ses_client = Client::new(sdk_config);
-
         EmailClient {
-            ses_client,
+            transport,
             verified_email: verified_email.to_string(),
             frontend_url: frontend_url.to_string(),
-            should_mock,
         }
     }
 
@@ -52,16 +52,7 @@ impl EmailClient {
             expire_in_hours,
         };
 
-        let email_content = EmailContent::builder()
-            .template(
-                Template::builder()
-                    .template_name(EmailTemplates::EmailVerify)
-                    .template_data(serde_json::to_string(&email_data).unwrap())
-                    .build(),
-            )
-            .build();
-
-        Ok(email_content)
+        EmailContent::new(EmailTemplates::EmailVerify, &email_data)
     }
 
     #[tracing::instrument(name = "Building reset password content", skip_all)]
@@ -78,16 +69,7 @@ impl EmailClient {
             expire_in_hours,
         };
 
-        let email_content = EmailContent::builder()
-            .template(
-                Template::builder()
-                    .template_name(EmailTemplates::PasswordReset)
-                    .template_data(serde_json::to_string(&email_data).unwrap())
-                    .build(),
-            )
-            .build();
-
-        Ok(email_content)
+        EmailContent::new(EmailTemplates::PasswordReset, &email_data)
     }
 
     #[tracing::instrument(name = "Building password changed content", skip_all)]
@@ -96,41 +78,89 @@ impl EmailClient {
             email: email.to_string(),
         };
 
-        let email_content = EmailContent::builder()
-            .template(
-                Template::builder()
-                    .template_name(EmailTemplates::PasswordChanged)
-                    .template_data(serde_json::to_string(&email_data).unwrap())
-                    .build(),
-            )
-            .build();
+        EmailContent::new(EmailTemplates::PasswordChanged, &email_data)
+    }
+
+    #[tracing::instrument(name = "Building social link confirmation content", skip_all)]
+    pub async fn build_social_link_confirmation(
+        &self,
+        code: &str,
+        provider: &str,
+        expire_in_hours: i64,
+    ) -> anyhow::Result<EmailContent> {
+        let email_data = SocialLinkConfirmData {
+            provider: provider.to_string(),
+            code: code.to_string(),
+            expire_in_hours,
+        };
 
-        Ok(email_content)
+        EmailContent::new(EmailTemplates::SocialLinkConfirm, &email_data)
     }
 
-    #[tracing::instrument(name = "Sending email", skip_all, fields(email = ?email))]
-    pub async fn send_email(&self, email: &str, email_content: EmailContent) -> anyhow::Result<()> {
-        if self.should_mock {
-            return Ok(());
-        }
+    #[tracing::instrument(name = "Building primary email change content", skip_all)]
+    pub async fn build_primary_email_change(
+        &self,
+        new_email: &str,
+        cancel_token: &str,
+        effective_in_hours: i64,
+    ) -> anyhow::Result<EmailContent> {
+        let cancel_url = format!(
+            "{}/account/email-change/cancel?token={}",
+            self.frontend_url, cancel_token
+        );
+
+        let email_data = PrimaryEmailChangeData {
+            new_email: new_email.to_string(),
+            cancel_link: cancel_url,
+            effective_in_hours,
+        };
 
-        match self
-            .ses_client
-            .send_email()
-            .from_email_address(&self.verified_email)
-            .destination(Destination::builder().to_addresses(email).build())
-            .content(email_content)
-            .send()
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                return Err(anyhow::anyhow!(
-                    "Error sending newsletter to {}: {}",
-                    email,
-                    e
-                ))
-            }
-        }
+        EmailContent::new(EmailTemplates::PrimaryEmailChange, &email_data)
     }
+
+    /// Queues the email to the outbox, see `queue::queue_email`
+    #[tracing::instrument(name = "Queue email", skip_all, fields(email = ?email))]
+    pub async fn queue_email(
+        &self,
+        email: &str,
+        locale: Locale,
+        email_content: EmailContent,
+        executor: impl PgExecutor<'_>,
+    ) -> anyhow::Result<()> {
+        let message = EmailMessage {
+            from: self.verified_email.clone(),
+            to: email.to_string(),
+            template: email_content.template,
+            locale,
+            data: email_content.data,
+        };
+
+        queue_email(&message, executor).await?;
+
+        Ok(())
+    }
+
+    /// Sends right away with the transport, used by the outbox worker
+    #[tracing::instrument(name = "Delivering email", skip_all, fields(email = ?message.to))]
+    pub async fn deliver(&self, message: &EmailMessage) -> anyhow::Result<()> {
+        self.transport.send(message).await
+    }
+}
+
+/// Preferred locale of the user, emails to them are sent in it
+#[tracing::instrument(name = "Get user locale", skip_all)]
+pub async fn get_user_locale(
+    user_id: &Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<Locale, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            select locale as "locale: Locale"
+            from "user"
+            where user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(executor)
+    .await
 }
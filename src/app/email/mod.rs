@@ -1,2 +1,6 @@
 pub mod client;
+pub mod queue;
+pub mod sns;
+pub mod suppression;
 pub mod template;
+pub mod transport;
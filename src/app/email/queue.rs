@@ -0,0 +1,267 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{app::utils::types::Locale, config::EmailOutboxConfig};
+
+use super::{
+    client::EmailClient, suppression::clear_expired_suppressions, template::EmailTemplates,
+    transport::EmailMessage,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "email_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EmailStatus {
+    Queued,
+    /// Claimed by a worker, queued again when it didn't finish by `next_attempt_at`
+    Sending,
+    Sent,
+    /// Gave up after `max_attempts`, can be queued again by an admin
+    Failed,
+    /// Not sent, the recipient bounced or complained before
+    Suppressed,
+}
+
+/// Writes the email to the outbox, delivered later by `EmailWorker`.
+///
+/// Pass the transaction of the change that triggers the email,
+/// so the email is only sent when it's committed.
+#[tracing::instrument(name = "Queue email", skip_all, fields(email = ?message.to, template = %message.template))]
+pub async fn queue_email(
+    message: &EmailMessage,
+    executor: impl PgExecutor<'_>,
+) -> anyhow::Result<Uuid> {
+    let id = sqlx::query_scalar!(
+        r#"
+            insert into email_outbox (sender, recipient, template, locale, data)
+            values ($1, $2, $3, $4, $5)
+            returning email_outbox_id
+        "#,
+        message.from,
+        message.to,
+        message.template.to_string(),
+        message.locale as Locale,
+        message.data
+    )
+    .fetch_one(executor)
+    .await
+    .context("failed to queue email")?;
+
+    Ok(id)
+}
+
+/// Delivers the queued emails, retrying failures with exponential backoff.
+#[derive(Clone)]
+pub struct EmailWorker {
+    db_pool: Arc<PgPool>,
+    email_client: Arc<EmailClient>,
+    config: EmailOutboxConfig,
+}
+
+impl EmailWorker {
+    pub fn new(
+        db_pool: Arc<PgPool>,
+        email_client: Arc<EmailClient>,
+        config: EmailOutboxConfig,
+    ) -> Self {
+        Self {
+            db_pool,
+            email_client,
+            config,
+        }
+    }
+
+    /// Polls the outbox until the task is dropped, old emails and expired
+    /// suppressions are purged hourly
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.poll_interval_secs,
+        ));
+        let mut purge_interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = purge_interval.tick() => {
+                    if let Err(e) = self.purge_expired().await {
+                        tracing::error!("failed to purge email outbox: {:?}", e);
+                    }
+                    if let Err(e) = clear_expired_suppressions(&*self.db_pool).await {
+                        tracing::error!("failed to clear expired email suppressions: {:?}", e);
+                    }
+                    continue;
+                }
+            }
+
+            // Keep going while full batches come back
+            loop {
+                match self.process_batch().await {
+                    Ok(count) if count as i64 >= self.config.batch_size => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("failed to process email outbox: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends the due emails, returns how many were attempted.
+    ///
+    /// The rows are claimed with `skip locked` in a statement of their own, so several
+    /// instances can run at once, and each email is marked sent or failed after delivery.
+    /// An email stays claimed for `lease_secs`, nothing is held while sending.
+    ///
+    /// The attempt is counted when claiming, so an email whose worker never finishes
+    /// still runs out of attempts. The data holds tokens and codes, it's cleared once
+    /// the email is sent. Failed emails keep it, so an admin can queue them again.
+    #[tracing::instrument(name = "Process email outbox", skip_all)]
+    pub async fn process_batch(&self) -> anyhow::Result<usize> {
+        let rows = sqlx::query!(
+            r#"
+                with due as (
+                    select email_outbox_id
+                    from email_outbox
+                    where status in ('queued', 'sending') and next_attempt_at <= now()
+                    order by next_attempt_at
+                    limit $1
+                    for update skip locked
+                )
+                update email_outbox o
+                set status = 'sending',
+                    attempts = o.attempts + 1,
+                    next_attempt_at = now() + make_interval(secs => $2)
+                from due
+                where o.email_outbox_id = due.email_outbox_id
+                returning o.email_outbox_id, o.sender, o.recipient, o.template,
+                    o.locale as "locale: Locale", o.data as "data!", o.attempts,
+                    exists(
+                        select 1
+                        from email_suppression s
+                        where s.email = o.recipient collate "case_insensitive"
+                            and (s.expires_at is null or s.expires_at > now())
+                    ) as "suppressed!"
+            "#,
+            self.config.batch_size,
+            self.config.lease_secs as f64
+        )
+        .fetch_all(&*self.db_pool)
+        .await?;
+
+        let count = rows.len();
+
+        for row in rows {
+            if row.suppressed {
+                sqlx::query!(
+                    r#"
+                        update email_outbox
+                        set status = 'suppressed',
+                            data = null
+                        where email_outbox_id = $1
+                    "#,
+                    row.email_outbox_id
+                )
+                .execute(&*self.db_pool)
+                .await?;
+
+                continue;
+            }
+
+            let attempts = row.attempts;
+
+            // The worker of the last attempt didn't finish before its lease ran out
+            let result = if attempts > self.config.max_attempts {
+                Err(anyhow::anyhow!("lease ran out on the last attempt"))
+            } else {
+                match row.template.parse::<EmailTemplates>() {
+                    Ok(template) => {
+                        let message = EmailMessage {
+                            from: row.sender,
+                            to: row.recipient,
+                            template,
+                            locale: row.locale,
+                            data: row.data,
+                        };
+
+                        self.email_client.deliver(&message).await
+                    }
+                    Err(e) => Err(e),
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    sqlx::query!(
+                        r#"
+                            update email_outbox
+                            set status = 'sent',
+                                data = null,
+                                last_error = null,
+                                sent_at = now()
+                            where email_outbox_id = $1
+                        "#,
+                        row.email_outbox_id
+                    )
+                    .execute(&*self.db_pool)
+                    .await?;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "email {} failed on attempt {}: {:?}",
+                        row.email_outbox_id,
+                        attempts,
+                        e
+                    );
+
+                    let status = if attempts >= self.config.max_attempts {
+                        EmailStatus::Failed
+                    } else {
+                        EmailStatus::Queued
+                    };
+
+                    sqlx::query!(
+                        r#"
+                            update email_outbox
+                            set status = $1,
+                                last_error = $2,
+                                next_attempt_at = now() + make_interval(secs => $3)
+                            where email_outbox_id = $4
+                        "#,
+                        status as EmailStatus,
+                        format!("{:#}", e),
+                        self.config.backoff(attempts).as_secs_f64(),
+                        row.email_outbox_id
+                    )
+                    .execute(&*self.db_pool)
+                    .await?;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Deletes the sent and suppressed emails older than `retention_secs`,
+    /// returns how many were deleted
+    #[tracing::instrument(name = "Purge email outbox", skip_all)]
+    pub async fn purge_expired(&self) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            r#"
+                delete from email_outbox
+                where status in ('sent', 'suppressed')
+                    and created_at < now() - make_interval(secs => $1)
+            "#,
+            self.config.retention_secs as f64
+        )
+        .execute(&*self.db_pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
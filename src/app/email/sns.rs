@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::future::BoxFuture;
+use regex::Regex;
+use rsa::{
+    pkcs1v15::{Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+    signature::Verifier,
+    RsaPublicKey,
+};
+use serde::Deserialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::RwLock;
+use url::Url;
+use x509_cert::{
+    der::{DecodePem, Encode},
+    Certificate,
+};
+
+/// Message posted by SNS to an HTTP subscription.
+///
+/// https://docs.aws.amazon.com/sns/latest/dg/sns-message-and-json-formats.html
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SnsMessage {
+    #[serde(rename = "Type")]
+    pub kind: SnsMessageType,
+    pub message_id: String,
+    pub topic_arn: String,
+    pub subject: Option<String>,
+    pub message: String,
+    pub timestamp: String,
+    pub signature_version: String,
+    pub signature: String,
+    #[serde(rename = "SigningCertURL")]
+    pub signing_cert_url: String,
+    #[serde(rename = "SubscribeURL")]
+    pub subscribe_url: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub enum SnsMessageType {
+    Notification,
+    SubscriptionConfirmation,
+    UnsubscribeConfirmation,
+}
+
+impl SnsMessage {
+    /// The string SNS signs, fields in byte order, each followed by its value
+    pub fn string_to_sign(&self) -> anyhow::Result<String> {
+        let mut fields: Vec<(&str, &str)> = vec![("Message", &self.message)];
+        fields.push(("MessageId", &self.message_id));
+
+        match self.kind {
+            SnsMessageType::Notification => {
+                if let Some(subject) = &self.subject {
+                    fields.push(("Subject", subject));
+                }
+                fields.push(("Timestamp", &self.timestamp));
+                fields.push(("TopicArn", &self.topic_arn));
+                fields.push(("Type", "Notification"));
+            }
+            SnsMessageType::SubscriptionConfirmation | SnsMessageType::UnsubscribeConfirmation => {
+                let subscribe_url = self
+                    .subscribe_url
+                    .as_deref()
+                    .context("missing SubscribeURL")?;
+                let token = self.token.as_deref().context("missing Token")?;
+
+                fields.push(("SubscribeURL", subscribe_url));
+                fields.push(("Timestamp", &self.timestamp));
+                fields.push(("Token", token));
+                fields.push(("TopicArn", &self.topic_arn));
+                fields.push((
+                    "Type",
+                    if self.kind == SnsMessageType::SubscriptionConfirmation {
+                        "SubscriptionConfirmation"
+                    } else {
+                        "UnsubscribeConfirmation"
+                    },
+                ));
+            }
+        }
+
+        Ok(fields
+            .into_iter()
+            .map(|(key, value)| format!("{}\n{}\n", key, value))
+            .collect())
+    }
+
+    /// Checks the signature with the public key of the signer.
+    ///
+    /// `SignatureVersion` 1 is RSA SHA1, 2 is RSA SHA256.
+    pub fn verify_with_key(&self, key: &RsaPublicKey) -> anyhow::Result<()> {
+        let signature = STANDARD
+            .decode(&self.signature)
+            .context("signature is not base64")?;
+        let signature = Signature::try_from(signature.as_slice()).context("invalid signature")?;
+        let string_to_sign = self.string_to_sign()?;
+
+        match self.signature_version.as_str() {
+            "1" => VerifyingKey::<sha1::Sha1>::new(key.clone())
+                .verify(string_to_sign.as_bytes(), &signature)
+                .context("signature does not match")?,
+            "2" => VerifyingKey::<sha2::Sha256>::new(key.clone())
+                .verify(string_to_sign.as_bytes(), &signature)
+                .context("signature does not match")?,
+            version => anyhow::bail!("unsupported signature version {}", version),
+        }
+
+        Ok(())
+    }
+
+    /// Fails when SNS sent the message more than `max_age` ago, or that far in the future.
+    ///
+    /// The signature covers the timestamp, a captured message can't be replayed later on.
+    pub fn check_timestamp(&self, max_age: time::Duration) -> anyhow::Result<()> {
+        let sent_at =
+            OffsetDateTime::parse(&self.timestamp, &Rfc3339).context("invalid timestamp")?;
+        let age = OffsetDateTime::now_utc() - sent_at;
+
+        if age.abs() > max_age {
+            anyhow::bail!("message was sent at {}", self.timestamp);
+        }
+
+        Ok(())
+    }
+}
+
+/// Verifies that a message was sent by SNS.
+///
+/// Picked at startup from `EmailWebhookConfig::verifier`.
+pub trait SnsVerifier: Send + Sync {
+    fn verify<'a>(&'a self, message: &'a SnsMessage) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// Verifies with the certificate AWS publishes at `SigningCertURL`.
+///
+/// Certificates are cached by url, SNS rotates them rarely.
+pub struct AwsSnsVerifier {
+    http_client: reqwest::Client,
+    cert_host: Regex,
+    keys: RwLock<HashMap<String, RsaPublicKey>>,
+}
+
+impl AwsSnsVerifier {
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self {
+            http_client,
+            cert_host: Regex::new(r"^sns\.[a-z0-9-]+\.amazonaws\.com(\.cn)?$").unwrap(),
+            keys: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Only certificates served by SNS over https can be trusted
+    fn check_cert_url(&self, cert_url: &str) -> anyhow::Result<()> {
+        let url = Url::parse(cert_url).context("invalid SigningCertURL")?;
+
+        let trusted = url.scheme() == "https"
+            && url
+                .host_str()
+                .is_some_and(|host| self.cert_host.is_match(host))
+            && url.path().ends_with(".pem");
+
+        if !trusted {
+            anyhow::bail!("untrusted SigningCertURL {}", cert_url);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get SNS signing key", skip_all, fields(cert_url = ?cert_url))]
+    async fn get_key(&self, cert_url: &str) -> anyhow::Result<RsaPublicKey> {
+        if let Some(key) = self.keys.read().await.get(cert_url) {
+            return Ok(key.clone());
+        }
+
+        self.check_cert_url(cert_url)?;
+
+        let pem = self
+            .http_client
+            .get(cert_url)
+            .send()
+            .await
+            .context("failed to fetch signing certificate")?
+            .error_for_status()
+            .context("failed to fetch signing certificate")?
+            .text()
+            .await
+            .context("failed to read signing certificate")?;
+
+        let certificate = Certificate::from_pem(pem.as_bytes()).context("invalid certificate")?;
+        let spki = certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .context("invalid certificate public key")?;
+        let key = RsaPublicKey::from_public_key_der(&spki).context("certificate is not RSA")?;
+
+        self.keys
+            .write()
+            .await
+            .insert(cert_url.to_string(), key.clone());
+
+        Ok(key)
+    }
+}
+
+impl SnsVerifier for AwsSnsVerifier {
+    fn verify<'a>(&'a self, message: &'a SnsMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let key = self.get_key(&message.signing_cert_url).await?;
+            message.verify_with_key(&key)
+        })
+    }
+}
+
+/// Verifies with a fixed public key, ignoring `SigningCertURL`.
+pub struct LocalKeyVerifier {
+    key: RsaPublicKey,
+}
+
+impl LocalKeyVerifier {
+    pub fn from_pem(pem: &str) -> anyhow::Result<Self> {
+        let key = RsaPublicKey::from_public_key_pem(pem).context("invalid SNS public key")?;
+
+        Ok(Self { key })
+    }
+}
+
+impl SnsVerifier for LocalKeyVerifier {
+    fn verify<'a>(&'a self, message: &'a SnsMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move { message.verify_with_key(&self.key) })
+    }
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use time::{Duration, OffsetDateTime};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "suppression_reason", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SuppressionReason {
+    /// Permanent bounce, the address doesn't exist or rejects our mail
+    Bounce,
+    /// The recipient marked our mail as spam
+    Complaint,
+    /// Soft bounce, e.g. a full mailbox, suppressed until it expires
+    Transient,
+}
+
+/// SES notification carried in `SnsMessage::message`.
+///
+/// Both the notification and the event publishing formats are accepted.
+/// https://docs.aws.amazon.com/ses/latest/dg/notification-contents.html
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SesNotification {
+    #[serde(alias = "eventType")]
+    pub notification_type: String,
+    pub bounce: Option<SesBounce>,
+    pub complaint: Option<SesComplaint>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SesBounce {
+    /// `Permanent`, `Transient` or `Undetermined`
+    pub bounce_type: String,
+    pub bounce_sub_type: Option<String>,
+    pub bounced_recipients: Vec<SesRecipient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SesComplaint {
+    pub complaint_feedback_type: Option<String>,
+    pub complained_recipients: Vec<SesRecipient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SesRecipient {
+    pub email_address: String,
+}
+
+/// Address to stop sending to
+#[derive(Debug)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: SuppressionReason,
+    pub detail: Option<String>,
+    /// Permanent when `None`
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+impl SesNotification {
+    /// Addresses to suppress, soft bounces only for `soft_bounce_ttl`
+    pub fn suppressions(self, soft_bounce_ttl: Duration) -> Vec<Suppression> {
+        match (self.notification_type.as_str(), self.bounce, self.complaint) {
+            ("Bounce", Some(bounce), _) => {
+                let (reason, expires_at) = match bounce.bounce_type.as_str() {
+                    "Permanent" => (SuppressionReason::Bounce, None),
+                    _ => (
+                        SuppressionReason::Transient,
+                        Some(OffsetDateTime::now_utc() + soft_bounce_ttl),
+                    ),
+                };
+
+                bounce
+                    .bounced_recipients
+                    .into_iter()
+                    .map(|recipient| Suppression {
+                        email: recipient.email_address,
+                        reason,
+                        detail: bounce.bounce_sub_type.clone(),
+                        expires_at,
+                    })
+                    .collect()
+            }
+            ("Complaint", _, Some(complaint)) => complaint
+                .complained_recipients
+                .into_iter()
+                .map(|recipient| Suppression {
+                    email: recipient.email_address,
+                    reason: SuppressionReason::Complaint,
+                    detail: complaint.complaint_feedback_type.clone(),
+                    expires_at: None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Stores the suppression and flags the matching `email` row, so the owner
+/// is asked to replace it.
+///
+/// A soft bounce never replaces a permanent suppression, only extends another soft one.
+#[tracing::instrument(name = "Suppress email", skip_all, fields(email = ?suppression.email, reason = ?suppression.reason))]
+pub async fn suppress_email(
+    suppression: &Suppression,
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            insert into email_suppression (email, reason, detail, expires_at)
+            values ($1, $2, $3, $4)
+            on conflict (email) do update
+            set reason = excluded.reason,
+                detail = excluded.detail,
+                expires_at = excluded.expires_at
+            where email_suppression.expires_at is not null
+                or excluded.expires_at is null
+        "#,
+        suppression.email,
+        suppression.reason as SuppressionReason,
+        suppression.detail,
+        suppression.expires_at
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            update email
+            set delivery_issue = s.reason
+            from email_suppression s
+            where s.email = $1 and email.email = s.email
+        "#,
+        suppression.email
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes the suppression of `email` and clears its delivery issue,
+/// returns whether there was one
+#[tracing::instrument(name = "Lift email suppression", skip_all, fields(email = ?email))]
+pub async fn lift_suppression(
+    email: &str,
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            delete
+            from email_suppression
+            where email = $1
+        "#,
+        email
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            update email
+            set delivery_issue = null
+            where email = $1
+        "#,
+        email
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Removes the soft bounce suppressions past `expires_at` and clears their
+/// delivery issues, returns how many were removed
+#[tracing::instrument(name = "Clear expired email suppressions", skip_all)]
+pub async fn clear_expired_suppressions(db: impl PgExecutor<'_>) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            with expired as (
+                delete
+                from email_suppression
+                where expires_at <= now()
+                returning email
+            ),
+            cleared as (
+                update email
+                set delivery_issue = null
+                from expired
+                where email.email = expired.email
+            )
+            select count(*) as "count!"
+            from expired
+        "#
+    )
+    .fetch_one(db)
+    .await
+}
@@ -1,10 +1,69 @@
-use serde::Serialize;
+use std::{collections::HashSet, path::Path};
 
-#[derive(Debug)]
+use anyhow::Context;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::app::utils::types::Locale;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EmailTemplates {
     EmailVerify,
     PasswordReset,
     PasswordChanged,
+    SocialLinkConfirm,
+    PrimaryEmailChange,
+}
+
+impl EmailTemplates {
+    pub const ALL: [EmailTemplates; 5] = [
+        EmailTemplates::EmailVerify,
+        EmailTemplates::PasswordReset,
+        EmailTemplates::PasswordChanged,
+        EmailTemplates::SocialLinkConfirm,
+        EmailTemplates::PrimaryEmailChange,
+    ];
+
+    /// `TemplateName` of the locale variant, english keeps the original SES name
+    pub fn template_name(&self, locale: Locale) -> String {
+        match locale {
+            Locale::En => self.to_string(),
+            _ => format!("{}_{}", self, locale),
+        }
+    }
+
+    /// Placeholder data with every field of the template's data struct
+    pub fn sample_data(&self) -> Value {
+        let data = match self {
+            EmailTemplates::EmailVerify => serde_json::to_value(EmailVerifyData {
+                verification_link: "https://example.com/account/verify?token=A1B2C3D4".into(),
+                code: "A1B2C3D4".into(),
+                expire_in_hours: 24,
+            }),
+            EmailTemplates::PasswordReset => serde_json::to_value(PasswordResetData {
+                reset_link: "https://example.com/reset-password?token=A1B2C3D4".into(),
+                code: "A1B2C3D4".into(),
+                expire_in_hours: 1,
+            }),
+            EmailTemplates::PasswordChanged => serde_json::to_value(PasswordChangedData {
+                email: "user@example.com".into(),
+            }),
+            EmailTemplates::SocialLinkConfirm => serde_json::to_value(SocialLinkConfirmData {
+                provider: "Github".into(),
+                code: "A1B2C3D4".into(),
+                expire_in_hours: 1,
+            }),
+            EmailTemplates::PrimaryEmailChange => serde_json::to_value(PrimaryEmailChangeData {
+                new_email: "new@example.com".into(),
+                cancel_link: "https://example.com/account/email-change/cancel?token=A1B2C3D4"
+                    .into(),
+                effective_in_hours: 24,
+            }),
+        };
+
+        data.expect("email data is serializable")
+    }
 }
 
 impl std::fmt::Display for EmailTemplates {
@@ -13,12 +72,53 @@ impl std::fmt::Display for EmailTemplates {
     }
 }
 
+impl std::str::FromStr for EmailTemplates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EmailTemplates::ALL
+            .into_iter()
+            .find(|template| template.to_string() == s)
+            .ok_or_else(|| anyhow::anyhow!("unknown email template {}", s))
+    }
+}
+
 impl From<EmailTemplates> for String {
     fn from(value: EmailTemplates) -> Self {
         value.to_string()
     }
 }
 
+/// Template and the data to fill it with, rendered by the transport
+#[derive(Debug, Clone)]
+pub struct EmailContent {
+    pub template: EmailTemplates,
+    pub data: Value,
+}
+
+impl EmailContent {
+    pub fn new(template: EmailTemplates, data: &impl Serialize) -> anyhow::Result<Self> {
+        Ok(Self {
+            template,
+            data: serde_json::to_value(data).context("failed to serialize email data")?,
+        })
+    }
+}
+
+/// Fields of the email data holding a token or code
+const SECRET_FIELDS: [&str; 4] = ["code", "verification_link", "reset_link", "cancel_link"];
+
+/// Hides the tokens and codes, anyone who sees them can use them
+pub fn redact_data(data: &mut Value) {
+    if let Value::Object(fields) = data {
+        for field in SECRET_FIELDS {
+            if let Some(value) = fields.get_mut(field) {
+                *value = Value::String("[redacted]".to_string());
+            }
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct EmailVerifyData {
     pub verification_link: String,
@@ -37,3 +137,138 @@ pub struct PasswordResetData {
 pub struct PasswordChangedData {
     pub email: String,
 }
+
+#[derive(Serialize)]
+pub struct SocialLinkConfirmData {
+    pub provider: String,
+    pub code: String,
+    pub expire_in_hours: i64,
+}
+
+#[derive(Serialize)]
+pub struct PrimaryEmailChangeData {
+    pub new_email: String,
+    pub cancel_link: String,
+    pub effective_in_hours: i64,
+}
+
+// The SES template format, also used for rendering locally.
+// https://docs.aws.amazon.com/ses/latest/dg/send-personalized-email-api.html
+#[derive(Debug, Deserialize)]
+struct TemplateFile {
+    #[serde(rename = "Template")]
+    template: TemplateParts,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TemplateParts {
+    template_name: String,
+    subject_part: String,
+    html_part: String,
+    text_part: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Renders the templates locally, for every transport and previews.
+///
+/// Every template has a variant per locale in `{dir}/{locale}/*.json`.
+/// It should only be created once, and shared
+pub struct TemplateRenderer {
+    html: Handlebars<'static>,
+    plain: Handlebars<'static>,
+}
+
+impl TemplateRenderer {
+    /// Loads every `*.json` template in the locale directories of `dir`
+    pub fn from_dir(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut html = Handlebars::new();
+        html.set_strict_mode(true);
+
+        let mut plain = Handlebars::new();
+        plain.set_strict_mode(true);
+        plain.register_escape_fn(handlebars::no_escape);
+
+        for locale in Locale::ALL {
+            let locale_dir = dir.as_ref().join(locale.to_string());
+            let known: HashSet<String> = EmailTemplates::ALL
+                .iter()
+                .map(|template| template.template_name(locale))
+                .collect();
+
+            let entries = std::fs::read_dir(&locale_dir)
+                .with_context(|| format!("failed to read templates dir {:?}", locale_dir))?;
+
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+
+                let raw = std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read template {:?}", path))?;
+                let file: TemplateFile = serde_json::from_str(&raw)
+                    .with_context(|| format!("failed to parse template {:?}", path))?;
+
+                let name = file.template.template_name;
+                if !known.contains(&name) {
+                    anyhow::bail!("unknown template name {} in {:?}", name, path);
+                }
+
+                plain.register_template_string(
+                    &format!("{}.subject", name),
+                    file.template.subject_part,
+                )?;
+                plain
+                    .register_template_string(&format!("{}.text", name), file.template.text_part)?;
+                html.register_template_string(&format!("{}.html", name), file.template.html_part)?;
+            }
+        }
+
+        Ok(Self { html, plain })
+    }
+
+    /// Renders every template and locale with its sample data.
+    ///
+    /// Strict mode makes a missing variant, or a variable the data struct
+    /// doesn't have, fail here instead of when sending.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for template in EmailTemplates::ALL {
+            for locale in Locale::ALL {
+                self.render(&template, locale, &template.sample_data())
+                    .with_context(|| format!("invalid {} template for {}", template, locale))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn render(
+        &self,
+        template: &EmailTemplates,
+        locale: Locale,
+        data: &Value,
+    ) -> anyhow::Result<RenderedEmail> {
+        let name = template.template_name(locale);
+        let render_plain = |part: &str| {
+            self.plain
+                .render(&format!("{}.{}", name, part), data)
+                .with_context(|| format!("failed to render {} of {}", part, name))
+        };
+
+        Ok(RenderedEmail {
+            subject: render_plain("subject")?,
+            text: render_plain("text")?,
+            html: self
+                .html
+                .render(&format!("{}.html", name), data)
+                .with_context(|| format!("failed to render html of {}", name))?,
+        })
+    }
+}
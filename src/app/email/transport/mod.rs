@@ -0,0 +1,28 @@
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::app::utils::types::Locale;
+
+use super::template::EmailTemplates;
+
+pub mod outbox;
+pub mod ses;
+pub mod smtp;
+
+/// A single outgoing email, before rendering.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailMessage {
+    pub from: String,
+    pub to: String,
+    pub template: EmailTemplates,
+    pub locale: Locale,
+    pub data: Value,
+}
+
+/// Delivers emails built by `EmailClient`.
+///
+/// Picked at startup from `EmailConfig::transport`.
+pub trait EmailTransport: Send + Sync {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, anyhow::Result<()>>;
+}
@@ -0,0 +1,98 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::app::email::template::{RenderedEmail, TemplateRenderer};
+
+use super::{EmailMessage, EmailTransport};
+
+#[derive(Serialize)]
+struct OutboxFile<'a> {
+    #[serde(flatten)]
+    message: &'a EmailMessage,
+    rendered: RenderedEmail,
+}
+
+/// Writes every email to `dir` as a JSON file, along with the rendered parts.
+pub struct FileOutbox {
+    dir: PathBuf,
+    renderer: Arc<TemplateRenderer>,
+}
+
+impl FileOutbox {
+    pub fn new(dir: impl Into<PathBuf>, renderer: Arc<TemplateRenderer>) -> Self {
+        Self {
+            dir: dir.into(),
+            renderer,
+        }
+    }
+}
+
+impl EmailTransport for FileOutbox {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let rendered =
+                self.renderer
+                    .render(&message.template, message.locale, &message.data)?;
+            let content = serde_json::to_vec_pretty(&OutboxFile { message, rendered })?;
+
+            // Sortable by time of sending
+            let path = self.dir.join(format!(
+                "{}-{}-{}.json",
+                OffsetDateTime::now_utc().unix_timestamp_nanos(),
+                message.template,
+                Uuid::new_v4()
+            ));
+
+            tokio::fs::create_dir_all(&self.dir)
+                .await
+                .context("failed to create outbox dir")?;
+            tokio::fs::write(&path, content)
+                .await
+                .with_context(|| format!("failed to write email to {:?}", path))?;
+
+            tracing::info!("Email to {} written to {:?}", message.to, path);
+
+            Ok(())
+        })
+    }
+}
+
+/// Keeps sent emails in memory so tests can assert on them.
+#[derive(Default)]
+pub struct MemoryOutbox {
+    messages: Mutex<Vec<EmailMessage>>,
+}
+
+impl MemoryOutbox {
+    /// Every email sent so far, oldest first
+    pub async fn messages(&self) -> Vec<EmailMessage> {
+        self.messages.lock().await.clone()
+    }
+
+    /// Most recent email sent to `to`
+    pub async fn last_to(&self, to: &str) -> Option<EmailMessage> {
+        self.messages
+            .lock()
+            .await
+            .iter()
+            .rev()
+            .find(|message| message.to == to)
+            .cloned()
+    }
+}
+
+impl EmailTransport for MemoryOutbox {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.messages.lock().await.push(message.clone());
+
+            Ok(())
+        })
+    }
+}
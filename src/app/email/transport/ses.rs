@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use aws_config::SdkConfig;
+use aws_sdk_sesv2::{
+    types::{Body, Content, Destination, EmailContent, Message},
+    Client,
+};
+use futures::future::BoxFuture;
+
+use crate::app::email::template::TemplateRenderer;
+
+use super::{EmailMessage, EmailTransport};
+
+/// Sends with AWS SES, templates are rendered locally.
+pub struct SesTransport {
+    client: Client,
+    renderer: Arc<TemplateRenderer>,
+}
+
+impl SesTransport {
+    pub fn new(sdk_config: &SdkConfig, renderer: Arc<TemplateRenderer>) -> Self {
+        Self {
+            client: Client::new(sdk_config),
+            renderer,
+        }
+    }
+}
+
+fn utf8_content(data: String) -> anyhow::Result<Content> {
+    Content::builder()
+        .data(data)
+        .charset("UTF-8")
+        .build()
+        .context("failed to build email content")
+}
+
+impl EmailTransport for SesTransport {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let rendered =
+                self.renderer
+                    .render(&message.template, message.locale, &message.data)?;
+
+            let email_content = EmailContent::builder()
+                .simple(
+                    Message::builder()
+                        .subject(utf8_content(rendered.subject)?)
+                        .body(
+                            Body::builder()
+                                .text(utf8_content(rendered.text)?)
+                                .html(utf8_content(rendered.html)?)
+                                .build(),
+                        )
+                        .build(),
+                )
+                .build();
+
+            self.client
+                .send_email()
+                .from_email_address(&message.from)
+                .destination(Destination::builder().to_addresses(&message.to).build())
+                .content(email_content)
+                .send()
+                .await
+                .with_context(|| format!("failed to send email to {}", message.to))?;
+
+            Ok(())
+        })
+    }
+}
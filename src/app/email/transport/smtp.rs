@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use futures::future::BoxFuture;
+use lettre::{
+    message::{header::ContentType, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use crate::{app::email::template::TemplateRenderer, config::SmtpConfig};
+
+use super::{EmailMessage, EmailTransport};
+
+/// Sends through any SMTP server, templates are rendered locally.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    renderer: Arc<TemplateRenderer>,
+}
+
+impl SmtpTransport {
+    pub fn new(config: &SmtpConfig, renderer: Arc<TemplateRenderer>) -> anyhow::Result<Self> {
+        let builder = if config.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                .context("failed to build smtp transport")?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+        };
+
+        let builder = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().to_string(),
+            )),
+            _ => builder,
+        };
+
+        Ok(Self {
+            mailer: builder.port(config.port).build(),
+            renderer,
+        })
+    }
+}
+
+impl EmailTransport for SmtpTransport {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let rendered =
+                self.renderer
+                    .render(&message.template, message.locale, &message.data)?;
+
+            let email = Message::builder()
+                .from(message.from.parse().context("invalid from address")?)
+                .to(message.to.parse().context("invalid to address")?)
+                .subject(rendered.subject)
+                .multipart(
+                    MultiPart::alternative()
+                        .singlepart(
+                            SinglePart::builder()
+                                .header(ContentType::TEXT_PLAIN)
+                                .body(rendered.text),
+                        )
+                        .singlepart(
+                            SinglePart::builder()
+                                .header(ContentType::TEXT_HTML)
+                                .body(rendered.html),
+                        ),
+                )
+                .context("failed to build email")?;
+
+            self.mailer
+                .send(email)
+                .await
+                .with_context(|| format!("failed to send email to {}", message.to))?;
+
+            Ok(())
+        })
+    }
+}
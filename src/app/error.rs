@@ -2,7 +2,10 @@ use std::{borrow::Cow, collections::HashMap};
 
 use axum::{
     extract::rejection::JsonRejection,
-    http::{header::WWW_AUTHENTICATE, StatusCode},
+    http::{
+        header::{RETRY_AFTER, WWW_AUTHENTICATE},
+        StatusCode,
+    },
     response::{IntoResponse, Response},
     Json,
 };
@@ -34,6 +37,16 @@ pub enum AppError {
         errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
     },
 
+    #[error("social login requires an email to finish signup")]
+    EmailRequired { signup_token: String },
+
+    #[error("social login must be confirmed by the owner of the email")]
+    LinkConfirmationRequired { link_token: String },
+
+    #[error("too many requests, try again later")]
+    #[serde(skip)]
+    TooManyRequests { retry_after: u64 },
+
     #[error("request body does not meet validation requirements")]
     #[serde(skip)]
     ValidationError(#[from] validator::ValidationErrors),
@@ -52,6 +65,18 @@ struct InputErrorResponse {
     errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
 }
 
+#[derive(Serialize)]
+struct EmailRequiredResponse {
+    errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
+    signup_token: String,
+}
+
+#[derive(Serialize)]
+struct LinkConfirmationResponse {
+    errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
+    link_token: String,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         match self {
@@ -65,6 +90,10 @@ impl IntoResponse for AppError {
                 )
                     .into_response();
             }
+            Self::TooManyRequests { retry_after } => {
+                return (self.status_code(), [(RETRY_AFTER, retry_after.to_string())])
+                    .into_response();
+            }
             Self::ValidationError(e) => {
                 let mut error_map: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>> =
                     HashMap::new();
@@ -93,6 +122,28 @@ impl IntoResponse for AppError {
                     .into_response();
             }
 
+            Self::EmailRequired { signup_token } => {
+                return (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(EmailRequiredResponse {
+                        errors: HashMap::from([("email".into(), vec!["missing".into()])]),
+                        signup_token,
+                    }),
+                )
+                    .into_response();
+            }
+
+            Self::LinkConfirmationRequired { link_token } => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(LinkConfirmationResponse {
+                        errors: HashMap::from([("email".into(), vec!["unverified".into()])]),
+                        link_token,
+                    }),
+                )
+                    .into_response();
+            }
+
             Self::Sqlx(ref e) => {
                 if let sqlx::Error::RowNotFound = e {
                     return (StatusCode::NOT_FOUND).into_response();
@@ -140,6 +191,9 @@ impl AppError {
             Self::NotFound => StatusCode::NOT_FOUND,
             Self::AxumJsonRejection(_) => StatusCode::BAD_REQUEST,
             Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::EmailRequired { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::LinkConfirmationRequired { .. } => StatusCode::CONFLICT,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             Self::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
             Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -1,11 +1,34 @@
-use auth::token::TokenManager;
+use auth::{email_change::EmailChangeWorker, token::TokenManager};
 use aws_config::{meta::region::RegionProviderChain, BehaviorVersion, SdkConfig};
 use axum::{middleware::from_fn_with_state, Router};
-use email::client::EmailClient;
+use email::{
+    client::EmailClient,
+    queue::EmailWorker,
+    sns::{AwsSnsVerifier, LocalKeyVerifier, SnsVerifier},
+    template::TemplateRenderer,
+    transport::{
+        outbox::{FileOutbox, MemoryOutbox},
+        ses::SesTransport,
+        smtp::SmtpTransport,
+        EmailTransport,
+    },
+};
 use middleware::{api_key_required, login_required};
+use oauth::oidc::OidcDiscoveryCache;
+use sms::{
+    client::SmsClient,
+    transport::{
+        log::LogSms,
+        outbox::{FileSmsOutbox, MemorySmsOutbox},
+        SmsTransport,
+    },
+};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::sync::Arc;
-use storage::client::S3Storage;
+use storage::{
+    avatar::AvatarMigrator, image::ImageWorker, local::LocalStorage, multipart::MultipartCleaner,
+    orphan::OrphanCleaner, s3::S3Storage, Storage,
+};
 use tokio::net::TcpListener;
 use uuid::Uuid;
 
@@ -14,21 +37,27 @@ use tower_http::trace::TraceLayer;
 use tracing::info_span;
 
 pub mod auth;
+pub mod business;
 pub mod email;
 pub mod error;
 pub mod extrator;
 pub mod middleware;
 pub mod oauth;
 pub mod otp;
+pub mod sms;
 pub mod storage;
 pub mod utils;
 
 use crate::{
-    config::{AppConfig, Stage},
+    config::{
+        AppConfig, EmailTransportConfig, SmsTransportConfig, SnsVerifierConfig, Stage,
+        StorageConfig,
+    },
     routes::{
-        admin,
+        admin, area,
         auth::{self as auth_route},
-        docs, health_check, oauth as oauth_route, upload,
+        business as business_route, dev, docs, health_check, oauth as oauth_route,
+        storage as storage_route, upload, webhooks,
     },
 };
 
@@ -36,6 +65,21 @@ pub struct Application {
     listener: TcpListener,
     pub port: u16,
     app: Router,
+
+    /// Set when `email.transport` is `memory`
+    pub email_outbox: Option<Arc<MemoryOutbox>>,
+    /// Set when `sms.transport` is `memory`
+    pub sms_outbox: Option<Arc<MemorySmsOutbox>>,
+    /// Delivers queued emails, started by `run_gracefully`
+    pub email_worker: EmailWorker,
+    /// Applies primary email changes after the grace period, started by `run_gracefully`
+    pub email_change_worker: EmailChangeWorker,
+    /// Generates the variants of confirmed images, started by `run_gracefully`
+    pub image_worker: ImageWorker,
+    /// Aborts abandoned multipart uploads, started by `run_gracefully`
+    pub multipart_cleaner: MultipartCleaner,
+    pub orphan_cleaner: OrphanCleaner,
+    pub avatar_migrator: AvatarMigrator,
 }
 
 #[derive(Clone)]
@@ -45,7 +89,13 @@ pub struct ApiContext {
     pub redis_client: Arc<redis::Client>,
     pub token_manager: Arc<TokenManager>,
     pub email_client: Arc<EmailClient>,
-    pub storage_client: Arc<S3Storage>,
+    pub email_renderer: Arc<TemplateRenderer>,
+    pub sns_verifier: Arc<dyn SnsVerifier>,
+    pub sms_client: Arc<SmsClient>,
+    pub storage: Arc<dyn Storage>,
+    /// Set when `storage` is `local`, for serving its uploads
+    pub local_storage: Option<Arc<LocalStorage>>,
+    pub oidc_discovery: Arc<OidcDiscoveryCache>,
     pub http_client: reqwest::Client,
 }
 
@@ -63,14 +113,22 @@ impl Application {
         let token_manager = TokenManager::new(&config.hmac);
 
         let aws_config = get_aws_config().await;
+        // Fail early on broken templates instead of when sending
+        let email_renderer = Arc::new(TemplateRenderer::from_dir(&config.email.templates_dir)?);
+        email_renderer.validate()?;
+
+        let (email_transport, email_outbox) =
+            get_email_transport(&config, &aws_config, email_renderer.clone())?;
         let email_client = EmailClient::new(
-            &aws_config,
+            email_transport,
             &config.email.from_mail,
             &config.frontend.url,
-            config.stage == Stage::Dev,
         );
 
-        let storage_client = S3Storage::new(&aws_config, &config.aws.s3, &config.aws.cdn);
+        let (sms_transport, sms_outbox) = get_sms_transport(&config);
+        let sms_client = SmsClient::new(sms_transport);
+
+        let (storage, local_storage) = get_storage(&config, &aws_config, port)?;
 
         // it uses arc internally
         let http_client = reqwest::Client::builder()
@@ -78,13 +136,37 @@ impl Application {
             .build()
             .unwrap();
 
+        let sns_verifier = get_sns_verifier(&config, &http_client)?;
+
+        let db_pool = Arc::new(db_pool);
+        let email_client = Arc::new(email_client);
+        let email_worker = EmailWorker::new(
+            db_pool.clone(),
+            email_client.clone(),
+            config.email.outbox.clone(),
+        );
+        let email_change_worker =
+            EmailChangeWorker::new(db_pool.clone(), config.email.primary_change.clone());
+        let image_worker =
+            ImageWorker::new(db_pool.clone(), storage.clone(), config.images.clone());
+        let multipart_cleaner =
+            MultipartCleaner::new(db_pool.clone(), storage.clone(), config.multipart.clone());
+        let orphan_cleaner =
+            OrphanCleaner::new(db_pool.clone(), storage.clone(), config.orphans.clone());
+        let avatar_migrator = AvatarMigrator::new(db_pool.clone(), storage.clone());
+
         let api_context = ApiContext {
             config: Arc::new(config),
-            db_pool: Arc::new(db_pool),
+            db_pool,
             redis_client: Arc::new(redis_client),
             token_manager: Arc::new(token_manager),
-            email_client: Arc::new(email_client),
-            storage_client: Arc::new(storage_client),
+            email_client,
+            email_renderer,
+            sns_verifier,
+            sms_client: Arc::new(sms_client),
+            storage,
+            local_storage,
+            oidc_discovery: Arc::new(OidcDiscoveryCache::default()),
             http_client,
         };
 
@@ -94,21 +176,50 @@ impl Application {
             port,
             listener,
             app,
+            email_outbox,
+            sms_outbox,
+            email_worker,
+            email_change_worker,
+            image_worker,
+            multipart_cleaner,
+            orphan_cleaner,
+            avatar_migrator,
         })
     }
 
     /// Used in main, run the app
     pub async fn run_gracefully(self, close_rx: tokio::sync::oneshot::Receiver<()>) {
+        let email_worker = tokio::spawn(self.email_worker.run());
+        let email_change_worker = tokio::spawn(self.email_change_worker.run());
+        let image_worker = tokio::spawn(self.image_worker.run());
+        let multipart_cleaner = tokio::spawn(self.multipart_cleaner.run());
+        let orphan_cleaner = tokio::spawn(self.orphan_cleaner.run());
+        let avatar_migrator = tokio::spawn(self.avatar_migrator.run());
+
         axum::serve(self.listener, self.app)
             .with_graceful_shutdown(async move {
                 _ = close_rx.await;
             })
             .await
             .unwrap();
+
+        email_worker.abort();
+        email_change_worker.abort();
+        image_worker.abort();
+        multipart_cleaner.abort();
+        orphan_cleaner.abort();
+        avatar_migrator.abort();
     }
 
     /// Useful for tests
     /// Don't use in main
+    ///
+    /// Emails stay queued until `email_worker.process_batch` is called,
+    /// primary email changes until `email_change_worker.apply_due`
+    /// image variants until `image_worker.process_batch`,
+    /// abandoned multipart uploads until `multipart_cleaner.abort_abandoned`,
+    /// orphaned objects until `orphan_cleaner.clean`
+    /// and DiceBear avatars until `avatar_migrator.migrate_batch`
     pub async fn run_until_stopped(self) {
         axum::serve(self.listener, self.app).await.unwrap();
     }
@@ -125,14 +236,33 @@ fn build_routes(api_context: ApiContext) -> Router {
         .merge(auth_route::api_key_protected())
         .route_layer(from_fn_with_state(api_context.clone(), api_key_required));
 
+    // Uploads to the local storage, signed like S3 presigned requests
+    let local_storage = if api_context.local_storage.is_some() {
+        storage_route::router()
+    } else {
+        Router::new()
+    };
+
+    // Email previews and other tooling, never exposed in prod
+    let dev_only = if api_context.config.stage == Stage::Dev {
+        dev::router()
+    } else {
+        Router::new()
+    };
+
     // Incoming request goes through middleware from bottom to top
     // and outgoing request goes through middleware from top to bottom
 
     Router::new()
         .merge(health_check::router())
+        .merge(dev_only)
+        .merge(local_storage)
         .merge(docs::router())
         .merge(oauth_route::router())
         .merge(auth_route::public_router())
+        .merge(business_route::router())
+        .merge(area::router())
+        .merge(webhooks::router())
         .merge(protected)
         .merge(api_key_protected)
         .with_state(api_context)
@@ -174,3 +304,86 @@ async fn get_aws_config() -> SdkConfig {
         .load()
         .await
 }
+
+type EmailTransportParts = (Arc<dyn EmailTransport>, Option<Arc<MemoryOutbox>>);
+
+fn get_email_transport(
+    config: &AppConfig,
+    aws_config: &SdkConfig,
+    renderer: Arc<TemplateRenderer>,
+) -> anyhow::Result<EmailTransportParts> {
+    let transport: Arc<dyn EmailTransport> = match &config.email.transport {
+        EmailTransportConfig::Ses => Arc::new(SesTransport::new(aws_config, renderer)),
+        EmailTransportConfig::Smtp(smtp) => Arc::new(SmtpTransport::new(smtp, renderer)?),
+        EmailTransportConfig::File { dir } => Arc::new(FileOutbox::new(dir, renderer)),
+        EmailTransportConfig::Memory => {
+            let outbox = Arc::new(MemoryOutbox::default());
+            return Ok((outbox.clone(), Some(outbox)));
+        }
+    };
+
+    Ok((transport, None))
+}
+
+type StorageParts = (Arc<dyn Storage>, Option<Arc<LocalStorage>>);
+
+fn get_storage(
+    config: &AppConfig,
+    aws_config: &SdkConfig,
+    port: u16,
+) -> anyhow::Result<StorageParts> {
+    let parts: StorageParts = match &config.storage {
+        StorageConfig::S3 => (
+            Arc::new(S3Storage::new(
+                aws_config,
+                &config.aws.s3,
+                &config.aws.private_s3,
+                &config.aws.cdn,
+            )?),
+            None,
+        ),
+        StorageConfig::Local { dir, public_url } => {
+            let public_url = public_url
+                .clone()
+                .unwrap_or_else(|| format!("http://localhost:{}", port));
+            let storage = Arc::new(LocalStorage::new(dir, &public_url, &config.hmac));
+
+            (storage.clone(), Some(storage))
+        }
+    };
+
+    Ok(parts)
+}
+
+type SmsTransportParts = (Arc<dyn SmsTransport>, Option<Arc<MemorySmsOutbox>>);
+
+fn get_sms_transport(config: &AppConfig) -> SmsTransportParts {
+    match &config.sms.transport {
+        SmsTransportConfig::Log => {
+            if !config.sms_delivers() {
+                tracing::warn!("sms transport is `log`, phone login is disabled");
+            }
+
+            (Arc::new(LogSms), None)
+        }
+        SmsTransportConfig::File { dir } => (Arc::new(FileSmsOutbox::new(dir)), None),
+        SmsTransportConfig::Memory => {
+            let outbox = Arc::new(MemorySmsOutbox::default());
+            (outbox.clone(), Some(outbox))
+        }
+    }
+}
+
+fn get_sns_verifier(
+    config: &AppConfig,
+    http_client: &reqwest::Client,
+) -> anyhow::Result<Arc<dyn SnsVerifier>> {
+    let verifier: Arc<dyn SnsVerifier> = match &config.email.webhook.verifier {
+        SnsVerifierConfig::Aws => Arc::new(AwsSnsVerifier::new(http_client.clone())),
+        SnsVerifierConfig::Local { public_key } => {
+            Arc::new(LocalKeyVerifier::from_pem(public_key)?)
+        }
+    };
+
+    Ok(verifier)
+}
@@ -1,16 +1,10 @@
 use anyhow::Context;
 use serde::Deserialize;
-use sqlx::PgPool;
-use uuid::Uuid;
 
 use crate::{
-    app::{
-        error::AppError,
-        oauth::{
-            get_or_create_user, update_missing_user_metadata, upsert_email, upsert_social_login,
-            OAuthClient, UpdateUserMetadata,
-        },
-        utils::avatar_generator::generate_avatar,
+    app::oauth::{
+        state::OAuthState, ClientAuthMethod, OAuthClient, OAuthProvider, ProviderTokens,
+        ProviderUser,
     },
     config::AppConfig,
     routes::oauth::AssertionProvider,
@@ -26,96 +20,83 @@ struct DiscordUser {
     pub avatar: Option<String>, // avatar_hash
 }
 
-/// Converts a Discord OAuth token to an access token and updates the database.
-///
-/// # Overview
-/// `handle_discord_assertion` is responsible for handling Discord login assertions. It takes in
-/// an OAuth code, uses it to request an access token from Discord, and then updates the relevant
-/// database tables with the user information obtained from the access token.
-///
-/// # Returns
-///
-/// - `Result<Uuid, AppError>`: Returns a `Result` that, on success, contains the `user_id`
-///   associated with the Discord user. If the operation fails at any step (e.g., invalid code,
-///   network error, database error), it returns an `AppError`.
-pub async fn handle_discord_assertion(
-    pool: &PgPool,
-    config: &AppConfig,
-    http_client: &reqwest::Client,
-    code: &str,
-) -> Result<Uuid, AppError> {
-    let discord_client = OAuthClient::new(
-        &config.discord.id,
-        &config.discord.secret,
-        &config.discord.token_url,
-        &format!("{}/auth/oauth", &config.frontend.url),
-    );
+/// Discord OAuth2 login.
+pub struct DiscordProvider {
+    client: OAuthClient,
+    api_base_url: String,
+}
 
-    let token = discord_client
-        .exchange_code_for_access_token(code, http_client)
-        .await
-        .context("failed to exchange code for token")?;
+impl DiscordProvider {
+    pub fn new(config: &AppConfig) -> Self {
+        let client = OAuthClient::new(
+            &config.discord.id,
+            &config.discord.secret,
+            &config.discord.authorize_url,
+            &config.discord.token_url,
+            &config.frontend.oauth_redirect_uri(),
+            ClientAuthMethod::Basic,
+        );
 
-    let user_data: DiscordUser = http_client
-        .get(format!("{}/users/@me", config.discord.api_base_url))
-        .header("Accept", "application/json")
-        .header("User-Agent", "Let's Yahu".to_owned())
-        .bearer_auth(&token)
-        .send()
-        .await
-        .context("failed to get user details")?
-        .json::<DiscordUser>()
-        .await
-        .context("failed to deserialize as JSON")?;
+        Self {
+            client,
+            api_base_url: config.discord.api_base_url.clone(),
+        }
+    }
+}
 
-    tracing::debug!("User data: {:?}", &user_data);
+impl OAuthProvider for DiscordProvider {
+    fn provider(&self) -> AssertionProvider {
+        AssertionProvider::Discord
+    }
 
-    match user_data.email {
-        Some(provider_email) => {
-            let mut tx = pool.begin().await?;
+    fn authorize_url(&self, state: &str, auth: &OAuthState) -> anyhow::Result<String> {
+        self.client
+            .authorize_url("identify email", state, auth, false)
+    }
 
-            // Upsert db
-            let user_id = get_or_create_user(&provider_email, &mut tx).await?;
-            let email_id = upsert_email(
-                &provider_email,
-                &user_id,
-                Some(true) == user_data.verified,
-                &mut tx,
-            )
-            .await?;
-            upsert_social_login(
-                &email_id,
-                &user_id,
-                AssertionProvider::Discord,
-                &user_data.id.to_string(),
-                &mut tx,
-            )
-            .await?;
+    async fn exchange_code(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderTokens> {
+        self.client
+            .exchange_code_for_access_token(code, auth, http_client)
+            .await
+            .map(ProviderTokens::from)
+    }
 
-            // https://discord.com/developers/docs/reference#image-formatting
-            let image = match user_data.avatar {
-                Some(hash) => format!(
-                    "https://cdn.discordapp.com/avatars/{}/{}",
-                    user_data.id, hash
-                ),
-                None => generate_avatar(&user_id.to_string()),
-            };
+    async fn fetch_user(
+        &self,
+        tokens: &ProviderTokens,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderUser> {
+        let user_data: DiscordUser = http_client
+            .get(format!("{}/users/@me", self.api_base_url))
+            .header("Accept", "application/json")
+            .header("User-Agent", "Let's Yahu".to_owned())
+            .bearer_auth(&tokens.access_token)
+            .send()
+            .await
+            .context("failed to get user details")?
+            .json::<DiscordUser>()
+            .await
+            .context("failed to deserialize as JSON")?;
 
-            update_missing_user_metadata(
-                UpdateUserMetadata {
-                    user_id,
-                    bio: None,
-                    image,
-                },
-                &mut tx,
+        // https://discord.com/developers/docs/reference#image-formatting
+        let image = user_data.avatar.map(|hash| {
+            format!(
+                "https://cdn.discordapp.com/avatars/{}/{}",
+                user_data.id, hash
             )
-            .await?;
-
-            tx.commit().await?;
-
-            Ok(user_id)
-        }
+        });
 
-        None => Err(AppError::unprocessable_entity([("email", "missing")])),
+        Ok(ProviderUser {
+            id: user_data.id,
+            email: user_data.email,
+            email_verified: Some(true) == user_data.verified,
+            bio: None,
+            image,
+        })
     }
 }
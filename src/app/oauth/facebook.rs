@@ -0,0 +1,144 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+
+use crate::{
+    app::oauth::{
+        state::OAuthState, OAuthAccessToken, OAuthProvider, ProviderTokens, ProviderUser,
+    },
+    config::AppConfig,
+    routes::oauth::AssertionProvider,
+};
+
+// The user data we'll get back from the Graph API.
+// https://developers.facebook.com/docs/graph-api/reference/user
+#[derive(Debug, Deserialize)]
+struct FacebookUser {
+    pub id: String,
+    pub email: Option<String>,
+    pub picture: Option<FacebookPicture>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FacebookPicture {
+    pub data: FacebookPictureData,
+}
+
+#[derive(Debug, Deserialize)]
+struct FacebookPictureData {
+    pub url: String,
+    #[serde(default)]
+    pub is_silhouette: bool,
+}
+
+/// Facebook login through the Graph API.
+///
+/// Facebook only returns confirmed emails, but the user may have
+/// signed up with a phone number so email can be missing.
+pub struct FacebookProvider {
+    client_id: String,
+    client_secret: SecretString,
+    authorize_url: String,
+    token_url: String,
+    api_base_url: String,
+    redirect_uri: String,
+}
+
+impl FacebookProvider {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            client_id: config.facebook.id.clone(),
+            client_secret: config.facebook.secret.clone(),
+            authorize_url: config.facebook.authorize_url.clone(),
+            token_url: config.facebook.token_url.clone(),
+            api_base_url: config.facebook.api_base_url.clone(),
+            redirect_uri: config.frontend.oauth_redirect_uri(),
+        }
+    }
+}
+
+impl OAuthProvider for FacebookProvider {
+    fn provider(&self) -> AssertionProvider {
+        AssertionProvider::Facebook
+    }
+
+    /// https://developers.facebook.com/docs/facebook-login/guides/advanced/manual-flow#login
+    fn authorize_url(&self, state: &str, auth: &OAuthState) -> anyhow::Result<String> {
+        let challenge = auth.code_challenge();
+        let url = url::Url::parse_with_params(
+            &self.authorize_url,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("scope", "email,public_profile"),
+                ("state", state),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("invalid authorize url")?;
+
+        Ok(url.to_string())
+    }
+
+    /// https://developers.facebook.com/docs/facebook-login/guides/advanced/manual-flow#exchangecode
+    async fn exchange_code(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderTokens> {
+        let res: OAuthAccessToken = http_client
+            .get(&self.token_url)
+            .header("Accept", "application/json")
+            .query(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.expose_secret()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("code", code),
+                ("code_verifier", auth.code_verifier.as_str()),
+            ])
+            .send()
+            .await
+            .context("failed to exchange code for token")?
+            .json::<OAuthAccessToken>()
+            .await
+            .context("failed to deserialize as JSON")?;
+
+        Ok(res.access_token.into())
+    }
+
+    async fn fetch_user(
+        &self,
+        tokens: &ProviderTokens,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderUser> {
+        let user_data: FacebookUser = http_client
+            .get(format!("{}/me", self.api_base_url))
+            .query(&[("fields", "id,email,name,picture")])
+            .header("Accept", "application/json")
+            .bearer_auth(&tokens.access_token)
+            .send()
+            .await
+            .context("failed to get user details")?
+            .json::<FacebookUser>()
+            .await
+            .context("failed to deserialize as JSON")?;
+
+        // Default silhouette is not worth importing
+        let image = user_data
+            .picture
+            .map(|p| p.data)
+            .filter(|data| !data.is_silhouette)
+            .map(|data| data.url);
+
+        Ok(ProviderUser {
+            id: user_data.id,
+            email: user_data.email,
+            email_verified: true,
+            bio: None,
+            image,
+        })
+    }
+}
@@ -1,16 +1,10 @@
 use anyhow::Context;
 use serde::Deserialize;
-use sqlx::PgPool;
-use uuid::Uuid;
 
 use crate::{
-    app::{
-        error::AppError,
-        oauth::{
-            get_or_create_user, update_missing_user_metadata, upsert_email, upsert_social_login,
-            OAuthClient, UpdateUserMetadata,
-        },
-        utils::avatar_generator::generate_avatar,
+    app::oauth::{
+        state::OAuthState, ClientAuthMethod, OAuthClient, OAuthProvider, ProviderTokens,
+        ProviderUser,
     },
     config::AppConfig,
     routes::oauth::AssertionProvider,
@@ -34,59 +28,40 @@ struct GithubUserEmail {
     pub primary: bool,
 }
 
-/// Converts a GitHub OAuth token to an access token and updates the database.
+/// GitHub OAuth app login.
 ///
-/// # Overview
-/// `handle_github_assertion` is responsible for handling GitHub login assertions. It takes in
-/// an OAuth code, uses it to request an access token from GitHub, and then updates the relevant
-/// database tables with the user information obtained from the access token.
-///
-/// # Returns
-///
-/// - `Result<Uuid, AppError>`: Returns a `Result` that, on success, contains the `user_id`
-///   associated with the GitHub user. If the operation fails at any step (e.g., invalid code,
-///   network error, database error), it returns an `AppError`.
-pub async fn handle_github_assertion(
-    pool: &PgPool,
-    config: &AppConfig,
-    http_client: &reqwest::Client,
-    code: &str,
-) -> Result<Uuid, AppError> {
-    let git_client = OAuthClient::new(
-        &config.github.id,
-        &config.github.secret,
-        &config.github.token_url,
-        &format!("{}/auth/oauth", &config.frontend.url),
-    );
-
-    let token = git_client
-        .exchange_code_for_access_token(code, http_client)
-        .await
-        .context("failed to exchange code for token")?;
-
-    let user_data: GithubUser = http_client
-        .get(format!("{}/user", config.github.api_base_url))
-        .bearer_auth(&token)
-        .header("Accept", "application/json")
-        .header("X-GitHub-Api-Version", "2022-11-28")
-        .header("User-Agent", "Let's Yahu".to_owned())
-        .send()
-        .await
-        .context("failed to get user details")?
-        .json::<GithubUser>()
-        .await
-        .context("failed to deserialize as JSON")?;
-
-    tracing::debug!("User data: {:?}", &user_data);
-
-    let mut user_data_email = user_data.email.clone();
-    let mut user_email_verified = true;
-
-    if user_data_email.is_none() {
-        // fetch email
+/// GitHub is not an OIDC provider, public profile email may be hidden
+/// so it falls back to the `/user/emails` endpoint.
+pub struct GithubProvider {
+    client: OAuthClient,
+    api_base_url: String,
+}
+
+impl GithubProvider {
+    pub fn new(config: &AppConfig) -> Self {
+        let client = OAuthClient::new(
+            &config.github.id,
+            &config.github.secret,
+            &config.github.authorize_url,
+            &config.github.token_url,
+            &config.frontend.oauth_redirect_uri(),
+            ClientAuthMethod::Post,
+        );
+
+        Self {
+            client,
+            api_base_url: config.github.api_base_url.clone(),
+        }
+    }
+
+    async fn fetch_emails(
+        &self,
+        access_token: &str,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<Vec<GithubUserEmail>> {
         let user_email_list: Vec<GithubUserEmail> = http_client
-            .get(format!("{}/user/emails", config.github.api_base_url))
-            .bearer_auth(&token)
+            .get(format!("{}/user/emails", self.api_base_url))
+            .bearer_auth(access_token)
             .header("Accept", "application/json")
             .header("X-GitHub-Api-Version", "2022-11-28")
             .header("User-Agent", "Let's Yahu".to_owned())
@@ -97,60 +72,81 @@ pub async fn handle_github_assertion(
             .await
             .context("failed to deserialize as JSON")?;
 
-        if user_email_list.is_empty() {
-            tracing::warn!("User email list is empty {:?}", &user_data);
-            user_email_verified = false;
-        } else {
-            // find primary email
-            match user_email_list.iter().find(|x| x.primary) {
-                Some(email) => {
-                    user_data_email = Some(email.email.clone());
-                    user_email_verified = email.verified;
-                }
+        Ok(user_email_list)
+    }
+}
 
-                None => {
-                    if let Some(email) = user_email_list.first() {
-                        user_data_email = Some(email.email.clone())
-                    }
-                }
-            }
-        }
+impl OAuthProvider for GithubProvider {
+    fn provider(&self) -> AssertionProvider {
+        AssertionProvider::Github
     }
 
-    match user_data_email {
-        Some(provider_email) => {
-            let mut tx = pool.begin().await?;
-
-            // Upsert db
-            let user_id = get_or_create_user(&provider_email, &mut tx).await?;
-            let email_id =
-                upsert_email(&provider_email, &user_id, user_email_verified, &mut tx).await?;
-            upsert_social_login(
-                &email_id,
-                &user_id,
-                AssertionProvider::Github,
-                &user_data.id.to_string(),
-                &mut tx,
-            )
-            .await?;
-
-            update_missing_user_metadata(
-                UpdateUserMetadata {
-                    user_id,
-                    bio: user_data.bio,
-                    image: user_data
-                        .avatar_url
-                        .unwrap_or(generate_avatar(&user_id.to_string())),
-                },
-                &mut tx,
-            )
-            .await?;
-
-            tx.commit().await?;
-
-            Ok(user_id)
-        }
+    fn authorize_url(&self, state: &str, auth: &OAuthState) -> anyhow::Result<String> {
+        self.client
+            .authorize_url("read:user user:email", state, auth, false)
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderTokens> {
+        self.client
+            .exchange_code_for_access_token(code, auth, http_client)
+            .await
+            .map(ProviderTokens::from)
+    }
 
-        None => Err(AppError::unprocessable_entity([("email", "missing")])),
+    async fn fetch_user(
+        &self,
+        tokens: &ProviderTokens,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderUser> {
+        let user_data: GithubUser = http_client
+            .get(format!("{}/user", self.api_base_url))
+            .bearer_auth(&tokens.access_token)
+            .header("Accept", "application/json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "Let's Yahu".to_owned())
+            .send()
+            .await
+            .context("failed to get user details")?
+            .json::<GithubUser>()
+            .await
+            .context("failed to deserialize as JSON")?;
+
+        // Only verified emails can be made public on the profile
+        let (user_data_email, user_email_verified) = match user_data.email.clone() {
+            Some(email) => (Some(email), true),
+            None => {
+                let user_email_list = self.fetch_emails(&tokens.access_token, http_client).await?;
+
+                // Primary verified email, then any verified one,
+                // unverified ones are only kept to ask for confirmation
+                let email = user_email_list
+                    .iter()
+                    .find(|x| x.primary && x.verified)
+                    .or_else(|| user_email_list.iter().find(|x| x.verified))
+                    .or_else(|| user_email_list.iter().find(|x| x.primary))
+                    .or_else(|| user_email_list.first());
+
+                match email {
+                    Some(email) => (Some(email.email.clone()), email.verified),
+                    None => {
+                        tracing::warn!("User email list is empty {:?}", &user_data);
+                        (None, false)
+                    }
+                }
+            }
+        };
+
+        Ok(ProviderUser {
+            id: user_data.id.to_string(),
+            email: user_data_email,
+            email_verified: user_email_verified,
+            bio: user_data.bio,
+            image: user_data.avatar_url,
+        })
     }
 }
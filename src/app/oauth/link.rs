@@ -0,0 +1,77 @@
+use redis::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::{
+    app::{
+        email::client::EmailClient,
+        otp::store::OtpPurpose,
+        utils::{redis_record, types::Locale},
+    },
+    routes::oauth::AssertionProvider,
+};
+
+use super::signup::hash;
+
+pub const SOCIAL_LINK_LENGTH: time::Duration = time::Duration::hours(1);
+
+/// Social login waiting to be attached to an existing user.
+///
+/// Created when a provider returns an email that belongs to an existing user
+/// but does not vouch for it. The owner confirms the link either by logging in
+/// or with the `OtpPurpose::SocialLink` code emailed to the address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingSocialLink {
+    pub user_id: Uuid,
+    pub email: String,
+    pub provider: AssertionProvider,
+    pub oidc_provider: Option<String>,
+    pub provider_user_id: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+}
+
+fn get_db_key(token: &str) -> String {
+    format!("social_link:{}", hash(token))
+}
+
+impl PendingSocialLink {
+    #[tracing::instrument(name = "Storing pending social link", skip_all)]
+    pub async fn store(&self, token: &str, client: &Client) -> anyhow::Result<()> {
+        redis_record::store(&get_db_key(token), self, SOCIAL_LINK_LENGTH, client).await
+    }
+
+    #[tracing::instrument(name = "Get pending social link", skip_all)]
+    pub async fn get(token: &str, client: &Client) -> anyhow::Result<Option<Self>> {
+        redis_record::get(&get_db_key(token), client).await
+    }
+
+    #[tracing::instrument(name = "Delete pending social link", skip_all)]
+    pub async fn delete(token: &str, client: &Client) -> anyhow::Result<()> {
+        redis_record::delete(&get_db_key(token), client).await
+    }
+
+    #[tracing::instrument(name = "Queue social link email", skip_all, fields(email = ?email))]
+    pub async fn queue_email(
+        client: &EmailClient,
+        code: &str,
+        email: &str,
+        locale: Locale,
+        provider_name: &str,
+        executor: impl PgExecutor<'_>,
+    ) -> anyhow::Result<()> {
+        let email_content = client
+            .build_social_link_confirmation(
+                code,
+                provider_name,
+                OtpPurpose::SocialLink.ttl().whole_hours(),
+            )
+            .await?;
+        client
+            .queue_email(email, locale, email_content, executor)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,71 +1,156 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future};
 
 use anyhow::Context;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
-use sqlx::{Postgres, Transaction};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::routes::oauth::AssertionProvider;
+use crate::{
+    app::{
+        email::client::get_user_locale,
+        error::AppError,
+        otp::store::{OtpPurpose, OtpStore},
+        storage::avatar::set_default_avatar,
+        ApiContext,
+    },
+    routes::oauth::AssertionProvider,
+};
+use discord::DiscordProvider;
+use facebook::FacebookProvider;
+use github::GithubProvider;
+use link::PendingSocialLink;
+use oidc::OidcProvider;
+use signup::PendingSocialSignup;
+use state::OAuthState;
 
 pub mod discord;
+pub mod facebook;
 pub mod github;
+pub mod link;
+pub mod oidc;
+pub mod signup;
+pub mod state;
+
+/// How the client credentials are sent to the token endpoint.
+///
+/// https://openid.net/specs/openid-connect-core-1_0.html#ClientAuthentication
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuthMethod {
+    /// `client_secret_basic`, credentials in the `Authorization` header
+    Basic,
+    /// `client_secret_post`, credentials in the form body
+    Post,
+}
 
 pub struct OAuthClient {
     client_id: String,
     client_secret: SecretString,
+    authorize_url: String,
     token_url: String,
     redirect_uri: String,
+    auth_method: ClientAuthMethod,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct OAuthAccessToken {
     access_token: String,
+    /// Only returned by OpenID Connect providers
+    id_token: Option<String>,
 }
 
 impl OAuthClient {
     pub fn new(
         client_id: &str,
         client_secret: &SecretString,
+        authorize_url: &str,
         token_url: &str,
         redirect_uri: &str,
+        auth_method: ClientAuthMethod,
     ) -> Self {
         Self {
             client_id: client_id.to_owned(),
             client_secret: client_secret.clone(),
+            authorize_url: authorize_url.to_owned(),
             token_url: token_url.to_owned(),
             redirect_uri: redirect_uri.to_owned(),
+            auth_method,
         }
     }
 
+    /// Provider url the user is sent to, with state and the PKCE challenge attached.
+    ///
+    /// `nonce` is only understood by OpenID Connect providers.
+    pub fn authorize_url(
+        &self,
+        scope: &str,
+        state: &str,
+        auth: &OAuthState,
+        nonce: bool,
+    ) -> anyhow::Result<String> {
+        let challenge = auth.code_challenge();
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("scope", scope),
+            ("state", state),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ];
+
+        if nonce {
+            params.push(("nonce", auth.nonce.as_str()));
+        }
+
+        let url = url::Url::parse_with_params(&self.authorize_url, &params)
+            .context("invalid authorize url")?;
+
+        Ok(url.to_string())
+    }
+
     /// # Security Warning
     ///
     /// Leaking this value may compromise the security of the OAuth2 flow.
     pub async fn exchange_code_for_access_token(
         &self,
         code: &str,
+        auth: &OAuthState,
         client: &reqwest::Client,
     ) -> anyhow::Result<String> {
-        let mut body = HashMap::new();
+        let res = self.exchange_code_for_tokens(code, auth, client).await?;
 
-        // Common
-        body.insert("code", code);
-        body.insert("redirect_uri", &self.redirect_uri);
+        Ok(res.access_token)
+    }
 
-        // Github
-        body.insert("client_id", &self.client_id);
-        body.insert("client_secret", self.client_secret.expose_secret());
+    pub async fn exchange_code_for_tokens(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        client: &reqwest::Client,
+    ) -> anyhow::Result<OAuthAccessToken> {
+        let mut body = HashMap::new();
 
-        // Discord
         body.insert("grant_type", "authorization_code");
+        body.insert("code", code);
+        body.insert("redirect_uri", &self.redirect_uri);
+        body.insert("code_verifier", &auth.code_verifier);
 
         let req = client
             .post(&self.token_url)
             .header("Accept", "application/json")
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .form(&body)
-            // Discord
-            .basic_auth(&self.client_id, Some(self.client_secret.expose_secret()));
+            .header("Content-Type", "application/x-www-form-urlencoded");
+
+        let req = match self.auth_method {
+            ClientAuthMethod::Basic => req
+                .basic_auth(&self.client_id, Some(self.client_secret.expose_secret()))
+                .form(&body),
+            ClientAuthMethod::Post => {
+                body.insert("client_id", &self.client_id);
+                body.insert("client_secret", self.client_secret.expose_secret());
+                req.form(&body)
+            }
+        };
 
         tracing::debug!("Exchange request: {:?}", req);
         let res: OAuthAccessToken = req
@@ -76,10 +161,395 @@ impl OAuthClient {
             .await
             .context("failed to deserialize as JSON")?;
 
-        Ok(res.access_token)
+        Ok(res)
     }
 }
 
+/// Tokens returned for the authorization code.
+#[derive(Debug)]
+pub struct ProviderTokens {
+    pub access_token: String,
+    /// `sub` of the validated id token, only set by OpenID Connect providers
+    pub subject: Option<String>,
+}
+
+impl From<String> for ProviderTokens {
+    fn from(access_token: String) -> Self {
+        Self {
+            access_token,
+            subject: None,
+        }
+    }
+}
+
+/// User profile normalized from a provider's user info response.
+#[derive(Debug)]
+pub struct ProviderUser {
+    pub id: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+}
+
+/// A social login provider that can turn an authorization code into a user profile.
+///
+/// Built-in providers (GitHub, Discord) carry their own API quirks, generic
+/// OpenID Connect providers are driven purely by configuration.
+pub trait OAuthProvider {
+    /// Value stored in `social_login.provider`
+    fn provider(&self) -> AssertionProvider;
+
+    /// Config key of the provider, only set for generic OIDC providers
+    fn oidc_provider(&self) -> Option<&str> {
+        None
+    }
+
+    /// Provider url that starts the authorization code flow
+    fn authorize_url(&self, state: &str, auth: &OAuthState) -> anyhow::Result<String>;
+
+    fn exchange_code(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> impl Future<Output = anyhow::Result<ProviderTokens>> + Send;
+
+    fn fetch_user(
+        &self,
+        tokens: &ProviderTokens,
+        http_client: &reqwest::Client,
+    ) -> impl Future<Output = anyhow::Result<ProviderUser>> + Send;
+}
+
+/// Any of the supported providers, picked from the client's input.
+pub enum SocialProvider {
+    Github(GithubProvider),
+    Discord(DiscordProvider),
+    Facebook(FacebookProvider),
+    Oidc(Box<OidcProvider>),
+}
+
+impl SocialProvider {
+    /// Builds the provider requested by the client.
+    ///
+    /// Returns `AppError::NotFound` for inactive or unknown providers.
+    pub async fn build(
+        provider: &AssertionProvider,
+        oidc_provider: Option<&str>,
+        ctx: &ApiContext,
+    ) -> Result<Self, AppError> {
+        match provider {
+            AssertionProvider::Github => Ok(Self::Github(GithubProvider::new(&ctx.config))),
+            AssertionProvider::Discord => Ok(Self::Discord(DiscordProvider::new(&ctx.config))),
+            AssertionProvider::Facebook => Ok(Self::Facebook(FacebookProvider::new(&ctx.config))),
+            AssertionProvider::Oidc => {
+                let key = oidc_provider.unwrap_or_default();
+                let provider =
+                    OidcProvider::discover(key, &ctx.config, &ctx.oidc_discovery, &ctx.http_client)
+                        .await?;
+
+                Ok(Self::Oidc(Box::new(provider)))
+            }
+            AssertionProvider::Google => Err(AppError::NotFound),
+        }
+    }
+}
+
+impl OAuthProvider for SocialProvider {
+    fn provider(&self) -> AssertionProvider {
+        match self {
+            Self::Github(p) => p.provider(),
+            Self::Discord(p) => p.provider(),
+            Self::Facebook(p) => p.provider(),
+            Self::Oidc(p) => p.provider(),
+        }
+    }
+
+    fn oidc_provider(&self) -> Option<&str> {
+        match self {
+            Self::Github(p) => p.oidc_provider(),
+            Self::Discord(p) => p.oidc_provider(),
+            Self::Facebook(p) => p.oidc_provider(),
+            Self::Oidc(p) => p.oidc_provider(),
+        }
+    }
+
+    fn authorize_url(&self, state: &str, auth: &OAuthState) -> anyhow::Result<String> {
+        match self {
+            Self::Github(p) => p.authorize_url(state, auth),
+            Self::Discord(p) => p.authorize_url(state, auth),
+            Self::Facebook(p) => p.authorize_url(state, auth),
+            Self::Oidc(p) => p.authorize_url(state, auth),
+        }
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderTokens> {
+        match self {
+            Self::Github(p) => p.exchange_code(code, auth, http_client).await,
+            Self::Discord(p) => p.exchange_code(code, auth, http_client).await,
+            Self::Facebook(p) => p.exchange_code(code, auth, http_client).await,
+            Self::Oidc(p) => p.exchange_code(code, auth, http_client).await,
+        }
+    }
+
+    async fn fetch_user(
+        &self,
+        tokens: &ProviderTokens,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderUser> {
+        match self {
+            Self::Github(p) => p.fetch_user(tokens, http_client).await,
+            Self::Discord(p) => p.fetch_user(tokens, http_client).await,
+            Self::Facebook(p) => p.fetch_user(tokens, http_client).await,
+            Self::Oidc(p) => p.fetch_user(tokens, http_client).await,
+        }
+    }
+}
+
+/// Exchanges the code and fetches the provider's user profile.
+pub async fn fetch_provider_user(
+    provider: &impl OAuthProvider,
+    code: &str,
+    auth: &OAuthState,
+    http_client: &reqwest::Client,
+) -> anyhow::Result<ProviderUser> {
+    let tokens = provider
+        .exchange_code(code, auth, http_client)
+        .await
+        .context("failed to exchange code for token")?;
+
+    let user_data = provider
+        .fetch_user(&tokens, http_client)
+        .await
+        .context("failed to get user details")?;
+
+    tracing::debug!("User data: {:?}", &user_data);
+
+    Ok(user_data)
+}
+
+/// Converts a provider OAuth code to a user and updates the database.
+///
+/// # Overview
+/// `handle_assertion` exchanges the code for an access token, fetches the user profile from
+/// the provider and upserts the user, email and social login rows in a single transaction.
+///
+/// A provider account that is already linked always logs into the linked user, email matching
+/// is only used the first time an account is seen.
+///
+/// Matching an existing user by email is only trusted when the provider verified the email.
+/// Otherwise the link is kept as a `PendingSocialLink`, a code is emailed to the address and
+/// `AppError::LinkConfirmationRequired` is returned, the owner confirms it with the code or by
+/// logging in.
+///
+/// When the provider returns no email for a user we haven't seen before, the profile is kept
+/// as a `PendingSocialSignup` and `AppError::EmailRequired` is returned so the user can
+/// supply and verify one.
+///
+/// # Returns
+///
+/// - `Result<Uuid, AppError>`: Returns a `Result` that, on success, contains the `user_id`
+///   associated with the provider user. If the operation fails at any step (e.g., invalid code,
+///   network error, database error), it returns an `AppError`.
+#[tracing::instrument(name = "Handle assertion", skip_all, fields(provider = ?provider.provider()))]
+pub async fn handle_assertion(
+    provider: &impl OAuthProvider,
+    code: &str,
+    auth: &OAuthState,
+    ctx: &ApiContext,
+) -> Result<Uuid, AppError> {
+    let user_data = fetch_provider_user(provider, code, auth, &ctx.http_client).await?;
+
+    let linked_user = get_social_login_user(
+        provider.provider(),
+        provider.oidc_provider(),
+        &user_data.id,
+        &ctx.db_pool,
+    )
+    .await?;
+
+    if let Some(user_id) = linked_user {
+        return Ok(user_id);
+    }
+
+    match user_data.email.clone() {
+        Some(provider_email) => {
+            let email_owner = get_email_user(&provider_email, &ctx.db_pool).await?;
+
+            if let Some(user_id) = email_owner {
+                if !user_data.email_verified {
+                    let link_token =
+                        start_pending_link(user_id, provider, provider_email, user_data, ctx)
+                            .await?;
+
+                    return Err(AppError::LinkConfirmationRequired { link_token });
+                }
+            }
+
+            let mut tx = ctx.db_pool.begin().await?;
+
+            let user_id = upsert_social_user(
+                provider.provider(),
+                provider.oidc_provider(),
+                &provider_email,
+                user_data,
+                &mut tx,
+            )
+            .await?;
+
+            tx.commit().await?;
+
+            // Without a provider image new users get a generated one
+            set_default_avatar(&user_id, &*ctx.storage, &ctx.db_pool).await;
+
+            Ok(user_id)
+        }
+
+        None => {
+            let signup_token = signup::generate_token();
+            PendingSocialSignup {
+                provider: provider.provider(),
+                oidc_provider: provider.oidc_provider().map(str::to_string),
+                provider_user_id: user_data.id,
+                bio: user_data.bio,
+                image: user_data.image,
+            }
+            .store(&signup_token, &ctx.redis_client)
+            .await?;
+
+            Err(AppError::EmailRequired { signup_token })
+        }
+    }
+}
+
+/// Stores the pending link and emails its code to the owner of the email
+async fn start_pending_link(
+    user_id: Uuid,
+    provider: &impl OAuthProvider,
+    email: String,
+    user_data: ProviderUser,
+    ctx: &ApiContext,
+) -> Result<String, AppError> {
+    let pending = PendingSocialLink {
+        user_id,
+        email,
+        provider: provider.provider(),
+        oidc_provider: provider.oidc_provider().map(str::to_string),
+        provider_user_id: user_data.id,
+        bio: user_data.bio,
+        image: user_data.image,
+    };
+
+    let link_token = signup::generate_token();
+    pending.store(&link_token, &ctx.redis_client).await?;
+
+    let code = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .issue(
+            OtpPurpose::SocialLink,
+            &signup::otp_subject(&link_token),
+            &pending.email,
+        )
+        .await?;
+
+    let provider_name = provider
+        .oidc_provider()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:?}", provider.provider()));
+
+    let locale = get_user_locale(&user_id, &*ctx.db_pool).await?;
+    PendingSocialLink::queue_email(
+        &ctx.email_client,
+        &code,
+        &pending.email,
+        locale,
+        &provider_name,
+        &*ctx.db_pool,
+    )
+    .await?;
+
+    Ok(link_token)
+}
+
+/// Attaches a confirmed pending link to its user.
+pub async fn confirm_pending_link(
+    pending: PendingSocialLink,
+    tx: &mut Transaction<'static, Postgres>,
+) -> anyhow::Result<Uuid> {
+    // The email may have been removed since the link was started
+    let email_id = sqlx::query_scalar!(
+        r#"
+            select email_id
+            from email
+            where email = $1 and user_id = $2
+        "#,
+        pending.email,
+        pending.user_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    upsert_social_login(
+        email_id.as_ref(),
+        &pending.user_id,
+        pending.provider,
+        pending.oidc_provider.as_deref(),
+        &pending.provider_user_id,
+        tx,
+    )
+    .await?;
+
+    update_missing_user_metadata(
+        UpdateUserMetadata {
+            user_id: pending.user_id,
+            bio: pending.bio,
+            image: pending.image,
+        },
+        tx,
+    )
+    .await?;
+
+    Ok(pending.user_id)
+}
+
+/// Upserts the user, email and social login rows for a provider profile.
+pub async fn upsert_social_user(
+    provider: AssertionProvider,
+    oidc_provider: Option<&str>,
+    email: &str,
+    user_data: ProviderUser,
+    tx: &mut Transaction<'static, Postgres>,
+) -> anyhow::Result<Uuid> {
+    let user_id = get_or_create_user(email, tx).await?;
+    let email_id = upsert_email(email, &user_id, user_data.email_verified, tx).await?;
+    upsert_social_login(
+        Some(&email_id),
+        &user_id,
+        provider,
+        oidc_provider,
+        &user_data.id,
+        tx,
+    )
+    .await?;
+
+    update_missing_user_metadata(
+        UpdateUserMetadata {
+            user_id,
+            bio: user_data.bio,
+            image: user_data.image,
+        },
+        tx,
+    )
+    .await?;
+
+    Ok(user_id)
+}
+
 // Helper methods
 
 /// Gets previous or newly created user's UUID
@@ -122,6 +592,47 @@ pub async fn get_or_create_user(
     }
 }
 
+/// Gets the user owning the email, if any
+pub async fn get_email_user(email: &str, pool: &PgPool) -> anyhow::Result<Option<Uuid>> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+            select user_id
+            from email
+            where email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
+/// Gets the user already linked to the provider account, if any
+pub async fn get_social_login_user(
+    provider: AssertionProvider,
+    oidc_provider: Option<&str>,
+    provider_user_id: &str,
+    pool: &PgPool,
+) -> anyhow::Result<Option<Uuid>> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+            select user_id
+            from social_login
+            where provider = $1
+                and coalesce(oidc_provider, '') = coalesce($2, '')
+                and provider_user_id = $3
+        "#,
+        provider as _,
+        oidc_provider,
+        provider_user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
 pub async fn upsert_email(
     email: &str,
     user_id: &Uuid,
@@ -146,9 +657,12 @@ pub async fn upsert_email(
             values ($1, $2, $3, $4)
 
             on conflict (email)
-            do update set 
-            verified = true,
-            confirmation_sent_at = null
+            do update set
+            verified = email.verified or excluded.verified,
+            confirmation_sent_at = case
+                when excluded.verified then null
+                else email.confirmation_sent_at
+            end
 
             returning email_id
         "#,
@@ -164,21 +678,23 @@ pub async fn upsert_email(
 }
 
 pub async fn upsert_social_login(
-    email_id: &Uuid,
+    email_id: Option<&Uuid>,
     user_id: &Uuid,
     provider: AssertionProvider,
+    oidc_provider: Option<&str>,
     provider_user_id: &str,
     tx: &mut Transaction<'static, Postgres>,
 ) -> anyhow::Result<()> {
     sqlx::query!(
         r#"
-            insert into social_login (email_id, user_id, provider, provider_user_id)
-            values ($1, $2, $3, $4)
-            on conflict (provider_user_id) do nothing
+            insert into social_login (email_id, user_id, provider, oidc_provider, provider_user_id)
+            values ($1, $2, $3, $4, $5)
+            on conflict (provider, coalesce(oidc_provider, ''), provider_user_id) do nothing
         "#,
         email_id,
         user_id,
         provider as _,
+        oidc_provider,
         provider_user_id
     )
     .execute(&mut **tx)
@@ -192,7 +708,7 @@ pub async fn upsert_social_login(
 pub struct UpdateUserMetadata {
     user_id: Uuid,
     bio: Option<String>,
-    image: String,
+    image: Option<String>,
 }
 
 pub async fn update_missing_user_metadata(
@@ -0,0 +1,433 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use rsa::{
+    pkcs1v15::{Signature, VerifyingKey},
+    signature::Verifier,
+    BigUint, RsaPublicKey,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use time::OffsetDateTime;
+use tokio::sync::RwLock;
+
+use crate::{
+    app::{
+        error::AppError,
+        oauth::{
+            state::OAuthState, ClientAuthMethod, OAuthClient, OAuthProvider, ProviderTokens,
+            ProviderUser,
+        },
+    },
+    config::{AppConfig, OidcClaimMapping, OidcProviderConfig},
+    routes::oauth::AssertionProvider,
+};
+
+pub const DISCOVERY_CACHE_LENGTH: time::Duration = time::Duration::hours(1);
+
+/// Clock difference allowed when checking `exp`
+pub const ID_TOKEN_LEEWAY_SECS: i64 = 60;
+
+// The provider metadata we'll get back from the discovery document.
+// https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcDiscovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+}
+
+// The signing keys published at `jwks_uri`, only RSA keys are used.
+// https://datatracker.ietf.org/doc/html/rfc7517#section-5
+#[derive(Debug, Clone, Deserialize)]
+struct JsonWebKeySet {
+    keys: Vec<JsonWebKey>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct JsonWebKey {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+impl JsonWebKey {
+    fn rsa_public_key(&self) -> anyhow::Result<RsaPublicKey> {
+        let decode = |value: &Option<String>| -> anyhow::Result<BigUint> {
+            let value = value.as_deref().context("RSA key is incomplete")?;
+            let bytes = URL_SAFE_NO_PAD
+                .decode(value)
+                .context("RSA key is not base64url")?;
+
+            Ok(BigUint::from_bytes_be(&bytes))
+        };
+
+        RsaPublicKey::new(decode(&self.n)?, decode(&self.e)?).context("invalid RSA key")
+    }
+}
+
+/// In memory cache of discovery documents keyed by issuer, and their signing keys.
+///
+/// It should only be created once, and shared
+#[derive(Default)]
+pub struct OidcDiscoveryCache {
+    documents: RwLock<HashMap<String, (OffsetDateTime, OidcDiscovery)>>,
+    key_sets: RwLock<HashMap<String, (OffsetDateTime, JsonWebKeySet)>>,
+}
+
+impl OidcDiscoveryCache {
+    #[tracing::instrument(name = "Get discovery document", skip(self, http_client))]
+    pub async fn get(
+        &self,
+        issuer: &str,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<OidcDiscovery> {
+        if let Some((fetched_at, document)) = self.documents.read().await.get(issuer) {
+            if *fetched_at + DISCOVERY_CACHE_LENGTH > OffsetDateTime::now_utc() {
+                return Ok(document.clone());
+            }
+        }
+
+        let document: OidcDiscovery = http_client
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                issuer.trim_end_matches('/')
+            ))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .context("failed to fetch discovery document")?
+            .error_for_status()
+            .context("discovery document request failed")?
+            .json::<OidcDiscovery>()
+            .await
+            .context("failed to deserialize as JSON")?;
+
+        // The issuer in the document must match the one we trust
+        if document.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+            anyhow::bail!("issuer mismatch in discovery document: {}", document.issuer);
+        }
+
+        self.documents.write().await.insert(
+            issuer.to_string(),
+            (OffsetDateTime::now_utc(), document.clone()),
+        );
+
+        Ok(document)
+    }
+
+    /// RSA key the provider signs id tokens with.
+    ///
+    /// An unknown `kid` fetches the keys again, providers rotate them.
+    #[tracing::instrument(name = "Get signing key", skip(self, http_client))]
+    async fn signing_key(
+        &self,
+        jwks_uri: &str,
+        kid: Option<&str>,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<RsaPublicKey> {
+        let find = |key_set: &JsonWebKeySet| {
+            key_set
+                .keys
+                .iter()
+                .filter(|key| key.kty == "RSA" && key.usage.as_deref().unwrap_or("sig") == "sig")
+                .find(|key| kid.is_none() || key.kid.as_deref() == kid)
+                .map(JsonWebKey::rsa_public_key)
+        };
+
+        if let Some((fetched_at, key_set)) = self.key_sets.read().await.get(jwks_uri) {
+            if *fetched_at + DISCOVERY_CACHE_LENGTH > OffsetDateTime::now_utc() {
+                if let Some(key) = find(key_set) {
+                    return key;
+                }
+            }
+        }
+
+        let key_set: JsonWebKeySet = http_client
+            .get(jwks_uri)
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .context("failed to fetch signing keys")?
+            .error_for_status()
+            .context("signing keys request failed")?
+            .json::<JsonWebKeySet>()
+            .await
+            .context("failed to deserialize as JSON")?;
+
+        let key = find(&key_set).context("no signing key matches the id token")?;
+
+        self.key_sets
+            .write()
+            .await
+            .insert(jwks_uri.to_string(), (OffsetDateTime::now_utc(), key_set));
+
+        key
+    }
+}
+
+/// Generic OpenID Connect provider, driven purely by `AppConfig::oidc`.
+pub struct OidcProvider {
+    key: String,
+    config: OidcProviderConfig,
+    discovery: OidcDiscovery,
+    cache: Arc<OidcDiscoveryCache>,
+    client: OAuthClient,
+}
+
+impl OidcProvider {
+    /// Builds the provider registered under `key`, fetching its discovery document if needed.
+    ///
+    /// Returns `AppError::NotFound` for unknown providers.
+    pub async fn discover(
+        key: &str,
+        config: &AppConfig,
+        cache: &Arc<OidcDiscoveryCache>,
+        http_client: &reqwest::Client,
+    ) -> Result<Self, AppError> {
+        let provider_config = config.oidc.get(key).ok_or(AppError::NotFound)?.clone();
+        let discovery = cache.get(&provider_config.issuer, http_client).await?;
+
+        // Prefer basic auth, which is the default in the spec
+        let auth_method = if discovery.token_endpoint_auth_methods_supported.is_empty()
+            || discovery
+                .token_endpoint_auth_methods_supported
+                .iter()
+                .any(|m| m == "client_secret_basic")
+        {
+            ClientAuthMethod::Basic
+        } else {
+            ClientAuthMethod::Post
+        };
+
+        let client = OAuthClient::new(
+            &provider_config.id,
+            &provider_config.secret,
+            &discovery.authorization_endpoint,
+            &discovery.token_endpoint,
+            &config.frontend.oauth_redirect_uri(),
+            auth_method,
+        );
+
+        Ok(Self {
+            key: key.to_string(),
+            config: provider_config,
+            discovery,
+            cache: cache.clone(),
+            client,
+        })
+    }
+
+    /// Checks the signature and claims of the id token, returns its `sub`.
+    ///
+    /// Only RS256 is accepted, the algorithm every provider must support.
+    /// https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<String> {
+        let mut parts = id_token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("id token is not a JWT");
+        };
+
+        let header: IdTokenHeader = decode_part(header).context("invalid id token header")?;
+        if header.alg != "RS256" {
+            anyhow::bail!("unsupported id token algorithm {}", header.alg);
+        }
+
+        let key = self
+            .cache
+            .signing_key(&self.discovery.jwks_uri, header.kid.as_deref(), http_client)
+            .await?;
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .context("id token signature is not base64url")?;
+        let signature =
+            Signature::try_from(signature.as_slice()).context("invalid id token signature")?;
+        let signed = &id_token[..header_and_payload_len(id_token)];
+
+        VerifyingKey::<sha2::Sha256>::new(key)
+            .verify(signed.as_bytes(), &signature)
+            .context("id token signature does not match")?;
+
+        let claims: IdTokenClaims = decode_part(payload).context("invalid id token claims")?;
+
+        if claims.iss.trim_end_matches('/') != self.discovery.issuer.trim_end_matches('/') {
+            anyhow::bail!("issuer mismatch in id token: {}", claims.iss);
+        }
+
+        if !claims.aud.contains(&self.config.id) {
+            anyhow::bail!("id token was not issued for this client");
+        }
+
+        if claims.exp + ID_TOKEN_LEEWAY_SECS < OffsetDateTime::now_utc().unix_timestamp() {
+            anyhow::bail!("id token has expired");
+        }
+
+        if claims.nonce.as_deref() != Some(auth.nonce.as_str()) {
+            anyhow::bail!("nonce mismatch in id token");
+        }
+
+        Ok(claims.sub)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+}
+
+/// `aud` is a single string or an array of them
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+impl OAuthProvider for OidcProvider {
+    fn provider(&self) -> AssertionProvider {
+        AssertionProvider::Oidc
+    }
+
+    fn oidc_provider(&self) -> Option<&str> {
+        Some(&self.key)
+    }
+
+    fn authorize_url(&self, state: &str, auth: &OAuthState) -> anyhow::Result<String> {
+        self.client
+            .authorize_url(&self.config.scopes.join(" "), state, auth, true)
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        auth: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderTokens> {
+        let res = self
+            .client
+            .exchange_code_for_tokens(code, auth, http_client)
+            .await?;
+
+        let subject = match &res.id_token {
+            Some(id_token) => Some(self.validate_id_token(id_token, auth, http_client).await?),
+            // Without an id token nothing ties the login to this client and nonce
+            None if self.config.scopes.iter().any(|scope| scope == "openid") => {
+                anyhow::bail!("id token is missing")
+            }
+            None => None,
+        };
+
+        Ok(ProviderTokens {
+            access_token: res.access_token,
+            subject,
+        })
+    }
+
+    async fn fetch_user(
+        &self,
+        tokens: &ProviderTokens,
+        http_client: &reqwest::Client,
+    ) -> anyhow::Result<ProviderUser> {
+        let userinfo_endpoint = self
+            .discovery
+            .userinfo_endpoint
+            .as_ref()
+            .context("provider has no userinfo endpoint")?;
+
+        let claims: Value = http_client
+            .get(userinfo_endpoint)
+            .header("Accept", "application/json")
+            .bearer_auth(&tokens.access_token)
+            .send()
+            .await
+            .context("failed to get user details")?
+            .error_for_status()
+            .context("user info request failed")?
+            .json::<Value>()
+            .await
+            .context("failed to deserialize as JSON")?;
+
+        // The user info must belong to the user the id token was issued to
+        // https://openid.net/specs/openid-connect-core-1_0.html#UserInfoResponse
+        if let Some(subject) = &tokens.subject {
+            if claims.get("sub").and_then(Value::as_str) != Some(subject.as_str()) {
+                anyhow::bail!("user info subject does not match the id token");
+            }
+        }
+
+        map_claims(&claims, &self.config.claims)
+    }
+}
+
+/// Length of `header.payload`, the part of the JWT that is signed
+fn header_and_payload_len(jwt: &str) -> usize {
+    jwt.rfind('.').unwrap_or(jwt.len())
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(part: &str) -> anyhow::Result<T> {
+    let decoded = URL_SAFE_NO_PAD
+        .decode(part.trim_end_matches('='))
+        .context("JWT part is not base64url")?;
+
+    serde_json::from_slice(&decoded).context("failed to deserialize JWT part")
+}
+
+fn map_claims(claims: &Value, mapping: &OidcClaimMapping) -> anyhow::Result<ProviderUser> {
+    let claim_str = |name: &str| match claims.get(name) {
+        Some(Value::String(s)) => Some(s.to_string()),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    };
+
+    let id = claim_str(&mapping.subject).context("subject claim is missing")?;
+
+    // Some providers send booleans as strings
+    let email_verified = match claims.get(&mapping.email_verified) {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => s == "true",
+        _ => false,
+    };
+
+    Ok(ProviderUser {
+        id,
+        email: claim_str(&mapping.email),
+        email_verified,
+        bio: mapping.bio.as_deref().and_then(claim_str),
+        image: claim_str(&mapping.picture),
+    })
+}
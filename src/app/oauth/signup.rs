@@ -0,0 +1,64 @@
+use base32::encode;
+use rand::RngCore;
+use redis::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{app::utils::redis_record, routes::oauth::AssertionProvider};
+
+pub const SOCIAL_SIGNUP_LENGTH: time::Duration = time::Duration::hours(1);
+
+/// Social profile waiting for the user to supply and verify an email.
+///
+/// Created when a provider does not return an email for a new user,
+/// the signup token is handed to the client to continue the flow.
+/// The email is verified with an `OtpPurpose::SocialSignup` code.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingSocialSignup {
+    pub provider: AssertionProvider,
+    pub oidc_provider: Option<String>,
+    pub provider_user_id: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+}
+
+fn get_db_key(token: &str) -> String {
+    format!("social_signup:{}", hash(token))
+}
+
+pub(super) fn hash(value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value);
+
+    hex::encode(hasher.finalize())
+}
+
+/// Random token handed to the client to continue a social login flow
+pub(super) fn generate_token() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes)
+}
+
+/// Subject of the codes sent for a pending signup or link, tokens are only stored hashed
+pub fn otp_subject(token: &str) -> String {
+    hash(token)
+}
+
+impl PendingSocialSignup {
+    #[tracing::instrument(name = "Storing pending social signup", skip_all)]
+    pub async fn store(&self, token: &str, client: &Client) -> anyhow::Result<()> {
+        redis_record::store(&get_db_key(token), self, SOCIAL_SIGNUP_LENGTH, client).await
+    }
+
+    #[tracing::instrument(name = "Get pending social signup", skip_all)]
+    pub async fn get(token: &str, client: &Client) -> anyhow::Result<Option<Self>> {
+        redis_record::get(&get_db_key(token), client).await
+    }
+
+    #[tracing::instrument(name = "Delete pending social signup", skip_all)]
+    pub async fn delete(token: &str, client: &Client) -> anyhow::Result<()> {
+        redis_record::delete(&get_db_key(token), client).await
+    }
+}
@@ -0,0 +1,73 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use rand::RngCore;
+use redis::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{app::utils::redis_record, routes::oauth::AssertionProvider};
+
+pub const OAUTH_STATE_LENGTH: time::Duration = time::Duration::minutes(10);
+
+/// Authorization request started by `/oauth/{provider}/authorize`.
+///
+/// Stored under the `state` value handed to the provider, the code sent back
+/// by the frontend is only accepted together with a matching, unused state.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthState {
+    pub provider: AssertionProvider,
+    pub oidc_provider: Option<String>,
+    /// Echoed back in the OIDC id token
+    pub nonce: String,
+    /// PKCE verifier, only its S256 challenge leaves the server
+    pub code_verifier: String,
+}
+
+fn get_db_key(state: &str) -> String {
+    format!("oauth_state:{}", state)
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+impl OAuthState {
+    pub fn new(provider: AssertionProvider, oidc_provider: Option<String>) -> Self {
+        Self {
+            provider,
+            oidc_provider,
+            nonce: random_token(),
+            code_verifier: random_token(),
+        }
+    }
+
+    pub fn generate_state() -> String {
+        random_token()
+    }
+
+    /// https://datatracker.ietf.org/doc/html/rfc7636#section-4.2
+    pub fn code_challenge(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.code_verifier);
+
+        URL_SAFE_NO_PAD.encode(hasher.finalize())
+    }
+
+    /// Whether the state was issued for the provider the client is asserting
+    pub fn matches(&self, provider: &AssertionProvider, oidc_provider: Option<&str>) -> bool {
+        self.provider == *provider && self.oidc_provider.as_deref() == oidc_provider
+    }
+
+    #[tracing::instrument(name = "Storing oauth state", skip_all)]
+    pub async fn store(&self, state: &str, client: &Client) -> anyhow::Result<()> {
+        redis_record::store(&get_db_key(state), self, OAUTH_STATE_LENGTH, client).await
+    }
+
+    /// Gets and removes the state, so it can only be used once
+    #[tracing::instrument(name = "Take oauth state", skip_all)]
+    pub async fn take(state: &str, client: &Client) -> anyhow::Result<Option<Self>> {
+        redis_record::take(&get_db_key(state), client).await
+    }
+}
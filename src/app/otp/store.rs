@@ -0,0 +1,269 @@
+use anyhow::Context;
+use base32::encode;
+use rand::{Rng, RngCore};
+use redis::{Client, Script};
+use sha2::{Digest, Sha256};
+use std::sync::LazyLock;
+
+use crate::{app::error::AppError, config::OtpConfig};
+
+/// What a code is for, every purpose has its own keys, lifetime and code format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpPurpose {
+    EmailVerify,
+    PasswordReset,
+    PhoneVerify,
+    PhoneLogin,
+    /// Sent to the email supplied for a social signup, the subject is the signup token
+    SocialSignup,
+    /// Sent to the owner of the email a social login is linked by, the subject is the link token
+    SocialLink,
+}
+
+impl OtpPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OtpPurpose::EmailVerify => "email_verify",
+            OtpPurpose::PasswordReset => "password_reset",
+            OtpPurpose::PhoneVerify => "phone_verify",
+            OtpPurpose::PhoneLogin => "phone_login",
+            OtpPurpose::SocialSignup => "social_signup",
+            OtpPurpose::SocialLink => "social_link",
+        }
+    }
+
+    pub fn ttl(&self) -> time::Duration {
+        match self {
+            OtpPurpose::EmailVerify => time::Duration::days(1),
+            OtpPurpose::PasswordReset => time::Duration::hours(1),
+            OtpPurpose::PhoneVerify => time::Duration::minutes(10),
+            OtpPurpose::PhoneLogin => time::Duration::minutes(5),
+            OtpPurpose::SocialSignup | OtpPurpose::SocialLink => time::Duration::hours(1),
+        }
+    }
+
+    /// Tokens are long enough to be looked up on their own,
+    /// short codes are only checked against the subject they were sent for.
+    fn is_token(&self) -> bool {
+        matches!(self, OtpPurpose::PasswordReset)
+    }
+
+    fn generate_code(&self) -> String {
+        match self {
+            OtpPurpose::EmailVerify | OtpPurpose::SocialSignup | OtpPurpose::SocialLink => {
+                let characters = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+                (0..8)
+                    .map(|_| characters[rand::thread_rng().gen_range(0..characters.len())] as char)
+                    .collect()
+            }
+            OtpPurpose::PasswordReset => {
+                // 15 random bytes, 120 bits of entropy
+                let mut bytes = [0u8; 15];
+                rand::thread_rng().fill_bytes(&mut bytes);
+
+                encode(base32::Alphabet::Rfc4648 { padding: true }, &bytes)
+            }
+            // Numeric codes, easy to type on a phone
+            OtpPurpose::PhoneVerify | OtpPurpose::PhoneLogin => (0..6)
+                .map(|_| char::from(b'0' + rand::thread_rng().gen_range(0..10)))
+                .collect(),
+        }
+    }
+}
+
+// Sets the cooldown, replaces the code previously sent for the same data and
+// stores the new one. Tokens live in a hash of their own, the one KEYS[3] points
+// at is deleted instead. Returns the seconds left when still cooling down.
+static ISSUE_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+            if tonumber(ARGV[4]) > 0 then
+                if not redis.call('SET', KEYS[2], '1', 'NX', 'EX', ARGV[4]) then
+                    return math.max(redis.call('TTL', KEYS[2]), 1)
+                end
+            end
+
+            if KEYS[3] then
+                local previous = redis.call('GET', KEYS[3])
+                if previous then
+                    redis.call('DEL', previous)
+                end
+                redis.call('SET', KEYS[3], KEYS[1], 'EX', ARGV[3])
+            end
+
+            local fields = redis.call('HGETALL', KEYS[1])
+            for i = 1, #fields, 2 do
+                if fields[i] ~= 'attempts' and fields[i + 1] == ARGV[2] then
+                    redis.call('HDEL', KEYS[1], fields[i])
+                end
+            end
+
+            redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+            redis.call('EXPIRE', KEYS[1], ARGV[3])
+            return 0
+        "#,
+    )
+});
+
+// Gets and deletes the code in one step. A wrong code counts as an attempt,
+// and every code of the subject is burned after the last attempt.
+static CONSUME_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+            if redis.call('EXISTS', KEYS[1]) == 0 then
+                return false
+            end
+
+            local data = redis.call('HGET', KEYS[1], ARGV[1])
+            if data then
+                redis.call('HDEL', KEYS[1], ARGV[1], 'attempts')
+                return data
+            end
+
+            local attempts = redis.call('HINCRBY', KEYS[1], 'attempts', 1)
+            if attempts >= tonumber(ARGV[2]) then
+                redis.call('DEL', KEYS[1])
+            end
+            return false
+        "#,
+    )
+});
+
+/// Codes are stored by their SHA-256 only, so lookups never compare the code itself
+fn hash(code: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(code);
+
+    hex::encode(hasher.finalize())
+}
+
+/// One-time codes in Redis, shared by every purpose.
+///
+/// The codes of a subject (the user or phone they were sent for) live in one
+/// hash, `otp:{purpose}:{subject}`, mapping the hashed code to its data along
+/// with a counter of wrong attempts. They expire together, a new code pushes
+/// the expiry of the older ones. Tokens are keyed by their hash instead, and
+/// `otp:{purpose}:{subject}:current:{data}` points at the latest one.
+pub struct OtpStore<'a> {
+    client: &'a Client,
+    config: &'a OtpConfig,
+}
+
+impl<'a> OtpStore<'a> {
+    pub fn new(client: &'a Client, config: &'a OtpConfig) -> Self {
+        Self { client, config }
+    }
+
+    fn record_key(purpose: OtpPurpose, subject: &str) -> String {
+        format!("otp:{}:{}", purpose.as_str(), subject)
+    }
+
+    /// Generates and stores a code for `subject`, returning the code to send.
+    ///
+    /// `data` is handed back when the code is consumed, the previous code (or token)
+    /// with the same data is replaced. Fails with `TooManyRequests` when a code for
+    /// the same subject and data was issued within the cooldown.
+    #[tracing::instrument(name = "Issue OTP", skip_all, fields(purpose = ?purpose))]
+    pub async fn issue(
+        &self,
+        purpose: OtpPurpose,
+        subject: &str,
+        data: &str,
+    ) -> Result<String, AppError> {
+        let code = purpose.generate_code();
+        let code_hash = hash(&code);
+
+        let record_key = if purpose.is_token() {
+            Self::record_key(purpose, &code_hash)
+        } else {
+            Self::record_key(purpose, subject)
+        };
+        let cooldown_key = format!(
+            "otp:{}:{}:cooldown:{}",
+            purpose.as_str(),
+            subject,
+            hash(data)
+        );
+
+        let mut conn = self
+            .client
+            .get_multiplexed_tokio_connection()
+            .await
+            .context("failed to connect to redis")?;
+
+        let mut invocation = ISSUE_SCRIPT.key(record_key);
+        invocation.key(cooldown_key);
+        if purpose.is_token() {
+            invocation.key(format!(
+                "otp:{}:{}:current:{}",
+                purpose.as_str(),
+                subject,
+                hash(data)
+            ));
+        }
+
+        let retry_after: u64 = invocation
+            .arg(code_hash)
+            .arg(data)
+            .arg(purpose.ttl().whole_seconds())
+            .arg(self.config.resend_cooldown_secs)
+            .invoke_async(&mut conn)
+            .await
+            .context("failed to store otp")?;
+
+        if retry_after > 0 {
+            return Err(AppError::TooManyRequests { retry_after });
+        }
+
+        Ok(code)
+    }
+
+    /// Data of the code sent for `subject`, the code can only be used once
+    #[tracing::instrument(name = "Consume OTP", skip_all, fields(purpose = ?purpose))]
+    pub async fn consume(
+        &self,
+        purpose: OtpPurpose,
+        subject: &str,
+        code: &str,
+    ) -> Result<Option<String>, AppError> {
+        debug_assert!(!purpose.is_token(), "use consume_token for {:?}", purpose);
+
+        self.consume_record(Self::record_key(purpose, subject), code)
+            .await
+    }
+
+    /// Data of the token, for purposes where the token is looked up on its own
+    #[tracing::instrument(name = "Consume OTP token", skip_all, fields(purpose = ?purpose))]
+    pub async fn consume_token(
+        &self,
+        purpose: OtpPurpose,
+        token: &str,
+    ) -> Result<Option<String>, AppError> {
+        debug_assert!(purpose.is_token(), "use consume for {:?}", purpose);
+
+        self.consume_record(Self::record_key(purpose, &hash(token)), token)
+            .await
+    }
+
+    async fn consume_record(
+        &self,
+        record_key: String,
+        code: &str,
+    ) -> Result<Option<String>, AppError> {
+        let mut conn = self
+            .client
+            .get_multiplexed_tokio_connection()
+            .await
+            .context("failed to connect to redis")?;
+
+        let data: Option<String> = CONSUME_SCRIPT
+            .key(record_key)
+            .arg(hash(code))
+            .arg(self.config.max_attempts)
+            .invoke_async(&mut conn)
+            .await
+            .context("failed to consume otp")?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use crate::app::utils::types::Locale;
+
+use super::transport::{SmsMessage, SmsTransport};
+
+/// Builds the text messages in the user's locale and sends them.
+///
+/// Texts are kept short, a single SMS segment where possible.
+#[derive(Clone)]
+pub struct SmsClient {
+    transport: Arc<dyn SmsTransport>,
+}
+
+impl SmsClient {
+    pub fn new(transport: Arc<dyn SmsTransport>) -> Self {
+        Self { transport }
+    }
+
+    #[tracing::instrument(name = "Send phone verification SMS", skip_all, fields(phone = ?phone))]
+    pub async fn send_verification_code(
+        &self,
+        phone: &str,
+        code: &str,
+        expire_in_minutes: i64,
+        locale: Locale,
+    ) -> anyhow::Result<()> {
+        let text = match locale {
+            Locale::En => format!(
+                "Your verification code is {}. It expires in {} minutes.",
+                code, expire_in_minutes
+            ),
+            Locale::Mn => format!(
+                "Таны баталгаажуулах код {}. {} минутын дараа хүчингүй болно.",
+                code, expire_in_minutes
+            ),
+        };
+
+        self.send(phone, text).await
+    }
+
+    #[tracing::instrument(name = "Send phone login SMS", skip_all, fields(phone = ?phone))]
+    pub async fn send_login_code(
+        &self,
+        phone: &str,
+        code: &str,
+        expire_in_minutes: i64,
+        locale: Locale,
+    ) -> anyhow::Result<()> {
+        let text = match locale {
+            Locale::En => format!(
+                "Your login code is {}. It expires in {} minutes. Don't share it with anyone.",
+                code, expire_in_minutes
+            ),
+            Locale::Mn => format!(
+                "Таны нэвтрэх код {}. {} минутын дараа хүчингүй болно. Хэнд ч бүү хэлээрэй.",
+                code, expire_in_minutes
+            ),
+        };
+
+        self.send(phone, text).await
+    }
+
+    async fn send(&self, phone: &str, text: String) -> anyhow::Result<()> {
+        let message = SmsMessage {
+            to: phone.to_string(),
+            text,
+        };
+
+        self.transport.send(&message).await
+    }
+}
@@ -0,0 +1,20 @@
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+pub mod log;
+pub mod outbox;
+
+/// A single outgoing text message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmsMessage {
+    /// Phone number in E.164 format
+    pub to: String,
+    pub text: String,
+}
+
+/// Delivers text messages built by `SmsClient`.
+///
+/// Picked at startup from `SmsConfig::transport`.
+pub trait SmsTransport: Send + Sync {
+    fn send<'a>(&'a self, message: &'a SmsMessage) -> BoxFuture<'a, anyhow::Result<()>>;
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use futures::future::BoxFuture;
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::{SmsMessage, SmsTransport};
+
+/// Writes every message to `dir` as a JSON file.
+pub struct FileSmsOutbox {
+    dir: PathBuf,
+}
+
+impl FileSmsOutbox {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl SmsTransport for FileSmsOutbox {
+    fn send<'a>(&'a self, message: &'a SmsMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let content = serde_json::to_vec_pretty(message)?;
+
+            // Sortable by time of sending
+            let path = self.dir.join(format!(
+                "{}-sms-{}.json",
+                OffsetDateTime::now_utc().unix_timestamp_nanos(),
+                Uuid::new_v4()
+            ));
+
+            tokio::fs::create_dir_all(&self.dir)
+                .await
+                .context("failed to create sms outbox dir")?;
+            tokio::fs::write(&path, content)
+                .await
+                .with_context(|| format!("failed to write sms to {:?}", path))?;
+
+            tracing::info!("SMS to {} written to {:?}", message.to, path);
+
+            Ok(())
+        })
+    }
+}
+
+/// Keeps sent messages in memory so tests can assert on them.
+#[derive(Default)]
+pub struct MemorySmsOutbox {
+    messages: Mutex<Vec<SmsMessage>>,
+}
+
+impl MemorySmsOutbox {
+    /// Every message sent so far, oldest first
+    pub async fn messages(&self) -> Vec<SmsMessage> {
+        self.messages.lock().await.clone()
+    }
+
+    /// Most recent message sent to `to`
+    pub async fn last_to(&self, to: &str) -> Option<SmsMessage> {
+        self.messages
+            .lock()
+            .await
+            .iter()
+            .rev()
+            .find(|message| message.to == to)
+            .cloned()
+    }
+}
+
+impl SmsTransport for MemorySmsOutbox {
+    fn send<'a>(&'a self, message: &'a SmsMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.messages.lock().await.push(message.clone());
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::app::utils::avatar_generator::generate_avatar;
+
+use super::{path::S3Path, Storage};
+
+const AVATAR_FILE_NAME: &str = "identicon.png";
+
+/// Images linked from DiceBear before avatars were generated locally
+const DICEBEAR_URL_PREFIX: &str = "https://api.dicebear.com/";
+const MIGRATION_BATCH_SIZE: i64 = 50;
+
+/// Generates the avatar of the user and uploads it, returns its key and size.
+///
+/// Storage can be slow, so this runs outside of any transaction.
+async fn upload_avatar(user_id: &Uuid, storage: &dyn Storage) -> anyhow::Result<(String, i64)> {
+    let bytes = generate_avatar(&user_id.to_string())?;
+    let key = S3Path::Profile.key(user_id, AVATAR_FILE_NAME);
+    let size = bytes.len() as i64;
+
+    storage.put(&key, "image/png", bytes).await?;
+
+    Ok((key, size))
+}
+
+/// Stores the uploaded avatar as a confirmed profile upload.
+///
+/// The image worker then adds the variants like for any uploaded image.
+async fn insert_avatar_upload(
+    user_id: &Uuid,
+    key: &str,
+    size: i64,
+    tx: &mut Transaction<'static, Postgres>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            insert into upload (user_id, path, key, mime_type, size, status, confirmed_at)
+            values ($1, 'profile', $2, 'image/png', $3, 'confirmed', now())
+            on conflict (key) do update
+            set mime_type = excluded.mime_type,
+                size = excluded.size,
+                status = 'confirmed',
+                confirmed_at = now(),
+                processed_at = null,
+                processing_error = null,
+                processing_started_at = null,
+                processing_attempts = 0,
+                variants_size = 0,
+                multipart_id = null,
+                multipart_started_at = null
+        "#,
+        user_id,
+        key,
+        size
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Gives the user a generated avatar when it has no image yet.
+///
+/// Called once the user is committed, a failed avatar is only logged
+/// and the user keeps no image.
+pub async fn set_default_avatar(user_id: &Uuid, storage: &dyn Storage, db_pool: &PgPool) {
+    if let Err(e) = try_set_default_avatar(user_id, storage, db_pool).await {
+        tracing::warn!("failed to set default avatar of {}: {:?}", user_id, e);
+    }
+}
+
+async fn try_set_default_avatar(
+    user_id: &Uuid,
+    storage: &dyn Storage,
+    db_pool: &PgPool,
+) -> anyhow::Result<()> {
+    let image = sqlx::query_scalar!(r#"select image from "user" where user_id = $1"#, user_id)
+        .fetch_one(db_pool)
+        .await?;
+
+    if image.is_some() {
+        return Ok(());
+    }
+
+    let (key, size) = upload_avatar(user_id, storage).await?;
+
+    let mut tx = db_pool.begin().await?;
+    insert_avatar_upload(user_id, &key, size, &mut tx).await?;
+
+    // An image set meanwhile wins over the generated one
+    sqlx::query!(
+        r#"update "user" set image = $1 where user_id = $2 and image is null"#,
+        key,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Replaces the DiceBear URLs of existing users with generated avatars.
+#[derive(Clone)]
+pub struct AvatarMigrator {
+    db_pool: Arc<PgPool>,
+    storage: Arc<dyn Storage>,
+}
+
+impl AvatarMigrator {
+    pub fn new(db_pool: Arc<PgPool>, storage: Arc<dyn Storage>) -> Self {
+        Self { db_pool, storage }
+    }
+
+    /// Migrates batch after batch, returns once no user links DiceBear anymore
+    pub async fn run(self) {
+        loop {
+            match self.migrate_batch().await {
+                Ok(count) if count as i64 >= MIGRATION_BATCH_SIZE => continue,
+                Ok(_) => break,
+                Err(e) => {
+                    tracing::error!("failed to migrate avatars: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Replaces the avatars of a batch of users, returns how many were replaced.
+    #[tracing::instrument(name = "Migrate DiceBear avatars", skip_all)]
+    pub async fn migrate_batch(&self) -> anyhow::Result<usize> {
+        let user_ids = sqlx::query_scalar!(
+            r#"
+                select user_id
+                from "user"
+                where starts_with(image, $1)
+                limit $2
+            "#,
+            DICEBEAR_URL_PREFIX,
+            MIGRATION_BATCH_SIZE
+        )
+        .fetch_all(&*self.db_pool)
+        .await?;
+
+        for user_id in &user_ids {
+            let (key, size) = upload_avatar(user_id, &*self.storage).await?;
+
+            let mut tx = self.db_pool.begin().await?;
+            insert_avatar_upload(user_id, &key, size, &mut tx).await?;
+
+            // Unless the user picked an image meanwhile
+            sqlx::query!(
+                r#"update "user" set image = $1 where user_id = $2 and starts_with(image, $3)"#,
+                key,
+                user_id,
+                DICEBEAR_URL_PREFIX
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+        }
+
+        Ok(user_ids.len())
+    }
+}
@@ -0,0 +1,378 @@
+use std::{io::Cursor, sync::Arc};
+
+use anyhow::Context;
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::config::ImageProcessingConfig;
+
+use super::{path::S3Path, Storage};
+
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageVariant {
+    Thumbnail,
+    Medium,
+}
+
+impl ImageVariant {
+    pub const ALL: [ImageVariant; 2] = [ImageVariant::Thumbnail, ImageVariant::Medium];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImageVariant::Thumbnail => "thumbnail",
+            ImageVariant::Medium => "medium",
+        }
+    }
+
+    /// Longest side in pixels, smaller images are not upscaled
+    fn max_side(&self) -> u32 {
+        match self {
+            ImageVariant::Thumbnail => 128,
+            ImageVariant::Medium => 512,
+        }
+    }
+
+    /// Stored next to the original, `profile/{id}/avatar.png` has
+    /// `profile/{id}/avatar.thumbnail.webp`
+    pub fn key(&self, key: &str) -> String {
+        let (dir, file_name) = key.rsplit_once('/').unwrap_or(("", key));
+        let stem = file_name
+            .rsplit_once('.')
+            .map(|(stem, _)| stem)
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or(file_name);
+
+        if dir.is_empty() {
+            format!("{}.{}.webp", stem, self.as_str())
+        } else {
+            format!("{}/{}.{}.webp", dir, stem, self.as_str())
+        }
+    }
+}
+
+/// Public URLs of a processed image
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageUrls {
+    pub original: String,
+    pub thumbnail: String,
+    pub medium: String,
+}
+
+impl ImageUrls {
+    pub fn new(storage: &dyn Storage, key: &str) -> Self {
+        Self {
+            original: storage.public_url(key),
+            thumbnail: storage.public_url(&ImageVariant::Thumbnail.key(key)),
+            medium: storage.public_url(&ImageVariant::Medium.key(key)),
+        }
+    }
+}
+
+/// Variants that were never generated are skipped
+pub async fn delete_variants(storage: &dyn Storage, key: &str) -> anyhow::Result<()> {
+    for variant in ImageVariant::ALL {
+        storage.delete(&variant.key(key)).await?;
+    }
+
+    Ok(())
+}
+
+pub struct ProcessedImage {
+    /// The original re-encoded in its format, without EXIF and other metadata
+    pub original: Vec<u8>,
+    pub variants: Vec<(ImageVariant, Vec<u8>)>,
+}
+
+/// Decodes the image, applies its EXIF orientation and encodes it again.
+///
+/// Encoders never write the metadata back, so location and camera details are dropped.
+/// CPU heavy, run it with `spawn_blocking`.
+pub fn process_image(bytes: &[u8], format: ImageFormat) -> anyhow::Result<ProcessedImage> {
+    let mut decoder = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_decoder()
+        .context("failed to read image")?;
+    let orientation = decoder
+        .orientation()
+        .context("failed to read orientation")?;
+
+    let mut image = DynamicImage::from_decoder(decoder).context("failed to decode image")?;
+    image.apply_orientation(orientation);
+
+    let original = encode(&image, format)?;
+
+    let variants = ImageVariant::ALL
+        .into_iter()
+        .map(|variant| {
+            let side = variant.max_side();
+            let resized = if image.width() > side || image.height() > side {
+                image.resize(side, side, FilterType::Lanczos3)
+            } else {
+                image.clone()
+            };
+
+            Ok((variant, encode(&resized, ImageFormat::WebP)?))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(ProcessedImage { original, variants })
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    match format {
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)),
+        // Only lossless WebP can be encoded
+        ImageFormat::WebP => image
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut bytes)),
+        format => image.write_to(&mut Cursor::new(&mut bytes), format),
+    }
+    .context("failed to encode image")?;
+
+    Ok(bytes)
+}
+
+/// Processes the confirmed image uploads, see `process_image`.
+#[derive(Clone)]
+pub struct ImageWorker {
+    db_pool: Arc<PgPool>,
+    storage: Arc<dyn Storage>,
+    config: ImageProcessingConfig,
+}
+
+impl ImageWorker {
+    pub fn new(
+        db_pool: Arc<PgPool>,
+        storage: Arc<dyn Storage>,
+        config: ImageProcessingConfig,
+    ) -> Self {
+        Self {
+            db_pool,
+            storage,
+            config,
+        }
+    }
+
+    /// Polls the uploads until the task is dropped
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.poll_interval_secs,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            // Keep going while full batches come back
+            loop {
+                match self.process_batch().await {
+                    Ok(count) if count as i64 >= self.config.batch_size => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("failed to process images: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Processes the confirmed uploads that have no variants yet, returns how many were attempted.
+    ///
+    /// The rows are claimed with `skip locked` in a statement of their own and stay claimed
+    /// for `lease_secs`, nothing is held while reading and decoding the images.
+    /// Images that can't be decoded are marked with the error and not retried,
+    /// storage failures are retried once the lease runs out, until `max_attempts`.
+    #[tracing::instrument(name = "Process images", skip_all)]
+    pub async fn process_batch(&self) -> anyhow::Result<usize> {
+        let paths: Vec<S3Path> = S3Path::ALL
+            .into_iter()
+            .filter(S3Path::has_variants)
+            .collect();
+
+        let rows = sqlx::query!(
+            r#"
+                with due as (
+                    select upload_id
+                    from upload
+                    where status = 'confirmed' and processed_at is null and path = any($1)
+                        and (
+                            processing_started_at is null
+                            or processing_started_at <= now() - make_interval(secs => $3)
+                        )
+                    order by confirmed_at
+                    limit $2
+                    for update skip locked
+                )
+                update upload u
+                set processing_started_at = now(),
+                    processing_attempts = u.processing_attempts + 1
+                from due
+                where u.upload_id = due.upload_id
+                returning u.upload_id, u.key, u.mime_type, u.processing_attempts,
+                    u.processing_started_at as "processing_started_at!"
+            "#,
+            &paths as &[S3Path],
+            self.config.batch_size,
+            self.config.lease_secs as f64
+        )
+        .fetch_all(&*self.db_pool)
+        .await?;
+
+        let count = rows.len();
+
+        for row in rows {
+            let attempts = row.processing_attempts;
+            let started_at = row.processing_started_at;
+
+            // The worker of the last attempt didn't finish before its lease ran out
+            if attempts > self.config.max_attempts {
+                mark_failed(
+                    &row.upload_id,
+                    started_at,
+                    "lease ran out on the last attempt",
+                    &self.db_pool,
+                )
+                .await?;
+                continue;
+            }
+
+            let error = match self
+                .process_upload(&row.upload_id, &row.key, &row.mime_type, started_at)
+                .await
+            {
+                Ok(Ok(())) => continue,
+                Ok(Err(error)) => error,
+                Err(e) if attempts >= self.config.max_attempts => format!("{:#}", e),
+                Err(e) => {
+                    tracing::warn!(
+                        "failed to store image variants of {} on attempt {}: {:?}",
+                        row.upload_id,
+                        attempts,
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            mark_failed(&row.upload_id, started_at, &error, &self.db_pool).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Stores the variants and the original without its metadata, unless the upload is
+    /// no longer claimed by the attempt started at `started_at`, e.g. the key was uploaded
+    /// again meanwhile. The row stays locked while storing, so a new upload of the key
+    /// waits until these bytes are written instead of being overwritten by them.
+    ///
+    /// Outer error is for storage failures, the inner one for images that can't be processed.
+    async fn process_upload(
+        &self,
+        upload_id: &Uuid,
+        key: &str,
+        mime_type: &str,
+        started_at: OffsetDateTime,
+    ) -> anyhow::Result<Result<(), String>> {
+        let Some(format) = ImageFormat::from_mime_type(mime_type) else {
+            return Ok(Err(format!("unsupported type {}", mime_type)));
+        };
+
+        let Some(bytes) = self.storage.get(key).await? else {
+            return Ok(Err("object missing".to_string()));
+        };
+
+        let processed =
+            match tokio::task::spawn_blocking(move || process_image(&bytes, format)).await? {
+                Ok(processed) => processed,
+                Err(e) => return Ok(Err(format!("{:#}", e))),
+            };
+
+        let variants_size: i64 = processed
+            .variants
+            .iter()
+            .map(|(_, bytes)| bytes.len() as i64)
+            .sum();
+        let size = processed.original.len() as i64;
+
+        let mut tx = self.db_pool.begin().await?;
+
+        let claimed = sqlx::query!(
+            r#"
+                update upload
+                set processed_at = now(),
+                    processing_error = null,
+                    size = $1,
+                    variants_size = $2
+                where upload_id = $3 and processing_started_at = $4
+                    and status = 'confirmed' and processed_at is null
+            "#,
+            size,
+            variants_size,
+            upload_id,
+            started_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+
+        if !claimed {
+            tracing::info!("image upload {} is no longer claimed, skipped", upload_id);
+            return Ok(Ok(()));
+        }
+
+        for (variant, bytes) in processed.variants {
+            self.storage
+                .put(&variant.key(key), ImageFormat::WebP.to_mime_type(), bytes)
+                .await?;
+        }
+
+        self.storage
+            .put(key, format.to_mime_type(), processed.original)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Ok(()))
+    }
+}
+
+/// Skipped when the upload is no longer claimed by the attempt started at `started_at`
+async fn mark_failed(
+    upload_id: &Uuid,
+    started_at: OffsetDateTime,
+    error: &str,
+    db_pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    tracing::warn!("image upload {} can't be processed: {}", upload_id, error);
+
+    sqlx::query!(
+        r#"
+            update upload
+            set processed_at = now(), processing_error = $1
+            where upload_id = $2 and processing_started_at = $3
+                and status = 'confirmed' and processed_at is null
+        "#,
+        error,
+        upload_id,
+        started_at
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,567 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context};
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use url::Url;
+use uuid::Uuid;
+
+use super::{
+    CompletedPart, ObjectMeta, PresignedUpload, Storage, StoredObject, DOWNLOAD_EXPIRES_IN,
+    PART_UPLOAD_EXPIRES_IN, UPLOAD_EXPIRES_IN,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Stores files on disk and serves them from `/storage/local`, for dev and CI.
+///
+/// Uploads go through the api with URLs signed like S3 presigned requests,
+/// so clients use the same flow against either storage.
+pub struct LocalStorage {
+    root: PathBuf,
+    base_url: String,
+    secret: HmacSha256,
+}
+
+/// Query of a signed upload URL
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalUploadQuery {
+    pub content_type: String,
+    pub content_length: i64,
+    /// Unix timestamp
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Query of a signed download URL, empty for public objects
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalDownloadQuery {
+    /// Unix timestamp
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Query of a signed part upload URL
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalPartQuery {
+    pub content_length: i64,
+    /// Unix timestamp
+    pub expires: i64,
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMeta {
+    content_type: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MultipartMeta {
+    key: String,
+    content_type: String,
+}
+
+/// Hash of the part content, quoted like S3 ETags
+fn part_etag(bytes: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Sha256::digest(bytes)))
+}
+
+impl LocalStorage {
+    /// `base_url` is where the api is reachable, signed URLs point back to it
+    pub fn new(dir: &str, base_url: &str, secret: &SecretString) -> Self {
+        Self {
+            root: PathBuf::from(dir),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            secret: HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+                .expect("hmac accepts keys of any size"),
+        }
+    }
+
+    fn object_url(&self, key: &str) -> anyhow::Result<Url> {
+        let mut url = Url::parse(&self.base_url).context("invalid local storage url")?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("local storage url can't have a path"))?
+            .extend(["storage", "local"])
+            .extend(key.split('/'));
+
+        Ok(url)
+    }
+
+    /// Keys are relative paths, anything that could leave the root is refused
+    fn object_path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let path = Path::new(key);
+        let is_safe = !key.is_empty()
+            && path.components().all(|c| match c {
+                Component::Normal(segment) => !segment.to_string_lossy().starts_with('.'),
+                _ => false,
+            });
+
+        if !is_safe {
+            bail!("invalid storage key: {}", key);
+        }
+
+        Ok(self.root.join("objects").join(path))
+    }
+
+    fn meta_path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        self.object_path(key)?;
+
+        Ok(self.root.join("meta").join(format!("{}.json", key)))
+    }
+
+    /// Parts are kept under `multipart/{id}` until completed
+    fn multipart_dir(&self, multipart_id: &str) -> anyhow::Result<PathBuf> {
+        let id = Uuid::parse_str(multipart_id).context("invalid multipart id")?;
+
+        Ok(self.root.join("multipart").join(id.to_string()))
+    }
+
+    fn part_signature(
+        &self,
+        multipart_id: &str,
+        part_number: i32,
+        content_length: i64,
+        expires: i64,
+    ) -> HmacSha256 {
+        let mut mac = self.secret.clone();
+        mac.update(
+            format!(
+                "part\n{}\n{}\n{}\n{}",
+                multipart_id, part_number, content_length, expires
+            )
+            .as_bytes(),
+        );
+
+        mac
+    }
+
+    /// Checks the signature and expiry of a part upload URL
+    pub fn verify_part(
+        &self,
+        multipart_id: &str,
+        part_number: i32,
+        query: &LocalPartQuery,
+    ) -> bool {
+        if query.expires < OffsetDateTime::now_utc().unix_timestamp() {
+            return false;
+        }
+
+        let Ok(signature) = hex::decode(&query.signature) else {
+            return false;
+        };
+
+        self.part_signature(
+            multipart_id,
+            part_number,
+            query.content_length,
+            query.expires,
+        )
+        .verify_slice(&signature)
+        .is_ok()
+    }
+
+    /// Stores the part, returns its ETag or `None` when the multipart upload is gone
+    pub async fn write_part(
+        &self,
+        multipart_id: &str,
+        part_number: i32,
+        bytes: &[u8],
+    ) -> anyhow::Result<Option<String>> {
+        let dir = self.multipart_dir(multipart_id)?;
+        if !tokio::fs::try_exists(dir.join("meta.json")).await? {
+            return Ok(None);
+        }
+
+        tokio::fs::write(dir.join(part_number.to_string()), bytes)
+            .await
+            .context("failed to write part")?;
+
+        Ok(Some(part_etag(bytes)))
+    }
+
+    fn signature(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: i64,
+        expires: i64,
+    ) -> HmacSha256 {
+        let mut mac = self.secret.clone();
+        mac.update(
+            format!("{}\n{}\n{}\n{}", key, content_type, content_length, expires).as_bytes(),
+        );
+
+        mac
+    }
+
+    fn download_signature(&self, key: &str, expires: i64) -> HmacSha256 {
+        let mut mac = self.secret.clone();
+        mac.update(format!("download\n{}\n{}", key, expires).as_bytes());
+
+        mac
+    }
+
+    /// Checks the signature and expiry of a download URL
+    pub fn verify_download(&self, key: &str, query: &LocalDownloadQuery) -> bool {
+        if query.expires < OffsetDateTime::now_utc().unix_timestamp() {
+            return false;
+        }
+
+        let Ok(signature) = hex::decode(&query.signature) else {
+            return false;
+        };
+
+        self.download_signature(key, query.expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    /// Checks the signature and expiry of an upload URL
+    pub fn verify_upload(&self, key: &str, query: &LocalUploadQuery) -> bool {
+        if query.expires < OffsetDateTime::now_utc().unix_timestamp() {
+            return false;
+        }
+
+        let Ok(signature) = hex::decode(&query.signature) else {
+            return false;
+        };
+
+        self.signature(
+            key,
+            &query.content_type,
+            query.content_length,
+            query.expires,
+        )
+        .verify_slice(&signature)
+        .is_ok()
+    }
+
+    pub async fn write(&self, key: &str, content_type: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        let object_path = self.object_path(key)?;
+        let meta_path = self.meta_path(key)?;
+
+        for path in [&object_path, &meta_path] {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .context("failed to create storage dir")?;
+            }
+        }
+
+        let meta = serde_json::to_vec(&StoredMeta {
+            content_type: content_type.to_string(),
+        })?;
+
+        tokio::fs::write(&meta_path, meta)
+            .await
+            .context("failed to write object meta")?;
+        tokio::fs::write(&object_path, bytes)
+            .await
+            .context("failed to write object")?;
+
+        Ok(())
+    }
+
+    /// Content and metadata of the object, `None` when missing
+    pub async fn read(&self, key: &str) -> anyhow::Result<Option<(Vec<u8>, ObjectMeta)>> {
+        let Some(meta) = self.read_meta(key).await? else {
+            return Ok(None);
+        };
+
+        match tokio::fs::read(self.object_path(key)?).await {
+            Ok(bytes) => Ok(Some((bytes, meta))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("failed to read object"),
+        }
+    }
+
+    /// Walks `objects/`, keys are the paths relative to it
+    async fn list_objects(&self) -> anyhow::Result<Vec<StoredObject>> {
+        let objects_dir = self.root.join("objects");
+        let mut objects = Vec::new();
+        let mut dirs = vec![objects_dir.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("failed to read storage dir"),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                    continue;
+                }
+
+                let path = entry.path();
+                let key = path
+                    .strip_prefix(&objects_dir)?
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                objects.push(StoredObject {
+                    key,
+                    size: metadata.len() as i64,
+                    last_modified: metadata.modified()?.into(),
+                });
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn read_meta(&self, key: &str) -> anyhow::Result<Option<ObjectMeta>> {
+        // Nothing can be stored under an invalid key
+        let Ok(object_path) = self.object_path(key) else {
+            return Ok(None);
+        };
+
+        let size = match tokio::fs::metadata(object_path).await {
+            Ok(metadata) => metadata.len() as i64,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("failed to read object metadata"),
+        };
+
+        let content_type = match tokio::fs::read(self.meta_path(key)?).await {
+            Ok(raw) => serde_json::from_slice::<StoredMeta>(&raw)
+                .map(|meta| meta.content_type)
+                .ok(),
+            Err(_) => None,
+        };
+
+        Ok(Some(ObjectMeta { size, content_type }))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn presign_upload<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+        content_length: i64,
+    ) -> BoxFuture<'a, anyhow::Result<PresignedUpload>> {
+        Box::pin(async move {
+            self.object_path(key)?;
+
+            let expires =
+                OffsetDateTime::now_utc().unix_timestamp() + UPLOAD_EXPIRES_IN.as_secs() as i64;
+            let signature = self
+                .signature(key, content_type, content_length, expires)
+                .finalize()
+                .into_bytes();
+
+            let query = LocalUploadQuery {
+                content_type: content_type.to_string(),
+                content_length,
+                expires,
+                signature: hex::encode(signature),
+            };
+
+            let mut url = self.object_url(key)?;
+            url.query_pairs_mut()
+                .append_pair("content_type", &query.content_type)
+                .append_pair("content_length", &query.content_length.to_string())
+                .append_pair("expires", &query.expires.to_string())
+                .append_pair("signature", &query.signature);
+
+            Ok(PresignedUpload {
+                uri: url.to_string(),
+                method: "PUT".to_string(),
+                headers: [
+                    ("content-type".to_string(), content_type.to_string()),
+                    ("content-length".to_string(), content_length.to_string()),
+                ]
+                .into(),
+            })
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            for path in [self.object_path(key)?, self.meta_path(key)?] {
+                match tokio::fs::remove_file(path).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e).context("failed to delete given path"),
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn create_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            self.object_path(key)?;
+
+            let multipart_id = Uuid::new_v4().to_string();
+            let dir = self.multipart_dir(&multipart_id)?;
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .context("failed to create multipart dir")?;
+
+            let meta = serde_json::to_vec(&MultipartMeta {
+                key: key.to_string(),
+                content_type: content_type.to_string(),
+            })?;
+            tokio::fs::write(dir.join("meta.json"), meta)
+                .await
+                .context("failed to write multipart meta")?;
+
+            Ok(multipart_id)
+        })
+    }
+
+    fn presign_part<'a>(
+        &'a self,
+        _key: &'a str,
+        multipart_id: &'a str,
+        part_number: i32,
+        content_length: i64,
+    ) -> BoxFuture<'a, anyhow::Result<PresignedUpload>> {
+        Box::pin(async move {
+            let expires = OffsetDateTime::now_utc().unix_timestamp()
+                + PART_UPLOAD_EXPIRES_IN.as_secs() as i64;
+            let signature = self
+                .part_signature(multipart_id, part_number, content_length, expires)
+                .finalize()
+                .into_bytes();
+
+            let mut url = Url::parse(&self.base_url).context("invalid local storage url")?;
+            url.path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("local storage url can't have a path"))?
+                .extend([
+                    "storage",
+                    "multipart",
+                    multipart_id,
+                    &part_number.to_string(),
+                ]);
+            url.query_pairs_mut()
+                .append_pair("content_length", &content_length.to_string())
+                .append_pair("expires", &expires.to_string())
+                .append_pair("signature", &hex::encode(signature));
+
+            Ok(PresignedUpload {
+                uri: url.to_string(),
+                method: "PUT".to_string(),
+                headers: [("content-length".to_string(), content_length.to_string())].into(),
+            })
+        })
+    }
+
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+        parts: &'a [CompletedPart],
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let dir = self.multipart_dir(multipart_id)?;
+            let raw = tokio::fs::read(dir.join("meta.json"))
+                .await
+                .context("multipart upload not found")?;
+            let meta = serde_json::from_slice::<MultipartMeta>(&raw)?;
+
+            if meta.key != key {
+                bail!("multipart upload belongs to another key");
+            }
+
+            let mut parts = parts.to_vec();
+            parts.sort_by_key(|part| part.part_number);
+
+            let mut bytes = Vec::new();
+            for part in parts {
+                let content = tokio::fs::read(dir.join(part.part_number.to_string()))
+                    .await
+                    .with_context(|| format!("part {} not uploaded", part.part_number))?;
+
+                if part_etag(&content).trim_matches('"') != part.etag.trim_matches('"') {
+                    bail!("etag of part {} does not match", part.part_number);
+                }
+
+                bytes.extend_from_slice(&content);
+            }
+
+            self.write(key, &meta.content_type, &bytes).await?;
+
+            tokio::fs::remove_dir_all(dir)
+                .await
+                .context("failed to remove parts")?;
+
+            Ok(())
+        })
+    }
+
+    fn abort_multipart<'a>(
+        &'a self,
+        _key: &'a str,
+        multipart_id: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_dir_all(self.multipart_dir(multipart_id)?).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e).context("failed to remove parts"),
+            }
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(async move { Ok(self.read(key).await?.map(|(bytes, _)| bytes)) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move { self.write(key, content_type, &bytes).await })
+    }
+
+    fn head<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<ObjectMeta>>> {
+        Box::pin(self.read_meta(key))
+    }
+
+    fn list<'a>(&'a self) -> BoxFuture<'a, anyhow::Result<Vec<StoredObject>>> {
+        Box::pin(self.list_objects())
+    }
+
+    fn presign_download<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let expires =
+                OffsetDateTime::now_utc().unix_timestamp() + DOWNLOAD_EXPIRES_IN.as_secs() as i64;
+            let signature = self
+                .download_signature(key, expires)
+                .finalize()
+                .into_bytes();
+
+            let mut url = self.object_url(key)?;
+            url.query_pairs_mut()
+                .append_pair("expires", &expires.to_string())
+                .append_pair("signature", &hex::encode(signature));
+
+            Ok(url.to_string())
+        })
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        self.object_url(key)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| format!("{}/storage/local/{}", self.base_url, key))
+    }
+}
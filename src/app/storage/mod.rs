@@ -1,2 +1,131 @@
-pub mod client;
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+pub mod avatar;
+pub mod image;
+pub mod local;
+pub mod multipart;
+pub mod orphan;
 pub mod path;
+pub mod quota;
+pub mod s3;
+pub mod upload;
+
+pub const UPLOAD_EXPIRES_IN: std::time::Duration = std::time::Duration::from_secs(60 * 5);
+/// Parts of a large file can take a while to upload
+pub const PART_UPLOAD_EXPIRES_IN: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+pub const DOWNLOAD_EXPIRES_IN: std::time::Duration = std::time::Duration::from_secs(60 * 5);
+
+/// Keys under it are never served publicly, only through `Storage::presign_download`
+pub const PRIVATE_PREFIX: &str = "private/";
+
+pub fn is_private_key(key: &str) -> bool {
+    key.starts_with(PRIVATE_PREFIX)
+}
+
+/// Request the client sends to upload the file itself.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PresignedUpload {
+    pub uri: String,
+    pub method: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// Uploaded part of a multipart upload, `etag` as returned by the part upload
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CompletedPart {
+    pub part_number: i32,
+    pub etag: String,
+}
+
+/// What the storage knows about a stored object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub size: i64,
+    pub content_type: Option<String>,
+}
+
+/// Object found when listing the storage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: i64,
+    pub last_modified: OffsetDateTime,
+}
+
+/// Stores uploaded files under a key, e.g. `profile/{user_id}/{file_name}`.
+///
+/// Picked at startup from `StorageConfig`.
+pub trait Storage: Send + Sync {
+    /// Presigned request to upload exactly `content_length` bytes of `content_type`
+    fn presign_upload<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+        content_length: i64,
+    ) -> BoxFuture<'a, anyhow::Result<PresignedUpload>>;
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Starts uploading the object in parts, returns the id of the multipart upload
+    fn create_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    /// Presigned request to upload exactly `content_length` bytes as the part
+    fn presign_part<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+        part_number: i32,
+        content_length: i64,
+    ) -> BoxFuture<'a, anyhow::Result<PresignedUpload>>;
+
+    /// Joins the parts into the object, in the order of `part_number`
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+        parts: &'a [CompletedPart],
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Drops the uploaded parts, done when the multipart upload is already gone
+    fn abort_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Content of the object, `None` when nothing was uploaded to the key
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>>;
+
+    /// Stores a file the api produced itself, e.g. image variants
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Metadata of the object, `None` when nothing was uploaded to the key
+    fn head<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<ObjectMeta>>>;
+
+    /// Every stored object, parts of unfinished multipart uploads excluded
+    fn list<'a>(&'a self) -> BoxFuture<'a, anyhow::Result<Vec<StoredObject>>>;
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(async move { Ok(self.head(key).await?.is_some()) })
+    }
+
+    /// Short-lived URL to download the object, valid for `DOWNLOAD_EXPIRES_IN`
+    fn presign_download<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    /// Where the object is publicly served from, private keys are not reachable through it
+    fn public_url(&self, key: &str) -> String;
+}
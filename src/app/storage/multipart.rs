@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use sqlx::PgPool;
+
+use crate::config::MultipartConfig;
+
+use super::Storage;
+
+/// Number of parts and size of the last one, every other part is `part_size`
+pub fn split_parts(file_size: i64, part_size: i64) -> (i32, i64) {
+    let count = ((file_size + part_size - 1) / part_size).max(1);
+    let last = file_size - (count - 1) * part_size;
+
+    (count as i32, last)
+}
+
+/// Aborts the multipart uploads that were never completed.
+#[derive(Clone)]
+pub struct MultipartCleaner {
+    db_pool: Arc<PgPool>,
+    storage: Arc<dyn Storage>,
+    config: MultipartConfig,
+}
+
+impl MultipartCleaner {
+    pub fn new(db_pool: Arc<PgPool>, storage: Arc<dyn Storage>, config: MultipartConfig) -> Self {
+        Self {
+            db_pool,
+            storage,
+            config,
+        }
+    }
+
+    /// Aborts the abandoned uploads every `poll_interval_secs`, never returns
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.poll_interval_secs,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            // Keep going while full batches come back
+            loop {
+                match self.abort_abandoned().await {
+                    Ok(count) if count as i64 >= self.config.batch_size => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("failed to abort multipart uploads: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Aborts the uploads started more than `abandon_after_secs` ago and removes
+    /// their rows, returns how many were aborted.
+    #[tracing::instrument(name = "Abort abandoned multipart uploads", skip_all)]
+    pub async fn abort_abandoned(&self) -> anyhow::Result<usize> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let rows = sqlx::query!(
+            r#"
+                select upload_id, key, multipart_id as "multipart_id!"
+                from upload
+                where multipart_id is not null
+                    and multipart_started_at < now() - make_interval(secs => $1)
+                order by multipart_started_at
+                limit $2
+                for update skip locked
+            "#,
+            self.config.abandon_after_secs as f64,
+            self.config.batch_size
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut count = 0;
+
+        for row in rows {
+            if let Err(e) = self
+                .storage
+                .abort_multipart(&row.key, &row.multipart_id)
+                .await
+            {
+                tracing::error!("failed to abort multipart upload: {:?}", e);
+                continue;
+            }
+
+            sqlx::query!("delete from upload where upload_id = $1", row.upload_id)
+                .execute(&mut *tx)
+                .await?;
+
+            count += 1;
+        }
+
+        tx.commit().await?;
+
+        Ok(count)
+    }
+}
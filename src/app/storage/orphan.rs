@@ -0,0 +1,181 @@
+use std::{collections::HashSet, sync::Arc};
+
+use serde::Serialize;
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{app::utils::types::Timestamptz, config::OrphanCleanupConfig};
+
+use super::{image::ImageVariant, path::S3Path, Storage};
+
+/// Stored object nothing refers to
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrphanObject {
+    pub key: String,
+    pub size: i64,
+    #[schema(value_type = String, format = DateTime)]
+    pub last_modified: Timestamptz,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrphanReport {
+    /// Nothing was deleted, the orphans are only reported
+    pub dry_run: bool,
+    pub objects: Vec<OrphanObject>,
+    /// Bytes the orphans take up
+    pub total_size: i64,
+    /// Uploads never confirmed and profile images no user has anymore
+    pub stale_uploads: usize,
+}
+
+/// Deletes the stored objects no user or upload refers to.
+///
+/// Uploads of a deleted user or business go with their rows, replaced profile images
+/// and uploads never confirmed are dropped once older than `grace_period_secs`.
+#[derive(Clone)]
+pub struct OrphanCleaner {
+    db_pool: Arc<PgPool>,
+    storage: Arc<dyn Storage>,
+    config: OrphanCleanupConfig,
+}
+
+impl OrphanCleaner {
+    pub fn new(
+        db_pool: Arc<PgPool>,
+        storage: Arc<dyn Storage>,
+        config: OrphanCleanupConfig,
+    ) -> Self {
+        Self {
+            db_pool,
+            storage,
+            config,
+        }
+    }
+
+    /// Cleans up every `poll_interval_secs`, never returns
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.poll_interval_secs,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            match self.clean(self.config.dry_run).await {
+                Ok(report) => tracing::info!(
+                    dry_run = report.dry_run,
+                    objects = report.objects.len(),
+                    total_size = report.total_size,
+                    stale_uploads = report.stale_uploads,
+                    "cleaned up orphaned objects"
+                ),
+                Err(e) => tracing::error!("failed to clean up orphaned objects: {:?}", e),
+            }
+        }
+    }
+
+    /// Finds the orphaned objects and, unless `dry_run`, deletes them together
+    /// with the stale uploads.
+    #[tracing::instrument(name = "Clean up orphaned objects", skip(self))]
+    pub async fn clean(&self, dry_run: bool) -> anyhow::Result<OrphanReport> {
+        let grace_period_secs = self.config.grace_period_secs as f64;
+
+        let stale_ids = sqlx::query_scalar!(
+            r#"
+                select upload_id
+                from upload
+                where (
+                        status = 'pending'
+                        and multipart_id is null
+                        and coalesce(updated_at, created_at) < now() - make_interval(secs => $1)
+                    )
+                    or (
+                        path = 'profile'
+                        and status = 'confirmed'
+                        and confirmed_at < now() - make_interval(secs => $1)
+                        and not exists (select 1 from "user" u where u.image = upload.key)
+                    )
+            "#,
+            grace_period_secs
+        )
+        .fetch_all(&*self.db_pool)
+        .await?;
+
+        if !dry_run && !stale_ids.is_empty() {
+            sqlx::query!(
+                "delete from upload where upload_id = any($1)",
+                &stale_ids as &[Uuid]
+            )
+            .execute(&*self.db_pool)
+            .await?;
+        }
+
+        let live_keys = self.live_keys(&stale_ids).await?;
+
+        let modified_before =
+            OffsetDateTime::now_utc() - Duration::seconds(self.config.grace_period_secs);
+        let objects = self
+            .storage
+            .list()
+            .await?
+            .into_iter()
+            .filter(|object| {
+                object.last_modified < modified_before && !live_keys.contains(&object.key)
+            })
+            .map(|object| OrphanObject {
+                key: object.key,
+                size: object.size,
+                last_modified: Timestamptz(object.last_modified),
+            })
+            .collect::<Vec<_>>();
+
+        if !dry_run {
+            for object in &objects {
+                self.storage.delete(&object.key).await?;
+            }
+        }
+
+        Ok(OrphanReport {
+            dry_run,
+            total_size: objects.iter().map(|object| object.size).sum(),
+            objects,
+            stale_uploads: stale_ids.len(),
+        })
+    }
+
+    /// Keys of the uploads but `stale_ids` and of the profile images, with their variants
+    async fn live_keys(&self, stale_ids: &[Uuid]) -> anyhow::Result<HashSet<String>> {
+        let uploads = sqlx::query!(
+            r#"
+                select key, path as "path: S3Path"
+                from upload
+                where upload_id <> all($1)
+            "#,
+            stale_ids
+        )
+        .fetch_all(&*self.db_pool)
+        .await?;
+
+        // Images set before uploads were tracked have no upload
+        let images =
+            sqlx::query_scalar!(r#"select image as "image!" from "user" where image is not null"#)
+                .fetch_all(&*self.db_pool)
+                .await?;
+
+        let mut keys = HashSet::new();
+
+        let uploads = uploads
+            .into_iter()
+            .map(|upload| (upload.key, upload.path.has_variants()));
+        for (key, has_variants) in uploads.chain(images.into_iter().map(|key| (key, true))) {
+            if has_variants {
+                keys.extend(ImageVariant::ALL.map(|variant| variant.key(&key)));
+            }
+            keys.insert(key);
+        }
+
+        Ok(keys)
+    }
+}
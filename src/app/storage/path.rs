@@ -1,34 +1,126 @@
 use mime2::Mime;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use uuid::Uuid;
 
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
+use super::PRIVATE_PREFIX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "s3_path", rename_all = "snake_case")]
 pub enum S3Path {
     Profile,
+    BusinessLogo,
+    BusinessGallery,
+    /// Verification documents, e.g. the business registration
+    BusinessDocument,
+    BusinessMenu,
+    BusinessVideo,
+}
+
+/// Who the files under a path belong to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathScope {
+    /// The uploading user
+    User,
+    /// A business, uploaded by one of its members
+    Business,
 }
 
 impl std::fmt::Display for S3Path {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             S3Path::Profile => write!(f, "profile"),
+            S3Path::BusinessLogo => write!(f, "business_logo"),
+            S3Path::BusinessGallery => write!(f, "business_gallery"),
+            S3Path::BusinessDocument => write!(f, "business_document"),
+            S3Path::BusinessMenu => write!(f, "business_menu"),
+            S3Path::BusinessVideo => write!(f, "business_video"),
         }
     }
 }
 
 impl S3Path {
+    pub const ALL: [S3Path; 6] = [
+        S3Path::Profile,
+        S3Path::BusinessLogo,
+        S3Path::BusinessGallery,
+        S3Path::BusinessDocument,
+        S3Path::BusinessMenu,
+        S3Path::BusinessVideo,
+    ];
+
+    pub fn scope(&self) -> PathScope {
+        match self {
+            S3Path::Profile => PathScope::User,
+            S3Path::BusinessLogo
+            | S3Path::BusinessGallery
+            | S3Path::BusinessDocument
+            | S3Path::BusinessMenu
+            | S3Path::BusinessVideo => PathScope::Business,
+        }
+    }
+
+    /// Storage key of the file, `owner_id` is the user or business of the `scope`
+    pub fn key(&self, owner_id: &Uuid, file_name: &str) -> String {
+        match self {
+            S3Path::Profile => format!("profile/{}/{}", owner_id, file_name),
+            S3Path::BusinessLogo => format!("business/{}/logo/{}", owner_id, file_name),
+            S3Path::BusinessGallery => format!("business/{}/gallery/{}", owner_id, file_name),
+            S3Path::BusinessDocument => format!(
+                "{}business/{}/documents/{}",
+                PRIVATE_PREFIX, owner_id, file_name
+            ),
+            S3Path::BusinessMenu => format!("business/{}/menu/{}", owner_id, file_name),
+            S3Path::BusinessVideo => format!("business/{}/video/{}", owner_id, file_name),
+        }
+    }
+
+    /// Files only downloadable through `/upload/{id}/download`, see `PRIVATE_PREFIX`
+    pub fn is_private(&self) -> bool {
+        match self {
+            S3Path::BusinessDocument => true,
+            S3Path::Profile
+            | S3Path::BusinessLogo
+            | S3Path::BusinessGallery
+            | S3Path::BusinessMenu
+            | S3Path::BusinessVideo => false,
+        }
+    }
+
+    /// Files larger than `multipart.part_size` have to be uploaded in parts
     pub fn get_max_size(&self) -> i64 {
         match self {
             S3Path::Profile => 500_000,
+            S3Path::BusinessLogo => 1_000_000,
+            S3Path::BusinessGallery => 5_000_000,
+            S3Path::BusinessDocument => 10_000_000,
+            S3Path::BusinessMenu => 50_000_000,
+            S3Path::BusinessVideo => 500_000_000,
         }
     }
 
-    pub fn is_allowed_type(&self, mime: &Mime) -> bool {
+    /// Images under the path get resized WebP variants, see `image::ImageWorker`
+    pub fn has_variants(&self) -> bool {
         match self {
-            S3Path::Profile => {
-                let types = [mime2::image::JPEG, mime2::image::PNG, mime2::image::WEBP];
+            S3Path::Profile | S3Path::BusinessLogo | S3Path::BusinessGallery => true,
+            S3Path::BusinessDocument | S3Path::BusinessMenu | S3Path::BusinessVideo => false,
+        }
+    }
 
-                types.contains(mime)
+    pub fn is_allowed_type(&self, mime: &Mime) -> bool {
+        let types: &[Mime] = match self {
+            S3Path::Profile | S3Path::BusinessLogo | S3Path::BusinessGallery => {
+                &[mime2::image::JPEG, mime2::image::PNG, mime2::image::WEBP]
             }
-        }
+            S3Path::BusinessDocument => &[
+                mime2::application::PDF,
+                mime2::image::JPEG,
+                mime2::image::PNG,
+            ],
+            S3Path::BusinessMenu => &[mime2::application::PDF],
+            S3Path::BusinessVideo => &[mime2::video::MP4, mime2::video::QUICKTIME],
+        };
+
+        types.contains(mime)
     }
 }
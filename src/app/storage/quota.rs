@@ -0,0 +1,37 @@
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::config::QuotaConfig;
+
+use super::path::PathScope;
+
+/// Bytes the owner of a path `scope` can store
+pub fn quota_bytes(scope: PathScope, config: &QuotaConfig) -> i64 {
+    match scope {
+        PathScope::User => config.user_bytes,
+        PathScope::Business => config.business_bytes,
+    }
+}
+
+/// Bytes stored by the user or business, uploads in progress and image variants
+/// included. `except_key` is left out, uploading to it again replaces the file.
+pub async fn storage_usage<'e, E>(
+    owner_id: &Uuid,
+    except_key: &str,
+    executor: E,
+) -> Result<i64, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar!(
+        r#"
+            select coalesce(sum(size + variants_size), 0)::bigint as "usage!"
+            from upload
+            where coalesce(business_id, user_id) = $1 and key <> $2
+        "#,
+        owner_id,
+        except_key
+    )
+    .fetch_one(executor)
+    .await
+}
@@ -0,0 +1,369 @@
+use anyhow::Context;
+use aws_config::SdkConfig;
+use aws_sdk_s3::{
+    operation::{
+        abort_multipart_upload::AbortMultipartUploadError, get_object::GetObjectError,
+        head_object::HeadObjectError,
+    },
+    presigning::{PresignedRequest, PresigningConfig},
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart as S3CompletedPart},
+    Client,
+};
+use futures::future::BoxFuture;
+use time::OffsetDateTime;
+
+use super::{
+    is_private_key, CompletedPart, ObjectMeta, PresignedUpload, Storage, StoredObject,
+    DOWNLOAD_EXPIRES_IN, PART_UPLOAD_EXPIRES_IN, UPLOAD_EXPIRES_IN,
+};
+
+/// Stores files in an S3 bucket served by a CDN.
+///
+/// Private keys go to a separate bucket the CDN doesn't serve,
+/// they're only reachable through presigned downloads.
+pub struct S3Storage {
+    s3_client: Client,
+    bucket_name: String,
+    private_bucket_name: String,
+    base_url: String,
+}
+
+impl S3Storage {
+    pub fn new(
+        sdk_config: &SdkConfig,
+        bucket_name: &str,
+        private_bucket_name: &str,
+        base_url: &str,
+    ) -> anyhow::Result<Self> {
+        if bucket_name == private_bucket_name {
+            anyhow::bail!("private files can't be stored in the bucket served by the CDN");
+        }
+
+        let client = Client::new(sdk_config);
+
+        Ok(Self {
+            s3_client: client,
+            bucket_name: bucket_name.to_string(),
+            private_bucket_name: private_bucket_name.to_string(),
+            base_url: base_url.to_string(),
+        })
+    }
+
+    fn bucket(&self, key: &str) -> &str {
+        if is_private_key(key) {
+            &self.private_bucket_name
+        } else {
+            &self.bucket_name
+        }
+    }
+}
+
+fn to_presigned_upload(presigned: PresignedRequest) -> PresignedUpload {
+    PresignedUpload {
+        uri: presigned.uri().to_string(),
+        method: presigned.method().to_string(),
+        headers: presigned
+            .headers()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+impl Storage for S3Storage {
+    fn presign_upload<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+        content_length: i64,
+    ) -> BoxFuture<'a, anyhow::Result<PresignedUpload>> {
+        Box::pin(async move {
+            let presigned = self
+                .s3_client
+                .put_object()
+                .bucket(self.bucket(key))
+                .key(key)
+                .content_type(content_type)
+                .content_length(content_length)
+                .presigned(
+                    PresigningConfig::builder()
+                        .expires_in(UPLOAD_EXPIRES_IN)
+                        .build()
+                        .expect("expire must be less than one week"),
+                )
+                .await
+                .context("failed to generate upload presigned_url")?;
+
+            Ok(to_presigned_upload(presigned))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.s3_client
+                .delete_object()
+                .bucket(self.bucket(key))
+                .key(key)
+                .send()
+                .await
+                .context("failed to delete given path")?;
+
+            Ok(())
+        })
+    }
+
+    fn create_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let output = self
+                .s3_client
+                .create_multipart_upload()
+                .bucket(self.bucket(key))
+                .key(key)
+                .content_type(content_type)
+                .send()
+                .await
+                .context("failed to create multipart upload")?;
+
+            output
+                .upload_id()
+                .map(str::to_string)
+                .context("multipart upload has no id")
+        })
+    }
+
+    fn presign_part<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+        part_number: i32,
+        content_length: i64,
+    ) -> BoxFuture<'a, anyhow::Result<PresignedUpload>> {
+        Box::pin(async move {
+            let presigned = self
+                .s3_client
+                .upload_part()
+                .bucket(self.bucket(key))
+                .key(key)
+                .upload_id(multipart_id)
+                .part_number(part_number)
+                .content_length(content_length)
+                .presigned(
+                    PresigningConfig::builder()
+                        .expires_in(PART_UPLOAD_EXPIRES_IN)
+                        .build()
+                        .expect("expire must be less than one week"),
+                )
+                .await
+                .context("failed to generate part presigned_url")?;
+
+            Ok(to_presigned_upload(presigned))
+        })
+    }
+
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+        parts: &'a [CompletedPart],
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut parts = parts.to_vec();
+            parts.sort_by_key(|part| part.part_number);
+
+            let parts = parts
+                .into_iter()
+                .map(|part| {
+                    S3CompletedPart::builder()
+                        .part_number(part.part_number)
+                        .e_tag(part.etag)
+                        .build()
+                })
+                .collect();
+
+            self.s3_client
+                .complete_multipart_upload()
+                .bucket(self.bucket(key))
+                .key(key)
+                .upload_id(multipart_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await
+                .context("failed to complete multipart upload")?;
+
+            Ok(())
+        })
+    }
+
+    fn abort_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        multipart_id: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let res = self
+                .s3_client
+                .abort_multipart_upload()
+                .bucket(self.bucket(key))
+                .key(key)
+                .upload_id(multipart_id)
+                .send()
+                .await;
+
+            match res {
+                Ok(_) => Ok(()),
+                Err(e)
+                    if matches!(
+                        e.as_service_error(),
+                        Some(AbortMultipartUploadError::NoSuchUpload(_))
+                    ) =>
+                {
+                    Ok(())
+                }
+                Err(e) => Err(e).context("failed to abort multipart upload"),
+            }
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let res = self
+                .s3_client
+                .get_object()
+                .bucket(self.bucket(key))
+                .key(key)
+                .send()
+                .await;
+
+            match res {
+                Ok(output) => {
+                    let bytes = output
+                        .body
+                        .collect()
+                        .await
+                        .context("failed to read object body")?;
+
+                    Ok(Some(bytes.to_vec()))
+                }
+                Err(e) if matches!(e.as_service_error(), Some(GetObjectError::NoSuchKey(_))) => {
+                    Ok(None)
+                }
+                Err(e) => Err(e).context("failed to get given path"),
+            }
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.s3_client
+                .put_object()
+                .bucket(self.bucket(key))
+                .key(key)
+                .content_type(content_type)
+                .body(ByteStream::from(bytes))
+                .send()
+                .await
+                .context("failed to put given path")?;
+
+            Ok(())
+        })
+    }
+
+    fn head<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<ObjectMeta>>> {
+        Box::pin(async move {
+            let res = self
+                .s3_client
+                .head_object()
+                .bucket(self.bucket(key))
+                .key(key)
+                .send()
+                .await;
+
+            match res {
+                Ok(output) => Ok(Some(ObjectMeta {
+                    size: output.content_length().unwrap_or_default(),
+                    content_type: output.content_type().map(str::to_string),
+                })),
+                Err(e) if matches!(e.as_service_error(), Some(HeadObjectError::NotFound(_))) => {
+                    Ok(None)
+                }
+                Err(e) => Err(e).context("failed to head given path"),
+            }
+        })
+    }
+
+    fn list<'a>(&'a self) -> BoxFuture<'a, anyhow::Result<Vec<StoredObject>>> {
+        Box::pin(async move {
+            let mut objects = Vec::new();
+
+            for bucket in [&self.bucket_name, &self.private_bucket_name] {
+                let mut pages = self
+                    .s3_client
+                    .list_objects_v2()
+                    .bucket(bucket)
+                    .into_paginator()
+                    .send();
+
+                while let Some(page) = pages.next().await {
+                    let page = page.context("failed to list objects")?;
+
+                    for object in page.contents() {
+                        let (Some(key), Some(last_modified)) =
+                            (object.key(), object.last_modified())
+                        else {
+                            continue;
+                        };
+
+                        objects.push(StoredObject {
+                            key: key.to_string(),
+                            size: object.size().unwrap_or_default(),
+                            last_modified: OffsetDateTime::from_unix_timestamp(
+                                last_modified.secs(),
+                            )
+                            .context("invalid last modified")?,
+                        });
+                    }
+                }
+            }
+
+            Ok(objects)
+        })
+    }
+
+    fn presign_download<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let presigned = self
+                .s3_client
+                .get_object()
+                .bucket(self.bucket(key))
+                .key(key)
+                .presigned(
+                    PresigningConfig::builder()
+                        .expires_in(DOWNLOAD_EXPIRES_IN)
+                        .build()
+                        .expect("expire must be less than one week"),
+                )
+                .await
+                .context("failed to generate download presigned_url")?;
+
+            Ok(presigned.uri().to_string())
+        })
+    }
+
+    /// Private keys aren't in the bucket the CDN serves
+    fn public_url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, key)
+    }
+}
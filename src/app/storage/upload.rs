@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::path::S3Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "upload_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum UploadStatus {
+    /// Presigned, the object may not be in storage yet
+    Pending,
+    /// The object was checked against the requested type and size
+    Confirmed,
+}
+
+/// Id of the confirmed upload stored under `key`, when it was uploaded to `path` of the owner.
+///
+/// `owner_id` is the user or business of the path scope.
+/// Records only reference files through this, so they never point at
+/// objects that were never uploaded.
+#[tracing::instrument(name = "Find confirmed upload", skip(executor))]
+pub async fn find_confirmed_upload(
+    key: &str,
+    owner_id: &Uuid,
+    path: S3Path,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            select upload_id
+            from upload
+            where key = $1
+                and coalesce(business_id, user_id) = $2
+                and path = $3
+                and status = 'confirmed'
+        "#,
+        key,
+        owner_id,
+        path as S3Path
+    )
+    .fetch_optional(executor)
+    .await
+}
@@ -1,6 +1,67 @@
-const GENERATOR_URL: &str =
-    "https://api.dicebear.com/9.x/thumbs/svg?backgroundColor=b6e3f4,c0aede,d1d4f9";
+use std::io::Cursor;
 
-pub fn generate_avatar(seed: &str) -> String {
-    format!("{}&seed={}", GENERATOR_URL, seed)
+use anyhow::Context;
+use image::{ImageFormat, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+
+/// Cells per side, the left half is mirrored to the right
+const GRID: u32 = 5;
+const CELL_SIZE: u32 = 70;
+const PADDING: u32 = 35;
+const BACKGROUND: Rgb<u8> = Rgb([240, 240, 240]);
+
+/// Identicon PNG of the seed, the same seed always gives the same image.
+///
+/// Only a hash of the seed ends up in the image, so the user id can be used.
+pub fn generate_avatar(seed: &str) -> anyhow::Result<Vec<u8>> {
+    let hash = Sha256::digest(seed.as_bytes());
+
+    let hue = u16::from_be_bytes([hash[0], hash[1]]) as f32 / u16::MAX as f32 * 360.0;
+    let color = hsl_to_rgb(hue, 0.55, 0.55);
+
+    // One bit per cell of the left half and the middle column
+    let half = GRID.div_ceil(2);
+    let filled = |row: u32, col: u32| {
+        let col = col.min(GRID - 1 - col);
+        hash[(2 + row * half + col) as usize] % 2 == 0
+    };
+
+    let side = GRID * CELL_SIZE + 2 * PADDING;
+    let image = RgbImage::from_fn(side, side, |x, y| {
+        let inside =
+            (PADDING..side - PADDING).contains(&x) && (PADDING..side - PADDING).contains(&y);
+
+        if inside && filled((y - PADDING) / CELL_SIZE, (x - PADDING) / CELL_SIZE) {
+            color
+        } else {
+            BACKGROUND
+        }
+    });
+
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageFormat::Png)
+        .context("failed to encode avatar")?;
+
+    Ok(bytes.into_inner())
+}
+
+/// `hue` in degrees, `saturation` and `lightness` between 0 and 1
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+
+    let (r, g, b) = match hue as u32 {
+        0..60 => (chroma, x, 0.0),
+        60..120 => (x, chroma, 0.0),
+        120..180 => (0.0, chroma, x),
+        180..240 => (0.0, x, chroma),
+        240..300 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let channel = |value: f32| ((value + m) * 255.0).round() as u8;
+
+    Rgb([channel(r), channel(g), channel(b)])
 }
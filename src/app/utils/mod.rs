@@ -1,3 +1,5 @@
 pub mod avatar_generator;
+pub mod redis_record;
+pub mod transliterate;
 pub mod types;
 pub mod validation;
@@ -0,0 +1,73 @@
+use anyhow::Context;
+use redis::{AsyncCommands, Client};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Stores the value as JSON under `key`, it expires after `ttl`
+pub async fn store<T: Serialize>(
+    key: &str,
+    value: &T,
+    ttl: time::Duration,
+    client: &Client,
+) -> anyhow::Result<()> {
+    let mut conn = client
+        .get_multiplexed_tokio_connection()
+        .await
+        .context("failed to connect to redis")?;
+
+    let _: () = conn
+        .set_ex(
+            key,
+            serde_json::to_string(value).context("failed to serialize redis value")?,
+            ttl.whole_seconds() as u64,
+        )
+        .await
+        .context("failed to store value to redis")?;
+
+    Ok(())
+}
+
+/// The value stored under `key`, `None` once it expired or was deleted
+pub async fn get<T: DeserializeOwned>(key: &str, client: &Client) -> anyhow::Result<Option<T>> {
+    let mut conn = client
+        .get_multiplexed_tokio_connection()
+        .await
+        .context("failed to connect to redis")?;
+
+    let res: Option<String> = conn
+        .get(key)
+        .await
+        .context("failed to get value from redis")?;
+
+    parse(res)
+}
+
+/// Gets and deletes the value in one command, so only one caller gets it
+pub async fn take<T: DeserializeOwned>(key: &str, client: &Client) -> anyhow::Result<Option<T>> {
+    let mut conn = client
+        .get_multiplexed_tokio_connection()
+        .await
+        .context("failed to connect to redis")?;
+
+    let res: Option<String> = conn
+        .get_del(key)
+        .await
+        .context("failed to get value from redis")?;
+
+    parse(res)
+}
+
+pub async fn delete(key: &str, client: &Client) -> anyhow::Result<()> {
+    let mut conn = client
+        .get_multiplexed_tokio_connection()
+        .await
+        .context("failed to connect to redis")?;
+
+    let _: () = conn.del(key).await.context("failed to delete key")?;
+
+    Ok(())
+}
+
+fn parse<T: DeserializeOwned>(raw: Option<String>) -> anyhow::Result<Option<T>> {
+    raw.map(|raw| serde_json::from_str(&raw).context("failed to parse redis value"))
+        .transpose()
+}
@@ -0,0 +1,156 @@
+/// Mongolian Cyrillic in Latin letters the way people type it, lowercased.
+///
+/// Same as the `mn_latin` SQL function the search columns are built with, keep in sync.
+pub fn transliterate(input: &str) -> String {
+    let mut latin = String::with_capacity(input.len());
+
+    for c in input.chars().flat_map(char::to_lowercase) {
+        let mapped = match c {
+            'а' => "a",
+            'б' => "b",
+            'в' => "v",
+            'г' => "g",
+            'д' => "d",
+            'е' => "e",
+            'ё' => "yo",
+            'ж' => "j",
+            'з' => "z",
+            'и' | 'й' | 'ы' | 'ь' => "i",
+            'к' => "k",
+            'л' => "l",
+            'м' => "m",
+            'н' => "n",
+            'о' => "o",
+            'п' => "p",
+            'р' => "r",
+            'с' => "s",
+            'т' => "t",
+            'у' | 'ө' | 'ү' | 'ö' | 'ü' => "u",
+            'ф' => "f",
+            'х' => "h",
+            'ц' => "ts",
+            'ч' => "ch",
+            'ш' | 'щ' => "sh",
+            'ъ' => "",
+            'э' => "e",
+            'ю' => "yu",
+            'я' => "ya",
+            _ => {
+                latin.push(c);
+                continue;
+            }
+        };
+        latin.push_str(mapped);
+    }
+
+    latin.replace("kh", "h")
+}
+
+/// Wraps the words of `text` that match one of the `query` words in `<mark>`,
+/// `None` when nothing matches.
+///
+/// Words are compared transliterated, so a Latin query marks Cyrillic words and the other way around.
+pub fn highlight(text: &str, query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| transliterate(term.trim_matches(|c: char| !c.is_alphanumeric())))
+        .filter(|term| !term.is_empty())
+        .collect();
+
+    let mut highlighted = String::with_capacity(text.len());
+    let mut matched = false;
+
+    for (i, word) in text.split(' ').enumerate() {
+        if i > 0 {
+            highlighted.push(' ');
+        }
+
+        let latin = transliterate(word.trim_matches(|c: char| !c.is_alphanumeric()));
+        let is_match = !latin.is_empty() && terms.iter().any(|term| words_match(&latin, term));
+
+        if is_match {
+            matched = true;
+            highlighted.push_str("<mark>");
+            push_escaped(&mut highlighted, word);
+            highlighted.push_str("</mark>");
+        } else {
+            push_escaped(&mut highlighted, word);
+        }
+    }
+
+    matched.then_some(highlighted)
+}
+
+/// The word starts with the term or the other way around, a longer term may differ in its
+/// last fifth since the search itself is typo tolerant, e.g. "cherri" marks "cherry".
+fn words_match(word: &str, term: &str) -> bool {
+    let common = word
+        .chars()
+        .zip(term.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let word_len = word.chars().count();
+    let term_len = term.chars().count();
+
+    common == term_len
+        || (common == word_len && word_len >= 3)
+        || (term_len >= 4 && common * 5 >= term_len * 4)
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transliterate() {
+        let cases = [
+            ("Сакура Кафе", "sakura kafe"),
+            ("Өргөө", "urguu"),
+            ("Хүүхдийн Ертөнц", "huuhdiin ertunts"),
+            ("Чингис Хаан", "chingis haan"),
+            ("Khaan Bank", "haan bank"),
+            ("Sakura Cafe", "sakura cafe"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(transliterate(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_highlight_across_scripts() {
+        assert_eq!(
+            highlight("Сакура Кафе", "sakura").as_deref(),
+            Some("<mark>Сакура</mark> Кафе")
+        );
+        assert_eq!(
+            highlight("Sakura Cafe", "сак").as_deref(),
+            Some("<mark>Sakura</mark> Cafe")
+        );
+        assert_eq!(
+            highlight("Cherry Blossom", "черри").as_deref(),
+            Some("<mark>Cherry</mark> Blossom")
+        );
+        assert_eq!(highlight("Sakura Cafe", "pizza"), None);
+    }
+
+    #[test]
+    fn test_highlight_escapes_html() {
+        assert_eq!(
+            highlight("Tom & <Jerry>", "tom").as_deref(),
+            Some("<mark>Tom</mark> &amp; &lt;Jerry&gt;")
+        );
+    }
+}
@@ -1,10 +1,13 @@
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
 use serde::de;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::postgres::types::PgHstore;
 use std::fmt::Formatter;
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
+use utoipa::ToSchema;
 use uuid::Uuid;
+use validator::Validate;
 
 #[derive(Debug, Clone, sqlx::Type)]
 pub struct Timestamptz(pub OffsetDateTime);
@@ -122,11 +125,81 @@ impl<'de> de::Deserialize<'de> for CPagination {
     }
 }
 
+/// Keyset cursor for results sorted by a score like the distance or the search rank, then id
+#[derive(Debug)]
+pub struct ScorePagination {
+    pub id: Uuid,
+    pub score: f64,
+}
+
+impl Serialize for ScorePagination {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Order is important, match with deserializer
+        let input = format!("{},{}", self.id, self.score);
+        let encoded = URL_SAFE.encode(input);
+
+        serializer.collect_str(&encoded)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for ScorePagination {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct StrVisitor;
+
+        impl de::Visitor<'_> for StrVisitor {
+            type Value = ScorePagination;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("expected a valid cursor string")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                let decoded_bytes = URL_SAFE.decode(v).map_err(E::custom)?;
+                let param_str = String::from_utf8(decoded_bytes).map_err(E::custom)?;
+
+                let Some((id, score)) = param_str.split_once(",") else {
+                    return Err(E::custom("malformed cursor"));
+                };
+
+                let id = Uuid::try_parse(id).map_err(E::custom)?;
+                let score: f64 = score.parse().map_err(E::custom)?;
+                if !score.is_finite() {
+                    return Err(E::custom("malformed cursor"));
+                }
+
+                Ok(ScorePagination { id, score })
+            }
+        }
+
+        deserializer.deserialize_str(StrVisitor)
+    }
+}
+
+/// Stored as the user's preferred language, picks the email template variant
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema, sqlx::Type,
+)]
+#[sqlx(type_name = "locale", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum Locale {
+    #[default]
     En,
     Mn,
 }
 
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Mn];
+}
+
 impl std::fmt::Display for Locale {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -146,3 +219,39 @@ impl std::str::FromStr for Locale {
         }
     }
 }
+
+/// Text in every `Locale`, stored as `hstore` where `en` is required
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct LocalizedText {
+    pub en: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mn: Option<String>,
+}
+
+impl LocalizedText {
+    pub fn get(&self, locale: Locale) -> Option<&str> {
+        match locale {
+            Locale::En => Some(&self.en),
+            Locale::Mn => self.mn.as_deref(),
+        }
+    }
+
+    pub fn to_hstore(&self) -> PgHstore {
+        Locale::ALL
+            .into_iter()
+            .filter_map(|locale| {
+                self.get(locale)
+                    .map(|text| (locale.to_string(), Some(text.to_string())))
+            })
+            .collect()
+    }
+}
+
+/// WGS 84 coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate, ToSchema)]
+pub struct GeoPoint {
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub lat: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub lng: f64,
+}
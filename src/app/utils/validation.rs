@@ -10,6 +10,10 @@ use crate::app::{auth::password::verify_password_hash, error::AppError};
 pub static USERNAME_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9_-]{3,32}$").unwrap());
 
+/// Phone number in E.164 format, e.g. `+97699112233`
+pub static PHONE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\+[1-9][0-9]{7,14}$").unwrap());
+
 #[tracing::instrument(name = "Password required API, validating")]
 pub async fn validate_password(
     candidate_pw: SecretString,
@@ -96,6 +100,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_phone_regex() {
+        let valid_phones = ["+97699112233", "+97688001122", "+14155552671"];
+        let invalid_phones = [
+            "99112233",
+            "+976 9911 2233",
+            "+0123456789",
+            "+976",
+            "+9769911a233",
+        ];
+
+        for &phone in &valid_phones {
+            assert!(PHONE_REGEX.is_match(phone), "{} should be valid", phone);
+        }
+
+        for &phone in &invalid_phones {
+            assert!(!PHONE_REGEX.is_match(phone), "{} should be invalid", phone);
+        }
+    }
+
     #[test]
     fn test_business_name_en_valid() {
         let valid_strings = [
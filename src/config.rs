@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
@@ -42,28 +44,262 @@ pub struct AppConfig {
 
     pub frontend: FrontConfig,
     pub email: EmailConfig,
+    #[serde(default)]
+    pub sms: SmsConfig,
+    #[serde(default)]
+    pub otp: OtpConfig,
     pub github: GithubOAuthConfig,
     pub discord: DiscordOAuthConfig,
+    pub facebook: FacebookOAuthConfig,
+    /// Generic OpenID Connect providers keyed by name, e.g. `[oidc.keycloak]`
+    #[serde(default)]
+    pub oidc: HashMap<String, OidcProviderConfig>,
     pub db: DatabaseConfig,
     pub redis: RedisConfig,
     pub aws: AWSConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub images: ImageProcessingConfig,
+    #[serde(default)]
+    pub multipart: MultipartConfig,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub orphans: OrphanCleanupConfig,
+}
+
+impl AppConfig {
+    /// The `log` transport never reaches the phone, so codes can't be sent in prod
+    pub fn sms_delivers(&self) -> bool {
+        !(self.stage == Stage::Prod && matches!(self.sms.transport, SmsTransportConfig::Log))
+    }
 }
 
 #[derive(Deserialize, Clone)]
 pub struct FrontConfig {
     pub url: String,
+    /// Page the providers redirect back to with `code` and `state`
+    #[serde(default = "default_oauth_callback_path")]
+    pub oauth_callback_path: String,
+}
+
+impl FrontConfig {
+    pub fn oauth_redirect_uri(&self) -> String {
+        format!("{}{}", self.url, self.oauth_callback_path)
+    }
+}
+
+fn default_oauth_callback_path() -> String {
+    "/auth/oauth".into()
 }
 
 #[derive(Deserialize, Clone)]
 pub struct EmailConfig {
     pub from_mail: String,
     pub account_email_limit: u8,
+    /// Directory with a `{locale}/*.json` folder of email templates per locale
+    #[serde(default = "default_templates_dir")]
+    pub templates_dir: String,
+    pub transport: EmailTransportConfig,
+    #[serde(default)]
+    pub outbox: EmailOutboxConfig,
+    #[serde(default)]
+    pub webhook: EmailWebhookConfig,
+    #[serde(default)]
+    pub primary_change: PrimaryEmailChangeConfig,
+}
+
+/// Primary email changes wait for a grace period, so the old address can cancel them
+#[derive(Deserialize, Clone)]
+pub struct PrimaryEmailChangeConfig {
+    pub grace_period_secs: i64,
+    /// How often the due changes are applied
+    pub poll_interval_secs: u64,
+}
+
+impl Default for PrimaryEmailChangeConfig {
+    fn default() -> Self {
+        Self {
+            grace_period_secs: 86400,
+            poll_interval_secs: 60,
+        }
+    }
+}
+
+/// SES bounce/complaint notifications delivered through SNS
+#[derive(Deserialize, Clone)]
+pub struct EmailWebhookConfig {
+    /// Accepted SNS topics, every message is rejected when empty
+    #[serde(default)]
+    pub topic_arns: Vec<String>,
+    #[serde(default)]
+    pub verifier: SnsVerifierConfig,
+    /// Messages whose `Timestamp` is further off are rejected as replays
+    #[serde(default = "default_max_message_age_secs")]
+    pub max_message_age_secs: i64,
+    /// How long a soft bounce suppresses the address
+    #[serde(default = "default_soft_bounce_suppression_secs")]
+    pub soft_bounce_suppression_secs: i64,
+}
+
+impl Default for EmailWebhookConfig {
+    fn default() -> Self {
+        Self {
+            topic_arns: vec![],
+            verifier: SnsVerifierConfig::default(),
+            max_message_age_secs: default_max_message_age_secs(),
+            soft_bounce_suppression_secs: default_soft_bounce_suppression_secs(),
+        }
+    }
+}
+
+/// How the SNS message signatures are verified, selected with `kind`.
+#[derive(Deserialize, Clone, Default)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SnsVerifierConfig {
+    /// With the AWS signing certificate from `SigningCertURL`
+    #[default]
+    Aws,
+    /// With a fixed RSA public key in PEM, for tests and local tooling
+    Local { public_key: String },
+}
+
+/// Delivery settings of the queued emails
+#[derive(Deserialize, Clone)]
+pub struct EmailOutboxConfig {
+    pub poll_interval_secs: u64,
+    pub batch_size: i64,
+    /// Attempts before the email is marked as failed
+    pub max_attempts: i32,
+    /// Delay after the first failure, doubled after each attempt
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+    /// How long a worker holds the emails it's sending before others may pick them up
+    pub lease_secs: u64,
+    /// Sent and suppressed emails are deleted after this long
+    pub retention_secs: i64,
+}
+
+impl Default for EmailOutboxConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 5,
+            batch_size: 20,
+            max_attempts: 6,
+            backoff_base_secs: 30,
+            backoff_max_secs: 3600,
+            lease_secs: 300,
+            retention_secs: 604800,
+        }
+    }
+}
+
+impl EmailOutboxConfig {
+    /// Delay before the next attempt, after `attempts` failed ones
+    pub fn backoff(&self, attempts: i32) -> std::time::Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 31) as u32;
+        let secs = self
+            .backoff_base_secs
+            .saturating_mul(2u64.saturating_pow(exponent))
+            .min(self.backoff_max_secs);
+
+        std::time::Duration::from_secs(secs)
+    }
+}
+
+/// Where outgoing emails are delivered, selected with `kind`.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum EmailTransportConfig {
+    /// AWS SES, templates are rendered locally like the other transports
+    Ses,
+    Smtp(SmtpConfig),
+    /// Writes every email as a JSON file to `dir`, for inspecting mail in dev
+    File {
+        dir: String,
+    },
+    /// Keeps emails in memory, for asserting on sent emails in tests
+    Memory,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    /// Upgrade the connection with STARTTLS, disable only for local catchers like Mailpit
+    #[serde(default = "default_true")]
+    pub starttls: bool,
+}
+
+fn default_templates_dir() -> String {
+    "templates".into()
+}
+
+fn default_max_message_age_secs() -> i64 {
+    300
+}
+
+fn default_soft_bounce_suppression_secs() -> i64 {
+    259200
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmsConfig {
+    pub account_phone_limit: u8,
+    pub transport: SmsTransportConfig,
+}
+
+impl Default for SmsConfig {
+    fn default() -> Self {
+        Self {
+            account_phone_limit: 3,
+            transport: SmsTransportConfig::Log,
+        }
+    }
+}
+
+/// Limits shared by every one-time code, see `app::otp::store::OtpStore`
+#[derive(Deserialize, Clone)]
+pub struct OtpConfig {
+    /// Wrong codes allowed before every code of the subject is burned
+    pub max_attempts: u32,
+    /// Seconds before the same code can be sent again
+    pub resend_cooldown_secs: u64,
+}
+
+impl Default for OtpConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            resend_cooldown_secs: 60,
+        }
+    }
+}
+
+/// Where outgoing text messages are delivered, selected with `kind`.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SmsTransportConfig {
+    /// Only logs the messages, until an SMS provider is set up
+    Log,
+    /// Writes every message as a JSON file to `dir`
+    File { dir: String },
+    /// Keeps messages in memory, for asserting on sent messages in tests
+    Memory,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct GithubOAuthConfig {
     pub id: String,
     pub secret: SecretString,
+    pub authorize_url: String,
     pub token_url: String,
     pub api_base_url: String,
 }
@@ -72,10 +308,59 @@ pub struct GithubOAuthConfig {
 pub struct DiscordOAuthConfig {
     pub id: String,
     pub secret: SecretString,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub api_base_url: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FacebookOAuthConfig {
+    pub id: String,
+    pub secret: SecretString,
+    pub authorize_url: String,
     pub token_url: String,
     pub api_base_url: String,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct OidcProviderConfig {
+    /// Issuer url, discovery document is fetched from `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    pub id: String,
+    pub secret: SecretString,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub claims: OidcClaimMapping,
+}
+
+/// Maps user info claims of a provider to our user fields.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct OidcClaimMapping {
+    pub subject: String,
+    pub email: String,
+    pub email_verified: String,
+    pub bio: Option<String>,
+    pub picture: String,
+}
+
+impl Default for OidcClaimMapping {
+    fn default() -> Self {
+        Self {
+            subject: "sub".to_string(),
+            email: "email".to_string(),
+            email_verified: "email_verified".to_string(),
+            bio: None,
+            picture: "picture".to_string(),
+        }
+    }
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".into(), "email".into(), "profile".into()]
+}
+
 #[derive(Deserialize, Clone)]
 pub struct DatabaseConfig {
     pub host: String,
@@ -94,9 +379,107 @@ pub struct RedisConfig {
 #[derive(Deserialize, Clone)]
 pub struct AWSConfig {
     pub s3: String,
+    /// Bucket of the `private/` keys, never served by `cdn`
+    pub private_s3: String,
     pub cdn: String,
 }
 
+/// Where uploaded files are stored, selected with `kind`.
+#[derive(Deserialize, Clone, Default)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum StorageConfig {
+    /// The `aws.s3` bucket served from `aws.cdn`, private files in `aws.private_s3`
+    #[default]
+    S3,
+    /// Files on disk under `dir`, uploaded and served through the api.
+    /// `public_url` defaults to `http://localhost:{port}`
+    Local {
+        dir: String,
+        public_url: Option<String>,
+    },
+}
+
+/// Resized variants of the uploaded images, see `app::storage::image::ImageWorker`
+#[derive(Deserialize, Clone)]
+pub struct ImageProcessingConfig {
+    pub poll_interval_secs: u64,
+    pub batch_size: i64,
+    /// Attempts before the image is marked as failed
+    pub max_attempts: i32,
+    /// How long a worker holds the images it's processing before others may pick them up,
+    /// also the delay before a failed attempt is retried
+    pub lease_secs: u64,
+}
+
+impl Default for ImageProcessingConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 5,
+            batch_size: 10,
+            max_attempts: 5,
+            lease_secs: 300,
+        }
+    }
+}
+
+/// Uploads in parts, see `app::storage::multipart::MultipartCleaner`
+#[derive(Deserialize, Clone)]
+pub struct MultipartConfig {
+    /// Size of every part but the last, S3 needs at least 5 MiB
+    pub part_size: i64,
+    /// Multipart uploads not completed by then are aborted
+    pub abandon_after_secs: i64,
+    pub poll_interval_secs: u64,
+    pub batch_size: i64,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        Self {
+            part_size: 10 * 1024 * 1024,
+            abandon_after_secs: 86400,
+            poll_interval_secs: 3600,
+            batch_size: 50,
+        }
+    }
+}
+
+/// Bytes of uploads and their image variants each owner can store
+#[derive(Deserialize, Clone)]
+pub struct QuotaConfig {
+    pub user_bytes: i64,
+    pub business_bytes: i64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            user_bytes: 50_000_000,
+            business_bytes: 2_000_000_000,
+        }
+    }
+}
+
+/// Stored objects no row refers to, see `app::storage::orphan::OrphanCleaner`
+#[derive(Deserialize, Clone)]
+pub struct OrphanCleanupConfig {
+    /// Orphans modified more recently are kept, uploads may still be in flight
+    pub grace_period_secs: i64,
+    pub poll_interval_secs: u64,
+    /// Only report the orphans, nothing is deleted
+    pub dry_run: bool,
+}
+
+impl Default for OrphanCleanupConfig {
+    fn default() -> Self {
+        Self {
+            grace_period_secs: 86400,
+            poll_interval_secs: 86400,
+            dry_run: false,
+        }
+    }
+}
+
 impl DatabaseConfig {
     pub fn db_connect_options(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
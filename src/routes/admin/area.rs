@@ -0,0 +1,401 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json as SqlJson, Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::{
+    app::{
+        business::area::{
+            assign_address_areas, children_allow_type, import_areas, parse_csv, parse_geojson,
+            valid_boundary, valid_parent, AreaImportSummary, AreaType,
+        },
+        error::{AppError, ResultExt},
+        extrator::{ExtractLocale, ValidatedJson},
+        utils::types::{Locale, LocalizedText, Timestamptz},
+        ApiContext,
+    },
+    routes::docs::ADMIN_TAG,
+};
+
+const MAX_AREA_NAME_LENGTH: usize = 100;
+
+/// Boundary files of every district are a few megabytes
+pub const MAX_AREA_IMPORT_SIZE: usize = 50 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct AreaInput {
+    /// Unique, imports match areas by it
+    #[validate(length(min = 1, max = 50))]
+    code: Option<String>,
+    #[validate(custom(function = "validate_area_name"))]
+    name: LocalizedText,
+    #[serde(rename = "type")]
+    area_type: AreaType,
+    /// Required unless the area is a country
+    parent_id: Option<Uuid>,
+    /// GeoJSON polygon or multipolygon, addresses inside it are assigned to the area
+    #[validate(custom(function = "validate_boundary"))]
+    #[schema(value_type = Option<Object>)]
+    boundary: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AreaResponse {
+    area_id: Uuid,
+    code: Option<String>,
+    /// In the requested locale, `en` when missing
+    name: Option<String>,
+    localized_name: LocalizedText,
+    #[serde(rename = "type")]
+    area_type: AreaType,
+    parent_id: Option<Uuid>,
+    /// GeoJSON multipolygon
+    #[schema(value_type = Option<Object>)]
+    boundary: Option<serde_json::Value>,
+    #[schema(value_type = String)]
+    created_at: Timestamptz,
+}
+
+fn validate_area_name(name: &LocalizedText) -> Result<(), ValidationError> {
+    let valid = Locale::ALL
+        .into_iter()
+        .filter_map(|locale| name.get(locale))
+        .all(|text| !text.trim().is_empty() && text.chars().count() <= MAX_AREA_NAME_LENGTH);
+
+    if !valid {
+        return Err(ValidationError::new("length"));
+    }
+
+    Ok(())
+}
+
+fn validate_boundary(boundary: &serde_json::Value) -> Result<(), ValidationError> {
+    if !valid_boundary(boundary) {
+        return Err(ValidationError::new("invalid_geometry"));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/area/{id}",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["area.manage"])
+    ),
+    params(
+        ("id" = String, Path, description = "Area database id")
+    ),
+    responses(
+        (status = 200, description = "Area with its boundary", body = AreaResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Area not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Get area", skip_all, fields(id = ?id))]
+pub async fn get_area(
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+    ExtractLocale(locale): ExtractLocale,
+) -> Result<Json<AreaResponse>, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+    let area = fetch_area(&id, locale, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(area))
+}
+
+#[utoipa::path(
+    post,
+    path = "/area",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["area.manage"])
+    ),
+    request_body = AreaInput,
+    responses(
+        (status = 201, description = "Area created", body = AreaResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Create area", skip_all, fields(req = ?req))]
+pub async fn create_area(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    ValidatedJson(req): ValidatedJson<AreaInput>,
+) -> Result<(StatusCode, Json<AreaResponse>), AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    check_parent(&req, &mut tx).await?;
+
+    let area_id = sqlx::query_scalar!(
+        r#"
+            insert into address_area (code, name, type, parent_id, boundary)
+            values ($1, $2, $3, $4, st_multi(st_setsrid(st_geomfromgeojson($5), 4326)))
+            returning area_id
+        "#,
+        req.code,
+        req.name.to_hstore() as _,
+        req.area_type as AreaType,
+        req.parent_id,
+        req.boundary.as_ref().map(|b| b.to_string())
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .on_constraint("address_area_code_key", |_| {
+        AppError::unprocessable_entity([("code", "taken")])
+    })?;
+
+    assign_address_areas(&mut *tx).await?;
+
+    let area = fetch_area(&area_id, locale, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(area)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/area/{id}",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["area.manage"])
+    ),
+    params(
+        ("id" = String, Path, description = "Area database id")
+    ),
+    request_body = AreaInput,
+    responses(
+        (status = 200, description = "Area replaced, boundary included", body = AreaResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Area not found"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Update area", skip_all, fields(id = ?id, req = ?req))]
+pub async fn update_area(
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+    ExtractLocale(locale): ExtractLocale,
+    ValidatedJson(req): ValidatedJson<AreaInput>,
+) -> Result<Json<AreaResponse>, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    check_parent(&req, &mut tx).await?;
+
+    sqlx::query_scalar!(
+        r#"
+            update address_area
+            set code = $1,
+                name = $2,
+                type = $3,
+                parent_id = $4,
+                boundary = st_multi(st_setsrid(st_geomfromgeojson($5), 4326))
+            where area_id = $6
+            returning area_id
+        "#,
+        req.code,
+        req.name.to_hstore() as _,
+        req.area_type as AreaType,
+        req.parent_id,
+        req.boundary.as_ref().map(|b| b.to_string()),
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .on_constraint("address_area_code_key", |_| {
+        AppError::unprocessable_entity([("code", "taken")])
+    })?;
+
+    if !children_allow_type(&id, req.area_type, &mut *tx).await? {
+        return Err(AppError::unprocessable_entity([(
+            "type",
+            "invalid_for_children",
+        )]));
+    }
+
+    assign_address_areas(&mut *tx).await?;
+
+    let area = fetch_area(&id, locale, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(area))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/area/{id}",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["area.manage"])
+    ),
+    params(
+        ("id" = String, Path, description = "Area database id")
+    ),
+    responses(
+        (status = 204, description = "Area deleted, its addresses are left without an area"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Area not found"),
+        (status = 422, description = "Area still has areas inside it", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Delete area", skip_all, fields(id = ?id))]
+pub async fn delete_area(
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    // Children would be deleted with it by the cascade, they have to be removed first
+    let has_children = sqlx::query_scalar!(
+        r#"select exists (select 1 from address_area where parent_id = $1) as "exists!""#,
+        id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    if has_children {
+        return Err(AppError::unprocessable_entity([("children", "not_empty")]));
+    }
+
+    let res = sqlx::query!("delete from address_area where area_id = $1", id)
+        .execute(&*ctx.db_pool)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/area/import",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["area.manage"])
+    ),
+    request_body(
+        description = "GeoJSON `FeatureCollection` with the area fields as properties, \
+            or CSV with `code,parent_code,type,name_en,name_mn,boundary` columns",
+        content(
+            (String = "application/geo+json"),
+            (String = "text/csv")
+        )
+    ),
+    responses(
+        (status = 200, description = "Areas created or updated by code", body = AreaImportSummary),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 422, description = "Invalid file, nothing is imported", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Import areas", skip_all)]
+pub async fn import_area_file(
+    ctx: State<ApiContext>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<AreaImportSummary>, AppError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(str::trim);
+
+    let records = match content_type {
+        Some("application/geo+json") | Some("application/json") => parse_geojson(&body)?,
+        Some("text/csv") => parse_csv(&body)?,
+        _ => {
+            return Err(AppError::unprocessable_entity([(
+                "file",
+                "unsupported_type",
+            )]))
+        }
+    };
+
+    let mut tx = ctx.db_pool.begin().await?;
+    let summary = import_areas(&records, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(summary))
+}
+
+/// Parent has to exist and be broader than the area
+async fn check_parent(
+    req: &AreaInput,
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<(), AppError> {
+    let parent_type = match req.parent_id {
+        Some(parent_id) => Some(
+            sqlx::query_scalar!(
+                r#"select type as "area_type: AreaType" from address_area where area_id = $1"#,
+                parent_id
+            )
+            .fetch_optional(&mut **tx)
+            .await?
+            .ok_or_else(|| AppError::unprocessable_entity([("parent_id", "not_found")]))?,
+        ),
+        None => None,
+    };
+
+    if !valid_parent(req.area_type, parent_type) {
+        return Err(AppError::unprocessable_entity([("parent_id", "invalid")]));
+    }
+
+    Ok(())
+}
+
+/// Area with its boundary, `RowNotFound` when missing
+async fn fetch_area(
+    area_id: &Uuid,
+    locale: Locale,
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<AreaResponse, AppError> {
+    let area = sqlx::query!(
+        r#"
+            select
+                area_id,
+                code,
+                coalesce(nullif(name->$1, ''), name->'en') as name,
+                hstore_to_json(name) as "localized_name!: SqlJson<LocalizedText>",
+                type as "area_type: AreaType",
+                parent_id,
+                st_asgeojson(boundary)::json as "boundary: SqlJson<serde_json::Value>",
+                created_at
+            from address_area
+            where area_id = $2
+        "#,
+        locale.to_string(),
+        area_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(AreaResponse {
+        area_id: area.area_id,
+        code: area.code,
+        name: area.name,
+        localized_name: area.localized_name.0,
+        area_type: area.area_type,
+        parent_id: area.parent_id,
+        boundary: area.boundary.map(|b| b.0),
+        created_at: area.created_at.into(),
+    })
+}
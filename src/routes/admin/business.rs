@@ -1,31 +1,195 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::StatusCode,
     Json,
 };
-use serde::Serialize;
-use utoipa::ToSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, types::Json as SqlJson, Postgres, QueryBuilder, Transaction};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
 use crate::{
-    app::{error::AppError, extrator::ExtractLocale, utils::types::Timestamptz, ApiContext},
+    app::{
+        error::{AppError, ResultExt},
+        extrator::{ExtractLocale, ValidatedJson},
+        utils::{
+            types::{CPagination, GeoPoint, Locale, LocalizedText, Timestamptz},
+            validation::{BUSINESS_NAME_EN_REGEX, BUSINESS_NAME_MN_REGEX},
+        },
+        ApiContext,
+    },
     routes::docs::ADMIN_TAG,
 };
 
+const MAX_ADDRESS_LINE_LENGTH: usize = 255;
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct BusinessInput {
+    #[validate(custom(function = "validate_business_name"))]
+    name: LocalizedText,
+    #[serde(default)]
+    category_ids: Vec<Uuid>,
+    #[serde(default)]
+    #[validate(nested)]
+    addresses: Vec<AddressInput>,
+    /// Listed publicly once published
+    #[serde(default)]
+    published: bool,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct AddressInput {
+    /// Shown as the address
+    #[validate(custom(function = "validate_address_line"))]
+    line_1: LocalizedText,
+    /// More details, e.g. the floor
+    #[validate(custom(function = "validate_address_line"))]
+    line_2: Option<LocalizedText>,
+    #[validate(nested)]
+    location: Option<GeoPoint>,
+    /// Address area the address is in, e.g. its district
+    area_id: Option<Uuid>,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct BusinessResponse {
     business_id: Uuid,
+    /// In the requested locale, `en` when missing
     name: Option<String>,
+    localized_name: LocalizedText,
+    category_ids: Vec<Uuid>,
+    addresses: Vec<AddressResponse>,
+    #[schema(value_type = Option<String>)]
+    published_at: Option<Timestamptz>,
     #[schema(value_type = String)]
     created_at: Timestamptz,
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct AddressResponse {
+    address_id: Uuid,
+    line_1: LocalizedText,
+    line_2: Option<LocalizedText>,
+    location: Option<GeoPoint>,
+    area_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListBusinessesInput {
+    #[param(value_type = Option<String>)]
+    cursor: Option<CPagination>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BusinessListResponse {
+    data: Vec<BusinessListItem>,
+    #[schema(value_type = Option<String>)]
+    next_cursor: Option<CPagination>,
+}
+
+#[derive(Serialize, ToSchema, FromRow)]
+pub struct BusinessListItem {
+    business_id: Uuid,
+    name: Option<String>,
+    #[schema(value_type = String)]
+    created_at: Timestamptz,
+}
+
+fn validate_business_name(name: &LocalizedText) -> Result<(), ValidationError> {
+    if !BUSINESS_NAME_EN_REGEX.is_match(&name.en) {
+        return Err(ValidationError::new("invalid_en"));
+    }
+
+    if let Some(mn) = &name.mn {
+        if !BUSINESS_NAME_MN_REGEX.is_match(mn) {
+            return Err(ValidationError::new("invalid_mn"));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_address_line(line: &LocalizedText) -> Result<(), ValidationError> {
+    let valid = Locale::ALL
+        .into_iter()
+        .filter_map(|locale| line.get(locale))
+        .all(|text| !text.trim().is_empty() && text.chars().count() <= MAX_ADDRESS_LINE_LENGTH);
+
+    if !valid {
+        return Err(ValidationError::new("length"));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/business",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["business.manage"])
+    ),
+    params(ListBusinessesInput),
+    responses(
+        (status = 200, description = "List businesses, newest first", body = BusinessListResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List businesses", skip_all, fields(req = ?req))]
+pub async fn list_businesses(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Query(req): Query<ListBusinessesInput>,
+) -> Result<Json<BusinessListResponse>, AppError> {
+    let page_size: usize = 25;
+    let cursor_size: i64 = (page_size + 1) as i64;
+
+    let mut query_builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("select b.business_id, coalesce(nullif(b.name->");
+    query_builder.push_bind(locale.to_string());
+    query_builder.push(", ''), b.name->'en') as name, b.created_at from business b");
+
+    if let Some(c) = req.cursor {
+        query_builder.push(" where (b.created_at, b.business_id) <= (");
+        let mut separated = query_builder.separated(", ");
+        separated.push_bind(c.created_at);
+        separated.push_bind(c.id);
+        separated.push_unseparated(") ");
+    }
+
+    query_builder.push(" order by b.created_at desc, b.business_id desc ");
+
+    query_builder.push(" limit ");
+    query_builder.push_bind(cursor_size);
+
+    let query = query_builder.build_query_as::<BusinessListItem>();
+    let mut next_res = query.fetch_all(&*ctx.db_pool).await?;
+
+    let next_cursor: Option<CPagination> = if next_res.len() < page_size + 1 {
+        None
+    } else {
+        let next_item = next_res.pop();
+        next_item.map(|item| CPagination {
+            id: item.business_id,
+            created_at: item.created_at.clone(),
+        })
+    };
+
+    Ok(Json(BusinessListResponse {
+        data: next_res,
+        next_cursor,
+    }))
+}
+
 #[utoipa::path(
     get,
     path = "/business/{id}",
     tag = ADMIN_TAG,
     security(
-        // TODO
-        ("bearerAuth" = ["user.view"])
+        ("bearerAuth" = ["business.manage"])
     ),
     params(
         ("id" = String, Path, description = "Business database id")
@@ -34,6 +198,7 @@ pub struct BusinessResponse {
         (status = 200, description = "Business detail", body = BusinessResponse),
         (status = 401, description = "Unauthorized"),
         (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Business not found"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -43,21 +208,277 @@ pub async fn get_business(
     Path(id): Path<Uuid>,
     ExtractLocale(locale): ExtractLocale,
 ) -> Result<Json<BusinessResponse>, AppError> {
-    let row = sqlx::query_as!(
-        BusinessResponse,
+    let mut tx = ctx.db_pool.begin().await?;
+    let business = fetch_business(&id, locale, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(business))
+}
+
+#[utoipa::path(
+    post,
+    path = "/business",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["business.manage"])
+    ),
+    request_body = BusinessInput,
+    responses(
+        (status = 201, description = "Business created", body = BusinessResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Create business", skip_all, fields(req = ?req))]
+pub async fn create_business(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    ValidatedJson(req): ValidatedJson<BusinessInput>,
+) -> Result<(StatusCode, Json<BusinessResponse>), AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    let business_id = sqlx::query_scalar!(
+        r#"
+            insert into business (name, published_at)
+            values ($1, case when $2 then now() end)
+            returning business_id
+        "#,
+        req.name.to_hstore() as _,
+        req.published
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    insert_categories(&business_id, &req.category_ids, &mut tx).await?;
+    insert_addresses(&business_id, &req.addresses, &mut tx).await?;
+
+    let business = fetch_business(&business_id, locale, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(business)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/business/{id}",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["business.manage"])
+    ),
+    params(
+        ("id" = String, Path, description = "Business database id")
+    ),
+    request_body = BusinessInput,
+    responses(
+        (status = 200, description = "Business replaced, categories and addresses included", body = BusinessResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Business not found"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Update business", skip_all, fields(id = ?id, req = ?req))]
+pub async fn update_business(
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+    ExtractLocale(locale): ExtractLocale,
+    ValidatedJson(req): ValidatedJson<BusinessInput>,
+) -> Result<Json<BusinessResponse>, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    sqlx::query_scalar!(
+        r#"
+            update business
+            set name = $1,
+                published_at = case when $2 then coalesce(published_at, now()) end
+            where business_id = $3
+            returning business_id
+        "#,
+        req.name.to_hstore() as _,
+        req.published,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!("delete from business_category where business_id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("delete from address where business_id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+
+    insert_categories(&id, &req.category_ids, &mut tx).await?;
+    insert_addresses(&id, &req.addresses, &mut tx).await?;
+
+    let business = fetch_business(&id, locale, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(business))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/business/{id}",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["business.manage"])
+    ),
+    params(
+        ("id" = String, Path, description = "Business database id")
+    ),
+    responses(
+        (status = 204, description = "Business deleted with its addresses, members and uploads"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Business not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Delete business", skip_all, fields(id = ?id))]
+pub async fn delete_business(
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    // Stored files are left to the orphan cleanup
+    let res = sqlx::query!("delete from business where business_id = $1", id)
+        .execute(&*ctx.db_pool)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn insert_categories(
+    business_id: &Uuid,
+    category_ids: &[Uuid],
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        r#"
+            insert into business_category (business_id, category_id)
+            select $1, category_id
+            from unnest($2::uuid[]) as category_id
+            on conflict do nothing
+        "#,
+        business_id,
+        category_ids
+    )
+    .execute(&mut **tx)
+    .await
+    .on_constraint("business_category_category_id_fkey", |_| {
+        AppError::unprocessable_entity([("category_ids", "not_found")])
+    })?;
+
+    Ok(())
+}
+
+async fn insert_addresses(
+    business_id: &Uuid,
+    addresses: &[AddressInput],
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<(), AppError> {
+    for address in addresses {
+        sqlx::query!(
+            r#"
+                insert into address (business_id, line_1, line_2, geom, area_id)
+                values (
+                    $1, $2, $3,
+                    case when $4::float8 is not null and $5::float8 is not null
+                        then st_setsrid(st_makepoint($5, $4), 4326)
+                    end,
+                    $6
+                )
+            "#,
+            business_id,
+            address.line_1.to_hstore() as _,
+            address.line_2.as_ref().map(LocalizedText::to_hstore) as _,
+            address.location.as_ref().map(|point| point.lat),
+            address.location.as_ref().map(|point| point.lng),
+            address.area_id
+        )
+        .execute(&mut **tx)
+        .await
+        .on_constraint("address_area_id_fkey", |_| {
+            AppError::unprocessable_entity([("addresses", "area_not_found")])
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Business with its categories and addresses, `RowNotFound` when missing
+async fn fetch_business(
+    business_id: &Uuid,
+    locale: Locale,
+    tx: &mut Transaction<'static, Postgres>,
+) -> Result<BusinessResponse, AppError> {
+    let business = sqlx::query!(
         r#"
             select
                 b.business_id,
                 coalesce(nullif(b.name->$1, ''), b.name->'en') as name,
-                b.created_at
+                hstore_to_json(b.name) as "localized_name!: SqlJson<LocalizedText>",
+                b.published_at,
+                b.created_at,
+                coalesce(
+                    array(
+                        select bc.category_id
+                        from business_category bc
+                        where bc.business_id = b.business_id
+                        order by bc.category_id
+                    ),
+                    '{}'
+                ) as "category_ids!: Vec<Uuid>"
             from business b
-            where business_id = $2
+            where b.business_id = $2
         "#,
         locale.to_string(),
-        id
+        business_id
     )
-    .fetch_one(&*ctx.db_pool)
+    .fetch_one(&mut **tx)
     .await?;
 
-    Ok(Json(row))
+    let addresses = sqlx::query!(
+        r#"
+            select
+                address_id,
+                hstore_to_json(line_1) as "line_1!: SqlJson<LocalizedText>",
+                hstore_to_json(line_2) as "line_2: SqlJson<LocalizedText>",
+                st_y(geom) as lat,
+                st_x(geom) as lng,
+                area_id
+            from address
+            where business_id = $1
+            order by created_at, address_id
+        "#,
+        business_id
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .map(|row| AddressResponse {
+        address_id: row.address_id,
+        line_1: row.line_1.0,
+        line_2: row.line_2.map(|line| line.0),
+        location: row.lat.zip(row.lng).map(|(lat, lng)| GeoPoint { lat, lng }),
+        area_id: row.area_id,
+    })
+    .collect();
+
+    Ok(BusinessResponse {
+        business_id: business.business_id,
+        name: business.name,
+        localized_name: business.localized_name.0,
+        category_ids: business.category_ids,
+        addresses,
+        published_at: business.published_at.map(Timestamptz),
+        created_at: Timestamptz(business.created_at),
+    })
 }
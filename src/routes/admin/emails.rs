@@ -0,0 +1,220 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{prelude::FromRow, Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    app::{
+        email::{queue::EmailStatus, suppression::lift_suppression, template::redact_data},
+        error::AppError,
+        utils::types::{CPagination, Locale, Timestamptz},
+        ApiContext,
+    },
+    routes::docs::ADMIN_TAG,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListEmailsInput {
+    /// Only emails with this status
+    status: Option<EmailStatus>,
+    #[param(value_type = Option<String>)]
+    cursor: Option<CPagination>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EmailListResponse {
+    data: Vec<OutboxEmail>,
+    #[schema(value_type = Option<String>)]
+    next_cursor: Option<CPagination>,
+}
+
+#[derive(Serialize, ToSchema, FromRow)]
+pub struct OutboxEmail {
+    email_outbox_id: Uuid,
+    recipient: String,
+    template: String,
+    locale: Locale,
+    /// Tokens and codes are redacted, cleared once the email is sent
+    #[schema(value_type = Option<Object>)]
+    data: Option<Value>,
+    status: EmailStatus,
+    attempts: i32,
+    last_error: Option<String>,
+    #[schema(value_type = String)]
+    next_attempt_at: Timestamptz,
+    #[schema(value_type = Option<String>)]
+    sent_at: Option<Timestamptz>,
+    #[schema(value_type = String)]
+    created_at: Timestamptz,
+}
+
+#[utoipa::path(
+    get,
+    path = "/emails",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["email.manage"])
+    ),
+    params(ListEmailsInput),
+    responses(
+        (status = 200, description = "List outbox emails, newest first", body = EmailListResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List outbox emails", skip_all, fields(req = ?req))]
+pub async fn list_emails(
+    ctx: State<ApiContext>,
+    Query(req): Query<ListEmailsInput>,
+) -> Result<Json<EmailListResponse>, AppError> {
+    let page_size: usize = 25;
+    let cursor_size: i64 = (page_size + 1) as i64;
+
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        r#"
+            select email_outbox_id, recipient, template, locale, data, status,
+                attempts, last_error, next_attempt_at, sent_at, created_at
+            from email_outbox
+            where true
+        "#,
+    );
+
+    if let Some(status) = req.status {
+        query_builder.push(" and status = ");
+        query_builder.push_bind(status);
+    }
+
+    if let Some(c) = req.cursor {
+        query_builder.push(" and (created_at, email_outbox_id) <= (");
+        let mut separated = query_builder.separated(", ");
+        separated.push_bind(c.created_at);
+        separated.push_bind(c.id);
+        separated.push_unseparated(") ");
+    }
+
+    query_builder.push(" order by created_at desc, email_outbox_id desc ");
+
+    query_builder.push(" limit ");
+    query_builder.push_bind(cursor_size);
+
+    let query = query_builder.build_query_as::<OutboxEmail>();
+    let mut next_res = query.fetch_all(&*ctx.db_pool).await?;
+
+    let next_cursor: Option<CPagination> = if next_res.len() < page_size + 1 {
+        None
+    } else {
+        let next_item = next_res.pop();
+        next_item.map(|item| CPagination {
+            id: item.email_outbox_id,
+            created_at: item.created_at.clone(),
+        })
+    };
+
+    for email in next_res.iter_mut() {
+        if let Some(data) = email.data.as_mut() {
+            redact_data(data);
+        }
+    }
+
+    Ok(Json(EmailListResponse {
+        data: next_res,
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/emails/{id}/retry",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["email.manage"])
+    ),
+    params(
+        ("id" = String, Path, description = "Outbox email database id")
+    ),
+    responses(
+        (status = 204, description = "Queued again"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Email not found"),
+        (status = 422, description = "Email has not failed", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Retry outbox email", skip_all, fields(id = ?id))]
+pub async fn retry_email(
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let status = sqlx::query_scalar!(
+        r#"
+            select status as "status: EmailStatus"
+            from email_outbox
+            where email_outbox_id = $1
+        "#,
+        id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    if status != EmailStatus::Failed {
+        return Err(AppError::unprocessable_entity([("status", "not_failed")]));
+    }
+
+    // Starts over with a full set of attempts
+    sqlx::query!(
+        r#"
+            update email_outbox
+            set status = 'queued',
+                attempts = 0,
+                next_attempt_at = now()
+            where email_outbox_id = $1 and status = 'failed'
+        "#,
+        id
+    )
+    .execute(&*ctx.db_pool)
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/emails/suppressions/{email}",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["email.manage"])
+    ),
+    params(
+        ("email" = String, Path, description = "Suppressed email address")
+    ),
+    responses(
+        (status = 204, description = "Emails are sent to the address again"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 404, description = "Address is not suppressed"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Unsuppress email", skip_all, fields(email = ?email))]
+pub async fn unsuppress_email(
+    ctx: State<ApiContext>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    if !lift_suppression(&email, &mut tx).await? {
+        return Err(AppError::NotFound);
+    }
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,11 +1,21 @@
-use axum::{routing::get, Router};
-use business::get_business;
+use area::{create_area, delete_area, get_area, import_area_file, update_area};
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{delete, get, post},
+    Router,
+};
+use business::{create_business, delete_business, get_business, list_businesses, update_business};
+use emails::{list_emails, retry_email, unsuppress_email};
+use storage::report_orphans;
 use users::list_users;
 use utoipa::OpenApi;
 
 use crate::{app::ApiContext, permission_required};
 
+pub mod area;
 pub mod business;
+pub mod emails;
+pub mod storage;
 pub mod users;
 
 fn users_router() -> Router<ApiContext> {
@@ -14,20 +24,74 @@ fn users_router() -> Router<ApiContext> {
         .route_layer(permission_required!(&AppPermission::UserView))
 }
 
+fn emails_router() -> Router<ApiContext> {
+    Router::new()
+        .route("/emails", get(list_emails))
+        .route("/emails/{id}/retry", post(retry_email))
+        .route("/emails/suppressions/{email}", delete(unsuppress_email))
+        .route_layer(permission_required!(&AppPermission::EmailManage))
+}
+
+fn storage_router() -> Router<ApiContext> {
+    Router::new()
+        .route("/storage/orphans", get(report_orphans))
+        .route_layer(permission_required!(&AppPermission::StorageManage))
+}
+
 fn business_router() -> Router<ApiContext> {
     Router::new()
-        .route("/business/{id}", get(get_business))
-        // TODO: Permission setup
-        .route_layer(permission_required!(&AppPermission::UserView))
+        .route("/business", get(list_businesses).post(create_business))
+        .route(
+            "/business/{id}",
+            get(get_business)
+                .put(update_business)
+                .delete(delete_business),
+        )
+        .route_layer(permission_required!(&AppPermission::BusinessManage))
+}
+
+fn area_router() -> Router<ApiContext> {
+    Router::new()
+        .route("/area", post(create_area))
+        .route(
+            "/area/{id}",
+            get(get_area).put(update_area).delete(delete_area),
+        )
+        .route(
+            "/area/import",
+            post(import_area_file).layer(DefaultBodyLimit::max(area::MAX_AREA_IMPORT_SIZE)),
+        )
+        .route_layer(permission_required!(&AppPermission::AreaManage))
 }
 
 pub fn router() -> Router<ApiContext> {
     Router::new().nest(
         "/admin",
-        Router::new().merge(users_router()).merge(business_router()),
+        Router::new()
+            .merge(users_router())
+            .merge(emails_router())
+            .merge(storage_router())
+            .merge(business_router())
+            .merge(area_router()),
     )
 }
 
 #[derive(OpenApi)]
-#[openapi(paths(users::list_users))]
+#[openapi(paths(
+    users::list_users,
+    emails::list_emails,
+    emails::retry_email,
+    emails::unsuppress_email,
+    storage::report_orphans,
+    business::list_businesses,
+    business::get_business,
+    business::create_business,
+    business::update_business,
+    business::delete_business,
+    area::get_area,
+    area::create_area,
+    area::update_area,
+    area::delete_area,
+    area::import_area_file
+))]
 pub struct AdminApi;
@@ -0,0 +1,35 @@
+use axum::{extract::State, Json};
+
+use crate::{
+    app::{
+        error::AppError,
+        storage::orphan::{OrphanCleaner, OrphanReport},
+        ApiContext,
+    },
+    routes::docs::ADMIN_TAG,
+};
+
+#[utoipa::path(
+    get,
+    path = "/storage/orphans",
+    tag = ADMIN_TAG,
+    security(
+        ("bearerAuth" = ["storage.manage"])
+    ),
+    responses(
+        (status = 200, description = "Objects the cleanup would delete, nothing is deleted", body = OrphanReport),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Forbidden, scope not present"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Report orphaned objects", skip_all)]
+pub async fn report_orphans(ctx: State<ApiContext>) -> Result<Json<OrphanReport>, AppError> {
+    let cleaner = OrphanCleaner::new(
+        ctx.db_pool.clone(),
+        ctx.storage.clone(),
+        ctx.config.orphans.clone(),
+    );
+
+    Ok(Json(cleaner.clean(true).await?))
+}
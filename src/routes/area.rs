@@ -0,0 +1,217 @@
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::app::{business::area::AreaType, error::AppError, extrator::ExtractLocale, ApiContext};
+
+use super::docs::AREA_TAG;
+
+#[derive(OpenApi)]
+#[openapi(paths(list_root_areas, get_area, list_child_areas, list_area_ancestors))]
+pub struct AreaApi;
+
+pub fn router() -> Router<ApiContext> {
+    Router::new()
+        .route("/areas", get(list_root_areas))
+        .route("/areas/{id}", get(get_area))
+        .route("/areas/{id}/children", get(list_child_areas))
+        .route("/areas/{id}/ancestors", get(list_area_ancestors))
+}
+
+/// Address area, the name in the requested locale or `en` when missing
+#[derive(Serialize, ToSchema)]
+pub struct Area {
+    pub area_id: Uuid,
+    /// Import code, e.g. `MN-1`
+    pub code: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub area_type: AreaType,
+    pub parent_id: Option<Uuid>,
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    tag = AREA_TAG,
+    responses(
+        (status = 200, description = "Areas without a parent, i.e. countries", body = Vec<Area>),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List root areas", skip_all)]
+pub async fn list_root_areas(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+) -> Result<Json<Vec<Area>>, AppError> {
+    let areas = sqlx::query_as!(
+        Area,
+        r#"
+            select
+                area_id,
+                code,
+                coalesce(nullif(name->$1, ''), name->'en') as "name!",
+                type as "area_type: AreaType",
+                parent_id
+            from address_area
+            where parent_id is null
+            order by name->'en'
+        "#,
+        locale.to_string()
+    )
+    .fetch_all(&*ctx.db_pool)
+    .await?;
+
+    Ok(Json(areas))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    tag = AREA_TAG,
+    params(
+        ("id" = String, Path, description = "Area database id")
+    ),
+    responses(
+        (status = 200, description = "Area", body = Area),
+        (status = 404, description = "Area not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Get area", skip_all, fields(id = ?id))]
+pub async fn get_area(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Area>, AppError> {
+    let area = sqlx::query_as!(
+        Area,
+        r#"
+            select
+                area_id,
+                code,
+                coalesce(nullif(name->$1, ''), name->'en') as "name!",
+                type as "area_type: AreaType",
+                parent_id
+            from address_area
+            where area_id = $2
+        "#,
+        locale.to_string(),
+        id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    Ok(Json(area))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/children",
+    tag = AREA_TAG,
+    params(
+        ("id" = String, Path, description = "Area database id")
+    ),
+    responses(
+        (status = 200, description = "Areas directly inside the area, by name", body = Vec<Area>),
+        (status = 404, description = "Area not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List child areas", skip_all, fields(id = ?id))]
+pub async fn list_child_areas(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Area>>, AppError> {
+    let exists = sqlx::query_scalar!(
+        r#"select exists (select 1 from address_area where area_id = $1) as "exists!""#,
+        id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound);
+    }
+
+    let areas = sqlx::query_as!(
+        Area,
+        r#"
+            select
+                area_id,
+                code,
+                coalesce(nullif(name->$1, ''), name->'en') as "name!",
+                type as "area_type: AreaType",
+                parent_id
+            from address_area
+            where parent_id = $2
+            order by name->'en'
+        "#,
+        locale.to_string(),
+        id
+    )
+    .fetch_all(&*ctx.db_pool)
+    .await?;
+
+    Ok(Json(areas))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/ancestors",
+    tag = AREA_TAG,
+    params(
+        ("id" = String, Path, description = "Area database id")
+    ),
+    responses(
+        (status = 200, description = "Chain from the country down to the area itself", body = Vec<Area>),
+        (status = 404, description = "Area not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List area ancestors", skip_all, fields(id = ?id))]
+pub async fn list_area_ancestors(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Area>>, AppError> {
+    let areas = sqlx::query_as!(
+        Area,
+        r#"
+            with recursive ancestors as (
+                select area_id, parent_id, 0 as depth
+                from address_area
+                where area_id = $2
+                union all
+                select a.area_id, a.parent_id, ancestors.depth + 1
+                from address_area a
+                    join ancestors on a.area_id = ancestors.parent_id
+            )
+            select
+                a.area_id,
+                a.code,
+                coalesce(nullif(a.name->$1, ''), a.name->'en') as "name!",
+                a.type as "area_type: AreaType",
+                a.parent_id
+            from ancestors
+                join address_area a on a.area_id = ancestors.area_id
+            order by ancestors.depth desc
+        "#,
+        locale.to_string(),
+        id
+    )
+    .fetch_all(&*ctx.db_pool)
+    .await?;
+
+    if areas.is_empty() {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(Json(areas))
+}
@@ -13,14 +13,14 @@ use validator::Validate;
 
 use crate::{
     app::{
+        auth::email_change::{generate_cancel_token, hash_cancel_token, EmailChangeStatus},
+        email::{client::get_user_locale, suppression::SuppressionReason},
         error::{AppError, ResultExt},
         extrator::{AuthUser, ValidatedJson},
-        otp::{email_otp::EmailVerifyOtp, OtpManager},
         utils::{types::Timestamptz, validation::validate_password},
         ApiContext,
     },
-    config::Stage,
-    routes::docs::EMAIL_TAG,
+    routes::{auth::verify::send_email_verification, docs::EMAIL_TAG},
 };
 
 #[derive(Debug, Deserialize, Validate, ToSchema)]
@@ -41,6 +41,36 @@ pub struct Email {
     created_at: Timestamptz,
     #[schema(value_type = Option<String>, format = DateTime)]
     confirmation_sent_at: Option<Timestamptz>,
+    /// Set when mail to the address bounced or was marked as spam
+    delivery_issue: Option<SuppressionReason>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PrimaryEmailChangeResponse {
+    /// When the new email becomes primary, unless cancelled from the old address
+    #[schema(value_type = String, format = DateTime)]
+    effective_at: Timestamptz,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct CancelEmailChangeInput {
+    /// Token from the link sent to the old primary email
+    #[validate(length(min = 1))]
+    token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PrimaryEmailChange {
+    primary_email_change_id: Uuid,
+    old_email: String,
+    new_email: String,
+    status: EmailChangeStatus,
+    #[schema(value_type = String, format = DateTime)]
+    effective_at: Timestamptz,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    resolved_at: Option<Timestamptz>,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: Timestamptz,
 }
 
 struct EmailFromQuery {
@@ -50,6 +80,7 @@ struct EmailFromQuery {
     is_primary: bool,
     created_at: OffsetDateTime,
     confirmation_sent_at: Option<OffsetDateTime>,
+    delivery_issue: Option<SuppressionReason>,
 }
 
 impl EmailFromQuery {
@@ -61,6 +92,7 @@ impl EmailFromQuery {
             is_primary: self.is_primary,
             created_at: Timestamptz(self.created_at),
             confirmation_sent_at: self.confirmation_sent_at.map(Timestamptz),
+            delivery_issue: self.delivery_issue,
         }
     }
 }
@@ -117,18 +149,8 @@ pub async fn add_email(
         AppError::unprocessable_entity([("email", "taken")])
     })?;
 
-    let otp_manager = EmailVerifyOtp {
-        user_id: auth_user.user_id,
-        should_hash: ctx.config.stage == Stage::Prod,
-    };
-
-    let token = otp_manager.generate_otp();
-
-    otp_manager
-        .store_data(&token, &ctx.redis_client, &req.new_email)
-        .await?;
-
-    EmailVerifyOtp::send_email(&ctx.email_client, &token, &req.new_email).await?;
+    let locale = get_user_locale(&auth_user.user_id, &mut *tx).await?;
+    send_email_verification(&ctx, &auth_user.user_id, &req.new_email, locale, &mut *tx).await?;
 
     // Store unverified email
     tx.commit().await?;
@@ -147,9 +169,10 @@ pub async fn add_email(
         ("id" = String, Path, description = "Email database id")
     ),
     responses(
-        (status = 204, description = "Successful updated"),
+        (status = 202, description = "Change scheduled, the old primary email is notified", body = PrimaryEmailChangeResponse),
         (status = 401, description = "Unauthorized"),
         (status = 404, description = "Email not found"),
+        (status = 422, description = "Email is already primary or not verified", body = AppError),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -158,12 +181,12 @@ pub async fn update_email_to_primary(
     auth_user: AuthUser,
     ctx: State<ApiContext>,
     Path(id): Path<Uuid>,
-) -> Result<StatusCode, AppError> {
+) -> Result<(StatusCode, Json<PrimaryEmailChangeResponse>), AppError> {
     let mut tx = ctx.db_pool.begin().await?;
 
-    let current_row = sqlx::query_scalar!(
+    let current = sqlx::query!(
         r#"
-            select email_id
+            select email_id, email
             from email
             where user_id = $1 and is_primary = true
         "#,
@@ -172,42 +195,169 @@ pub async fn update_email_to_primary(
     .fetch_one(&mut *tx)
     .await?;
 
-    if current_row == id {
-        return Ok(StatusCode::NO_CONTENT);
+    let new_email = sqlx::query!(
+        r#"
+            select email_id, email, verified
+            from email
+            where email_id = $1 and user_id = $2
+        "#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tracing::Span::current().record("email", tracing::field::display(&new_email.email));
+
+    if new_email.email_id == current.email_id {
+        return Err(AppError::unprocessable_entity([("email", "primary")]));
+    }
+
+    if !new_email.verified {
+        return Err(AppError::unprocessable_entity([("email", "not_verified")]));
     }
 
+    // A new request replaces the one still waiting
     sqlx::query!(
         r#"
-            update email 
-            set is_primary = false
-            where email_id = $1 and user_id = $2
+            update primary_email_change
+            set status = 'cancelled', resolved_at = now()
+            where user_id = $1 and status = 'pending'
         "#,
-        current_row,
         auth_user.user_id
     )
     .execute(&mut *tx)
     .await?;
 
-    let email = sqlx::query_scalar!(
+    let grace_period_secs = ctx.config.email.primary_change.grace_period_secs;
+    let cancel_token = generate_cancel_token();
+
+    let effective_at = sqlx::query_scalar!(
         r#"
-            update email 
-            set is_primary = true
-            where email_id = $1 and user_id = $2
-            returning email
+            insert into primary_email_change
+                (user_id, old_email, new_email_id, new_email, cancel_token_hash, effective_at)
+            values ($1, $2, $3, $4, $5, now() + make_interval(secs => $6))
+            returning effective_at
         "#,
-        id,
-        auth_user.user_id
+        auth_user.user_id,
+        current.email,
+        new_email.email_id,
+        new_email.email,
+        hash_cancel_token(&cancel_token),
+        grace_period_secs as f64
     )
     .fetch_one(&mut *tx)
     .await?;
 
+    // Rounded up, a grace period under an hour must not read as 0 hours
+    let effective_in_hours = (grace_period_secs + 3599) / 3600;
+
+    let locale = get_user_locale(&auth_user.user_id, &mut *tx).await?;
+    let email_content = ctx
+        .email_client
+        .build_primary_email_change(&new_email.email, &cancel_token, effective_in_hours)
+        .await?;
+    ctx.email_client
+        .queue_email(&current.email, locale, email_content, &mut *tx)
+        .await?;
+
     tx.commit().await?;
 
-    tracing::Span::current().record("email", tracing::field::display(&email));
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(PrimaryEmailChangeResponse {
+            effective_at: Timestamptz(effective_at),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/emails/primary/cancel",
+    tag = EMAIL_TAG,
+    request_body = CancelEmailChangeInput,
+    responses(
+        (status = 204, description = "Change cancelled"),
+        (status = 404, description = "No pending change for the token"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Cancel primary email change", skip_all)]
+pub async fn cancel_primary_email_change(
+    ctx: State<ApiContext>,
+    ValidatedJson(req): ValidatedJson<CancelEmailChangeInput>,
+) -> Result<StatusCode, AppError> {
+    sqlx::query_scalar!(
+        r#"
+            update primary_email_change
+            set status = 'cancelled', resolved_at = now()
+            where cancel_token_hash = $1 and status = 'pending'
+            returning primary_email_change_id
+        "#,
+        hash_cancel_token(&req.token)
+    )
+    .fetch_optional(&*ctx.db_pool)
+    .await?
+    .ok_or(AppError::NotFound)?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    get,
+    path = "/emails/primary/changes",
+    tag = EMAIL_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    responses(
+        (status = 200, description = "Successful, latest first", body = Vec<PrimaryEmailChange>),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List primary email changes", skip_all, fields(auth_user = ?auth_user))]
+pub async fn list_primary_email_changes(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+) -> Result<Json<Vec<PrimaryEmailChange>>, AppError> {
+    let rows = sqlx::query!(
+        r#"
+            select
+                primary_email_change_id,
+                old_email,
+                new_email,
+                status as "status: EmailChangeStatus",
+                effective_at,
+                resolved_at,
+                created_at
+            from primary_email_change
+            where user_id = $1
+            order by created_at desc
+            limit 20
+        "#,
+        auth_user.user_id
+    )
+    .fetch_all(&*ctx.db_pool)
+    .await?;
+
+    let changes = rows
+        .into_iter()
+        .map(|row| PrimaryEmailChange {
+            primary_email_change_id: row.primary_email_change_id,
+            old_email: row.old_email,
+            new_email: row.new_email,
+            status: row.status,
+            effective_at: Timestamptz(row.effective_at),
+            resolved_at: row.resolved_at.map(Timestamptz),
+            created_at: Timestamptz(row.created_at),
+        })
+        .collect();
+
+    Ok(Json(changes))
+}
+
 #[utoipa::path(
     get,
     path = "/emails",
@@ -235,7 +385,8 @@ pub async fn list_user_email(
                 e.verified, 
                 e.is_primary, 
                 e.created_at, 
-                e.confirmation_sent_at
+                e.confirmation_sent_at,
+                e.delivery_issue as "delivery_issue: SuppressionReason"
             from email e
             where e.user_id = $1
             limit $2
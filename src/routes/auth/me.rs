@@ -8,9 +8,15 @@ use validator::Validate;
 use crate::{
     app::{
         auth::password::compute_password_hash,
+        email::suppression::SuppressionReason,
         error::{AppError, ResultExt},
         extrator::{AuthUser, ValidatedJson},
-        utils::validation::USERNAME_REGEX,
+        storage::{
+            image::{delete_variants, ImageUrls},
+            path::S3Path,
+            upload::find_confirmed_upload,
+        },
+        utils::{types::Locale, validation::USERNAME_REGEX},
         ApiContext,
     },
     routes::docs::AUTH_TAG,
@@ -21,8 +27,13 @@ pub struct MeResponse {
     username: String,
     email: String,
     email_verified: bool,
+    /// Set when mail to the primary email bounced, the user should replace it
+    email_delivery_issue: Option<SuppressionReason>,
     bio: String,
     image: Option<String>,
+    /// Set once the uploaded image is processed
+    image_urls: Option<ImageUrls>,
+    locale: Locale,
     reset_password: Option<bool>,
     reset_username: Option<bool>,
 }
@@ -39,7 +50,10 @@ pub struct CompleteMeInput {
 #[serde(default)] // fill in any missing fields with `..UpdateUser::default()`
 pub struct UpdateUserInput {
     bio: Option<String>,
+    /// `key` of a confirmed profile upload
     image: Option<String>,
+    /// Language of the emails sent to the user
+    locale: Option<Locale>,
 }
 
 #[utoipa::path(
@@ -64,9 +78,12 @@ pub async fn get_me_profile(
     let res = sqlx::query!(
         r#"
             select u.user_id, u.username, e.email, e.verified,
-            u.bio, u.image, u.reset_username, u.reset_password
+            e.delivery_issue as "delivery_issue: SuppressionReason",
+            u.bio, u.image, u.locale as "locale: Locale", u.reset_username, u.reset_password,
+            (up.processed_at is not null and up.processing_error is null) as "image_processed!"
             from email e
             inner join "user" u using (user_id)
+            left join upload up on up.key = u.image
             where e.user_id = $1 and e.is_primary = true
         "#,
         auth_user.user_id
@@ -78,8 +95,15 @@ pub async fn get_me_profile(
         username: res.username,
         email: res.email,
         email_verified: res.verified,
+        email_delivery_issue: res.delivery_issue,
         bio: res.bio,
+        image_urls: res
+            .image
+            .as_deref()
+            .filter(|_| res.image_processed)
+            .map(|key| ImageUrls::new(&*ctx.storage, key)),
         image: res.image,
+        locale: res.locale,
         reset_password: res.reset_password,
         reset_username: res.reset_username,
     }))
@@ -182,6 +206,13 @@ pub async fn update_me_profile(
         return Ok(StatusCode::NO_CONTENT);
     }
 
+    // Only files the user uploaded and confirmed can be referenced
+    if let Some(image) = &req.image {
+        find_confirmed_upload(image, &auth_user.user_id, S3Path::Profile, &*ctx.db_pool)
+            .await?
+            .ok_or_else(|| AppError::unprocessable_entity([("image", "unconfirmed")]))?;
+    }
+
     // save current image
     let current_img = sqlx::query_scalar!(
         r#"
@@ -199,11 +230,13 @@ pub async fn update_me_profile(
         r#"
             update "user"
             set bio = coalesce($1, "user".bio),
-                image = coalesce($2, "user".image)
-            where user_id = $3
+                image = coalesce($2, "user".image),
+                locale = coalesce($3, "user".locale)
+            where user_id = $4
         "#,
         req.bio,
         req.image,
+        req.locale as Option<Locale>,
         auth_user.user_id
     )
     .execute(&*ctx.db_pool)
@@ -220,12 +253,23 @@ pub async fn update_me_profile(
                 // start different job
                 tokio::spawn(async move {
                     // delete image
-                    match ctx.storage_client.delete_path(old_image).await {
+                    let deleted = match ctx.storage.delete(&old_image).await {
+                        Ok(()) => delete_variants(&*ctx.storage, &old_image).await,
+                        Err(e) => Err(e),
+                    };
+                    match deleted {
                         Ok(()) => {}
                         Err(e) => {
                             tracing::error!("internal server error: {:?}", e)
                         }
                     };
+
+                    if let Err(e) = sqlx::query!("delete from upload where key = $1", old_image)
+                        .execute(&*ctx.db_pool)
+                        .await
+                    {
+                        tracing::error!("Database error: {:?}", e)
+                    }
                 });
             }
         }
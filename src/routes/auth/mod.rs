@@ -2,11 +2,19 @@ use axum::{
     routing::{delete, get, patch, post},
     Router,
 };
-use email::{add_email, delete_user_email, list_user_email, update_email_to_primary};
+use email::{
+    add_email, cancel_primary_email_change, delete_user_email, list_primary_email_changes,
+    list_user_email, update_email_to_primary,
+};
 use me::{complete_me_profile, get_me_profile, update_me_profile};
 use password::{change_password, forgot_password, reset_password};
+use phone::{
+    add_phone, delete_user_phone, list_user_phones, resend_phone_verification,
+    send_phone_login_code, verify_phone,
+};
 use register::register_user;
 use session::{list_active_sessions, revoke_session, revoke_session_by_id};
+use social::{confirm_social_link, link_social_login, list_social_logins, unlink_social_login};
 use utoipa::OpenApi;
 use verify::{resend_email_verification, verify_email};
 
@@ -15,8 +23,10 @@ use crate::app::ApiContext;
 pub mod email;
 pub mod me;
 pub mod password;
+pub mod phone;
 pub mod register;
 pub mod session;
+pub mod social;
 pub mod verify;
 
 pub fn router() -> Router<ApiContext> {
@@ -31,9 +41,23 @@ pub fn router() -> Router<ApiContext> {
             "/auth/emails/{token}/primary",
             patch(update_email_to_primary),
         )
+        .route(
+            "/auth/emails/primary/changes",
+            get(list_primary_email_changes),
+        )
+        .route("/auth/phones", post(add_phone).get(list_user_phones))
+        .route("/auth/phones/{id}", delete(delete_user_phone))
+        .route("/auth/phones/verify", post(verify_phone))
+        .route("/auth/phones/{id}/resend", post(resend_phone_verification))
         .route("/auth/change-password", post(change_password))
         .route("/auth/sessions", get(list_active_sessions))
         .route("/auth/sessions/revoke", delete(revoke_session))
+        .route(
+            "/auth/social",
+            get(list_social_logins).post(link_social_login),
+        )
+        .route("/auth/social/confirm", post(confirm_social_link))
+        .route("/auth/social/{id}", delete(unlink_social_login))
 }
 
 // Called when the user is logging out from the Next.js server
@@ -46,6 +70,11 @@ pub fn public_router() -> Router<ApiContext> {
         .route("/auth/users", post(register_user))
         .route("/auth/forgot-password", post(forgot_password))
         .route("/auth/reset-password", post(reset_password))
+        .route("/auth/phones/login-code", post(send_phone_login_code))
+        .route(
+            "/auth/emails/primary/cancel",
+            post(cancel_primary_email_change),
+        )
 }
 
 #[derive(OpenApi)]
@@ -57,6 +86,14 @@ pub fn public_router() -> Router<ApiContext> {
     verify::verify_email,
     verify::resend_email_verification,
     email::update_email_to_primary,
+    email::cancel_primary_email_change,
+    email::list_primary_email_changes,
+    phone::add_phone,
+    phone::list_user_phones,
+    phone::verify_phone,
+    phone::resend_phone_verification,
+    phone::delete_user_phone,
+    phone::send_phone_login_code,
     password::forgot_password,
     password::reset_password,
     password::change_password,
@@ -65,6 +102,10 @@ pub fn public_router() -> Router<ApiContext> {
     me::update_me_profile,
     session::list_active_sessions,
     session::revoke_session,
-    session::revoke_session_by_id
+    session::revoke_session_by_id,
+    social::list_social_logins,
+    social::link_social_login,
+    social::confirm_social_link,
+    social::unlink_social_login
 ))]
 pub struct AuthApi;
@@ -12,11 +12,10 @@ use crate::{
         email::client::EmailClient,
         error::AppError,
         extrator::{AuthUser, ValidatedJson},
-        otp::{email_forgot_otp::EmailForgotOtp, OtpManager},
-        utils::validation::validate_password,
+        otp::store::{OtpPurpose, OtpStore},
+        utils::{types::Locale, validation::validate_password},
         ApiContext,
     },
-    config::Stage,
     routes::docs::AUTH_TAG,
 };
 
@@ -59,21 +58,29 @@ pub async fn forgot_password(
     ctx: State<ApiContext>,
     ValidatedJson(req): ValidatedJson<ForgotPasswordInput>,
 ) -> Result<(), AppError> {
-    let email_ok = check_email(&req.email, &ctx.db_pool).await?;
-    if !email_ok {
+    let Some(locale) = check_email(&req.email, &ctx.db_pool).await? else {
         return Ok(());
-    }
+    };
 
-    // generate otp
-    let otp_manager = EmailForgotOtp {
-        should_hash: ctx.config.stage == Stage::Prod,
+    let purpose = OtpPurpose::PasswordReset;
+    let token = match OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .issue(purpose, &req.email, &req.email)
+        .await
+    {
+        Ok(token) => token,
+        // Same response as unknown emails, the last instruction is still valid
+        Err(AppError::TooManyRequests { .. }) => return Ok(()),
+        Err(e) => return Err(e),
     };
-    let token = otp_manager.generate_otp();
-    otp_manager
-        .store_data(&token, &ctx.redis_client, &req.email)
+
+    let email_content = ctx
+        .email_client
+        .build_reset_password(&token, purpose.ttl().whole_hours())
         .await?;
 
-    EmailForgotOtp::send_email(&ctx.email_client, &token, &req.email).await?;
+    ctx.email_client
+        .queue_email(&req.email, locale, email_content, &*ctx.db_pool)
+        .await?;
 
     Ok(())
 }
@@ -96,11 +103,11 @@ pub async fn reset_password(
     ctx: State<ApiContext>,
     ValidatedJson(req): ValidatedJson<ResetPasswordInput>,
 ) -> Result<StatusCode, AppError> {
-    let otp_manager = EmailForgotOtp {
-        should_hash: ctx.config.stage == Stage::Prod,
-    };
+    let email = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .consume_token(OtpPurpose::PasswordReset, &req.token)
+        .await?;
 
-    match otp_manager.get_data(&req.token, &ctx.redis_client).await? {
+    match email {
         Some(email) => {
             let password_hash = compute_password_hash(req.new_password).await?;
             let user_id = reset_user_password(&password_hash, &email, &ctx.db_pool).await?;
@@ -115,25 +122,21 @@ pub async fn reset_password(
     }
 }
 
-async fn check_email(email: &str, pool: &PgPool) -> Result<bool, AppError> {
-    let row = sqlx::query_scalar!(
+/// Locale of the owner when the email is verified
+async fn check_email(email: &str, pool: &PgPool) -> Result<Option<Locale>, AppError> {
+    let locale = sqlx::query_scalar!(
         r#"
-            select verified 
-            from email
-            where email = $1
+            select u.locale as "locale: Locale"
+            from email e
+            inner join "user" u using (user_id)
+            where e.email = $1 and e.verified = true
         "#,
         email
     )
-    .fetch_one(pool)
-    .await;
-
-    match row {
-        Ok(v) => Ok(v),
-        Err(err) => match err {
-            sqlx::Error::RowNotFound => Ok(false),
-            e => Err(AppError::from(e)),
-        },
-    }
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(locale)
 }
 
 #[tracing::instrument(name = "Updating password using email", skip_all)]
@@ -208,18 +211,19 @@ pub async fn change_password(
     Ok(StatusCode::NO_CONTENT)
 }
 
-#[tracing::instrument(name = "Sending password changed notification", skip_all)]
+#[tracing::instrument(name = "Queue password changed notification", skip_all)]
 async fn send_password_notification_email(
     user_id: &Uuid,
     pool: &PgPool,
     email_client: &EmailClient,
     cause_email: &str,
 ) {
-    if let Ok(primary_email) = sqlx::query_scalar!(
+    if let Ok(primary) = sqlx::query!(
         r#"
-            select email
-            from email
-            where user_id = $1 and is_primary = true
+            select e.email, u.locale as "locale: Locale"
+            from email e
+            inner join "user" u using (user_id)
+            where e.user_id = $1 and e.is_primary = true
         "#,
         user_id
     )
@@ -227,7 +231,9 @@ async fn send_password_notification_email(
     .await
     {
         if let Ok(email_content) = email_client.build_password_changed(cause_email).await {
-            let _ = email_client.send_email(&primary_email, email_content).await;
+            let _ = email_client
+                .queue_email(&primary.email, primary.locale, email_content, pool)
+                .await;
         }
     }
 }
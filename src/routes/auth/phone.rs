@@ -0,0 +1,424 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    app::{
+        email::client::get_user_locale,
+        error::{AppError, ResultExt},
+        extrator::{AuthUser, ValidatedJson},
+        otp::store::{OtpPurpose, OtpStore},
+        utils::{
+            types::{Locale, Timestamptz},
+            validation::{validate_password, PHONE_REGEX},
+        },
+        ApiContext,
+    },
+    routes::docs::PHONE_TAG,
+};
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct AddPhoneInput {
+    /// E.164 format, e.g. `+97699112233`
+    #[validate(regex(path = *PHONE_REGEX))]
+    new_phone: String,
+    #[schema(value_type = String)]
+    password: SecretString,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct VerifyPhoneInput {
+    #[validate(length(equal = 6))]
+    code: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct PhoneLoginCodeInput {
+    #[validate(regex(path = *PHONE_REGEX))]
+    phone: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Phone {
+    phone_id: Uuid,
+    phone: String,
+    verified: bool,
+    is_primary: bool,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: Timestamptz,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    confirmation_sent_at: Option<Timestamptz>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/phones",
+    tag = PHONE_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = AddPhoneInput,
+    responses(
+        (status = 201, description = "Successful created, verification code sent"),
+        (status = 401, description = "Unauthorized"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Add phone", skip_all, fields(phone = ?req.new_phone))]
+pub async fn add_phone(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    ValidatedJson(req): ValidatedJson<AddPhoneInput>,
+) -> Result<StatusCode, AppError> {
+    validate_password(req.password, &auth_user.user_id, &ctx.db_pool).await?;
+
+    let mut tx = ctx.db_pool.begin().await?;
+
+    let phone_count = sqlx::query_scalar!(
+        "select count(*) from phone where user_id = $1",
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .unwrap_or_default();
+
+    if phone_count >= ctx.config.sms.account_phone_limit.into() {
+        return Err(AppError::unprocessable_entity([("phone", "limit")]));
+    }
+
+    let taken = sqlx::query_scalar!(
+        r#"select exists(select 1 from phone where phone = $1 and verified = true) as "taken!""#,
+        req.new_phone
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if taken {
+        return Err(AppError::unprocessable_entity([("phone", "taken")]));
+    }
+
+    sqlx::query!(
+        r#"
+            insert into phone (phone, user_id, confirmation_sent_at)
+            values ($1, $2, now())
+        "#,
+        req.new_phone,
+        auth_user.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .on_constraint("phone_user_id_phone_key", |_| {
+        AppError::unprocessable_entity([("phone", "taken")])
+    })?;
+
+    send_verification_code(&ctx, &auth_user.user_id, &req.new_phone, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn send_verification_code(
+    ctx: &ApiContext,
+    user_id: &Uuid,
+    phone: &str,
+    tx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+) -> Result<(), AppError> {
+    let purpose = OtpPurpose::PhoneVerify;
+    let code = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .issue(purpose, &user_id.to_string(), phone)
+        .await?;
+
+    let locale = get_user_locale(user_id, &mut **tx).await?;
+    ctx.sms_client
+        .send_verification_code(phone, &code, purpose.ttl().whole_minutes(), locale)
+        .await?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/phones",
+    tag = PHONE_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    responses(
+        (status = 200, description = "Successful", body = Vec<Phone>),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List user phones", skip_all, fields(auth_user = ?auth_user))]
+pub async fn list_user_phones(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+) -> Result<Json<Vec<Phone>>, AppError> {
+    let rows = sqlx::query!(
+        r#"
+            select phone_id, phone, verified, is_primary, created_at, confirmation_sent_at
+            from phone
+            where user_id = $1
+            order by created_at
+        "#,
+        auth_user.user_id
+    )
+    .fetch_all(&*ctx.db_pool)
+    .await?;
+
+    let phones = rows
+        .into_iter()
+        .map(|row| Phone {
+            phone_id: row.phone_id,
+            phone: row.phone,
+            verified: row.verified,
+            is_primary: row.is_primary,
+            created_at: Timestamptz(row.created_at),
+            confirmation_sent_at: row.confirmation_sent_at.map(Timestamptz),
+        })
+        .collect();
+
+    Ok(Json(phones))
+}
+
+#[utoipa::path(
+    post,
+    path = "/phones/verify",
+    tag = PHONE_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = VerifyPhoneInput,
+    responses(
+        (status = 204, description = "Successful verified"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Code expired or invalid, or the phone was removed"),
+        (status = 422, description = "Invalid input, or verified by another account", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Verify phone", skip_all)]
+pub async fn verify_phone(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    ValidatedJson(req): ValidatedJson<VerifyPhoneInput>,
+) -> Result<StatusCode, AppError> {
+    let phone = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .consume(
+            OtpPurpose::PhoneVerify,
+            &auth_user.user_id.to_string(),
+            &req.code,
+        )
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    // The first verified phone becomes primary
+    let result = sqlx::query!(
+        r#"
+            update phone
+            set verified = true,
+                confirmation_sent_at = null,
+                is_primary = not exists (
+                    select 1 from phone p
+                    where p.user_id = $2 and p.is_primary = true
+                )
+            where phone = $1 and user_id = $2 and verified = false
+        "#,
+        phone,
+        auth_user.user_id
+    )
+    .execute(&*ctx.db_pool)
+    .await
+    .on_constraint("phone_verified_phone_key", |_| {
+        AppError::unprocessable_entity([("phone", "taken")])
+    })?;
+
+    // Removed since the code was sent
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/phones/{id}/resend",
+    tag = PHONE_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Phone database id")
+    ),
+    responses(
+        (status = 204, description = "Successful sent code"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Phone not found"),
+        (status = 422, description = "Phone already verified", body = AppError),
+        (status = 429, description = "Sent recently, see Retry-After"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Resend phone verification", skip_all, fields(id = ?id))]
+pub async fn resend_phone_verification(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    let row = sqlx::query!(
+        r#"
+            update phone
+            set confirmation_sent_at = now()
+            where phone_id = $1 and user_id = $2
+            returning phone, verified
+        "#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if row.verified {
+        return Err(AppError::unprocessable_entity([("phone", "verified")]));
+    }
+
+    send_verification_code(&ctx, &auth_user.user_id, &row.phone, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/phones/{id}",
+    tag = PHONE_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Phone database id")
+    ),
+    responses(
+        (status = 204, description = "Successfully deleted"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Phone not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Delete user phone", skip_all, fields(id = ?id))]
+pub async fn delete_user_phone(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    let was_primary = sqlx::query_scalar!(
+        r#"
+            delete from phone
+            where phone_id = $1 and user_id = $2
+            returning is_primary
+        "#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Another verified phone takes over
+    if was_primary {
+        sqlx::query!(
+            r#"
+                update phone
+                set is_primary = true
+                where phone_id = (
+                    select phone_id from phone
+                    where user_id = $1 and verified = true
+                    order by created_at
+                    limit 1
+                )
+            "#,
+            auth_user.user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/phones/login-code",
+    tag = PHONE_TAG,
+    request_body = PhoneLoginCodeInput,
+    responses(
+        (status = 204, description = "Code sent when the phone is verified"),
+        (status = 404, description = "Phone login is disabled, codes can't be sent"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Send phone login code", skip_all, fields(phone = ?req.phone))]
+pub async fn send_phone_login_code(
+    ctx: State<ApiContext>,
+    ValidatedJson(req): ValidatedJson<PhoneLoginCodeInput>,
+) -> Result<StatusCode, AppError> {
+    if !ctx.config.sms_delivers() {
+        return Err(AppError::NotFound);
+    }
+
+    let owner = sqlx::query!(
+        r#"
+            select u.user_id, u.locale as "locale: Locale"
+            from phone p
+            inner join "user" u using (user_id)
+            where p.phone = $1 and p.verified = true
+        "#,
+        req.phone
+    )
+    .fetch_optional(&*ctx.db_pool)
+    .await?;
+
+    // Same response for unknown phones, so numbers can't be probed
+    let Some(owner) = owner else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+
+    let purpose = OtpPurpose::PhoneLogin;
+    let code = match OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .issue(purpose, &req.phone, &owner.user_id.to_string())
+        .await
+    {
+        Ok(code) => code,
+        // Unknown phones never cool down, so neither do known ones
+        Err(AppError::TooManyRequests { .. }) => return Ok(StatusCode::NO_CONTENT),
+        Err(e) => return Err(e),
+    };
+
+    ctx.sms_client
+        .send_login_code(
+            &req.phone,
+            &code,
+            purpose.ttl().whole_minutes(),
+            owner.locale,
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -8,13 +8,12 @@ use crate::{
     app::{
         auth::password::compute_password_hash,
         error::{AppError, ResultExt},
-        extrator::ValidatedJson,
-        otp::{email_otp::EmailVerifyOtp, OtpManager},
-        utils::{avatar_generator::generate_avatar, validation::USERNAME_REGEX},
+        extrator::{ExtractLocale, ValidatedJson},
+        storage::avatar::set_default_avatar,
+        utils::{types::Locale, validation::USERNAME_REGEX},
         ApiContext,
     },
-    config::Stage,
-    routes::docs::AUTH_TAG,
+    routes::{auth::verify::send_email_verification, docs::AUTH_TAG},
 };
 
 #[derive(Debug, Deserialize, Validate, ToSchema)]
@@ -42,6 +41,7 @@ pub struct RegisterUserInput {
 #[tracing::instrument(name = "Register user", skip_all, fields(req = ?req))]
 pub async fn register_user(
     ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
     ValidatedJson(req): ValidatedJson<RegisterUserInput>,
 ) -> Result<(), AppError> {
     let password_hash = compute_password_hash(req.password).await?;
@@ -49,13 +49,13 @@ pub async fn register_user(
 
     let user_id = sqlx::query_scalar!(
         r#"
-            insert into "user" (username, password_hash, image)
+            insert into "user" (username, password_hash, locale)
             values ($1, $2, $3)
             returning user_id
         "#,
         req.username,
         password_hash,
-        generate_avatar(&req.username)
+        locale as Locale
     )
     .fetch_one(&mut *tx)
     .await
@@ -77,20 +77,12 @@ pub async fn register_user(
         AppError::unprocessable_entity([("email", "taken")])
     })?;
 
-    let otp_manager = EmailVerifyOtp {
-        user_id,
-        should_hash: ctx.config.stage == Stage::Prod,
-    };
-    let token = otp_manager.generate_otp();
-
-    otp_manager
-        .store_data(&token, &ctx.redis_client, &req.email)
-        .await?;
-
-    EmailVerifyOtp::send_email(&ctx.email_client, &token, &req.email).await?;
+    send_email_verification(&ctx, &user_id, &req.email, locale, &mut *tx).await?;
 
     // Store unverified user
     tx.commit().await?;
 
+    set_default_avatar(&user_id, &*ctx.storage, &ctx.db_pool).await;
+
     Ok(())
 }
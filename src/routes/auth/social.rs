@@ -0,0 +1,348 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    app::{
+        error::AppError,
+        extrator::{AuthUser, ValidatedJson},
+        oauth::{
+            confirm_pending_link, fetch_provider_user, get_social_login_user,
+            link::PendingSocialLink, upsert_email, upsert_social_login, OAuthProvider,
+            SocialProvider,
+        },
+        utils::types::Timestamptz,
+        ApiContext,
+    },
+    routes::{
+        docs::SOCIAL_TAG,
+        oauth::{take_oauth_state, AssertionProvider},
+    },
+};
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct LinkSocialInput {
+    code: String,
+    /// State returned by `/oauth/{provider}/authorize`
+    state: String,
+    provider: AssertionProvider,
+    /// Config key of the provider, required when `provider` is `oidc`
+    oidc_provider: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ConfirmSocialLinkInput {
+    /// Token returned when the social login needed confirmation
+    link_token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SocialLogin {
+    social_login_id: String,
+    provider: AssertionProvider,
+    oidc_provider: Option<String>,
+    email: Option<String>,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: Timestamptz,
+}
+
+struct SocialLoginFromQuery {
+    social_login_id: Uuid,
+    provider: AssertionProvider,
+    oidc_provider: Option<String>,
+    email: Option<String>,
+    created_at: OffsetDateTime,
+}
+
+impl SocialLoginFromQuery {
+    fn into_social_login(self) -> SocialLogin {
+        SocialLogin {
+            social_login_id: self.social_login_id.to_string(),
+            provider: self.provider,
+            oidc_provider: self.oidc_provider,
+            email: self.email,
+            created_at: Timestamptz(self.created_at),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/social",
+    tag = SOCIAL_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    responses(
+        (status = 200, description = "Successful", body = Vec<SocialLogin>),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List linked social logins", skip_all, fields(auth_user = ?auth_user))]
+pub async fn list_social_logins(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+) -> Result<Json<Vec<SocialLogin>>, AppError> {
+    let rows = sqlx::query_as!(
+        SocialLoginFromQuery,
+        r#"
+            select
+                s.social_login_id,
+                s.provider as "provider: AssertionProvider",
+                s.oidc_provider,
+                e.email as "email?",
+                s.created_at
+            from social_login s
+            left join email e using (email_id)
+            where s.user_id = $1
+            order by s.created_at
+        "#,
+        auth_user.user_id
+    )
+    .fetch(&*ctx.db_pool)
+    .map_ok(SocialLoginFromQuery::into_social_login)
+    .try_collect()
+    .await?;
+
+    Ok(Json(rows))
+}
+
+#[utoipa::path(
+    post,
+    path = "/social",
+    tag = SOCIAL_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = LinkSocialInput,
+    responses(
+        (status = 201, description = "Successfully linked"),
+        (status = 204, description = "Already linked to this account"),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Unimplemented or inactive provider"),
+        (status = 422, description = "Invalid input, or linked to another account", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Link social login", skip_all, fields(auth_user = ?auth_user, provider = ?req.provider))]
+pub async fn link_social_login(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    ValidatedJson(req): ValidatedJson<LinkSocialInput>,
+) -> Result<StatusCode, AppError> {
+    if req.provider == AssertionProvider::Oidc && req.oidc_provider.is_none() {
+        return Err(AppError::unprocessable_entity([(
+            "oidc_provider",
+            "missing",
+        )]));
+    }
+
+    let auth = take_oauth_state(
+        &req.state,
+        &req.provider,
+        req.oidc_provider.as_deref(),
+        &ctx,
+    )
+    .await?;
+
+    let provider = SocialProvider::build(&req.provider, req.oidc_provider.as_deref(), &ctx).await?;
+    let user_data = fetch_provider_user(&provider, &req.code, &auth, &ctx.http_client).await?;
+
+    let linked_user = get_social_login_user(
+        provider.provider(),
+        provider.oidc_provider(),
+        &user_data.id,
+        &ctx.db_pool,
+    )
+    .await?;
+
+    match linked_user {
+        Some(user_id) if user_id == auth_user.user_id => return Ok(StatusCode::NO_CONTENT),
+        Some(_) => return Err(AppError::unprocessable_entity([("provider", "taken")])),
+        None => {}
+    }
+
+    let mut tx = ctx.db_pool.begin().await?;
+
+    // Only attach the provider's email when it already belongs to this user,
+    // or when it's verified and nobody has claimed it yet
+    let email_id = match &user_data.email {
+        Some(email) => {
+            let owner = sqlx::query!(
+                r#"
+                    select email_id, user_id
+                    from email
+                    where email = $1
+                "#,
+                email
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            match owner {
+                Some(row) if row.user_id == auth_user.user_id => Some(row.email_id),
+                None if user_data.email_verified => {
+                    Some(upsert_email(email, &auth_user.user_id, true, &mut tx).await?)
+                }
+                _ => None,
+            }
+        }
+        None => None,
+    };
+
+    upsert_social_login(
+        email_id.as_ref(),
+        &auth_user.user_id,
+        provider.provider(),
+        provider.oidc_provider(),
+        &user_data.id,
+        &mut tx,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/social/confirm",
+    tag = SOCIAL_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = ConfirmSocialLinkInput,
+    responses(
+        (status = 204, description = "Successfully linked"),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Link token expired/missing"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Confirm social link", skip_all, fields(auth_user = ?auth_user))]
+pub async fn confirm_social_link(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    ValidatedJson(req): ValidatedJson<ConfirmSocialLinkInput>,
+) -> Result<StatusCode, AppError> {
+    let pending = PendingSocialLink::get(&req.link_token, &ctx.redis_client)
+        .await?
+        .filter(|pending| pending.user_id == auth_user.user_id)
+        .ok_or(AppError::NotFound)?;
+
+    let mut tx = ctx.db_pool.begin().await?;
+    confirm_pending_link(pending, &mut tx).await?;
+    tx.commit().await?;
+
+    PendingSocialLink::delete(&req.link_token, &ctx.redis_client).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/social/{id}",
+    tag = SOCIAL_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Social login database id")
+    ),
+    responses(
+        (status = 204, description = "Successfully unlinked"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Social login not found"),
+        (status = 422, description = "Last login method of the account", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Unlink social login", skip_all, fields(auth_user = ?auth_user, id = ?id))]
+pub async fn unlink_social_login(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = ctx.db_pool.begin().await?;
+
+    // Lock the user row so concurrent unlinks can't remove every login method
+    let reset_password = sqlx::query_scalar!(
+        r#"
+            select reset_password
+            from "user"
+            where user_id = $1
+            for update
+        "#,
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query_scalar!(
+        r#"
+            select social_login_id
+            from social_login
+            where social_login_id = $1 and user_id = $2
+        "#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    let linked_count = sqlx::query_scalar!(
+        "select count(*) from social_login where user_id = $1",
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .unwrap_or_default();
+
+    // A verified phone logs in with a code, unless the codes can't be delivered
+    let phone_count = if ctx.config.sms_delivers() {
+        sqlx::query_scalar!(
+            r#"select count(*) as "count!" from phone where user_id = $1 and verified = true"#,
+            auth_user.user_id
+        )
+        .fetch_one(&mut *tx)
+        .await?
+    } else {
+        0
+    };
+
+    // Users that never set a password can only log in with their providers or phones
+    if Some(true) == reset_password && linked_count + phone_count <= 1 {
+        return Err(AppError::unprocessable_entity([(
+            "provider",
+            "last_login_method",
+        )]));
+    }
+
+    sqlx::query!(
+        r#"
+            delete
+            from social_login
+            where social_login_id = $1
+        "#,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -4,18 +4,20 @@ use axum::{
     http::StatusCode,
 };
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use utoipa::ToSchema;
+use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     app::{
+        email::client::get_user_locale,
         error::AppError,
         extrator::{AuthUser, ValidatedJson},
-        otp::{email_otp::EmailVerifyOtp, OtpManager},
+        otp::store::{OtpPurpose, OtpStore},
+        utils::types::Locale,
         ApiContext,
     },
-    config::Stage,
     routes::docs::EMAIL_TAG,
 };
 
@@ -32,7 +34,7 @@ use crate::{
     responses(
         (status = 205, description = "Successful verified"),
         (status = 401, description = "Unauthorized"),
-        (status = 404, description = "Not found"),
+        (status = 404, description = "Code expired or invalid"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -42,12 +44,13 @@ pub async fn verify_email(
     ctx: State<ApiContext>,
     Path(token): Path<String>,
 ) -> Result<(), AppError> {
-    let otp_manager = EmailVerifyOtp {
-        user_id: auth_user.user_id,
-        should_hash: ctx.config.stage == Stage::Prod,
-    };
-
-    let email_to_verify = otp_manager.get_data(&token, &ctx.redis_client).await?;
+    let email_to_verify = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .consume(
+            OtpPurpose::EmailVerify,
+            &auth_user.user_id.to_string(),
+            &token,
+        )
+        .await?;
 
     match email_to_verify {
         Some(email) => {
@@ -95,6 +98,7 @@ pub struct ResendEmailVerificationInput {
         (status = 400, description = "Bad request"),
         (status = 401, description = "Unauthorized"),
         (status = 422, description = "Invalid input", body = AppError),
+        (status = 429, description = "Sent recently, see Retry-After"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -122,23 +126,9 @@ pub async fn resend_email_verification(
         return Err(AppError::unprocessable_entity([("email", "verified")]));
     }
 
-    let otp_manager = EmailVerifyOtp {
-        user_id: auth_user.user_id,
-        should_hash: ctx.config.stage == Stage::Prod,
-    };
-
-    let otps = otp_manager.get_keys(&ctx.redis_client, &req.email).await?;
-    let token = if otps.is_empty() {
-        let new_token = otp_manager.generate_otp();
-        otp_manager
-            .store_data(&new_token, &ctx.redis_client, &req.email)
-            .await?;
-        new_token
-    } else {
-        otps.first().unwrap().to_string()
-    };
-
-    EmailVerifyOtp::send_email(&ctx.email_client, &token, &req.email).await?;
+    // The new code replaces the one sent before
+    let locale = get_user_locale(&auth_user.user_id, &mut *tx).await?;
+    send_email_verification(&ctx, &auth_user.user_id, &req.email, locale, &mut *tx).await?;
 
     sqlx::query!(
         r#"
@@ -156,3 +146,28 @@ pub async fn resend_email_verification(
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Issues a verification code for the email and queues it to the address
+pub(super) async fn send_email_verification(
+    ctx: &ApiContext,
+    user_id: &Uuid,
+    email: &str,
+    locale: Locale,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), AppError> {
+    let purpose = OtpPurpose::EmailVerify;
+    let code = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .issue(purpose, &user_id.to_string(), email)
+        .await?;
+
+    let email_content = ctx
+        .email_client
+        .build_email_confirmation(&code, purpose.ttl().whole_hours())
+        .await?;
+
+    ctx.email_client
+        .queue_email(email, locale, email_content, executor)
+        .await?;
+
+    Ok(())
+}
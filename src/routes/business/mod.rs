@@ -0,0 +1,286 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use nearby::list_nearby_businesses;
+use search::search_businesses;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, Postgres, QueryBuilder};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::app::{
+    error::AppError,
+    extrator::ExtractLocale,
+    utils::types::{CPagination, GeoPoint, Locale, Timestamptz},
+    ApiContext,
+};
+
+use super::docs::BUSINESS_TAG;
+
+pub mod nearby;
+pub mod search;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_businesses,
+    get_business,
+    nearby::list_nearby_businesses,
+    search::search_businesses
+))]
+pub struct BusinessApi;
+
+pub fn router() -> Router<ApiContext> {
+    Router::new()
+        .route("/businesses", get(list_businesses))
+        .route("/businesses/nearby", get(list_nearby_businesses))
+        .route("/businesses/search", get(search_businesses))
+        .route("/businesses/{id}", get(get_business))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListBusinessesInput {
+    /// Only businesses in the category or one of its subcategories
+    category_id: Option<Uuid>,
+    /// Only businesses with an address in the area or one of the areas inside it
+    area_id: Option<Uuid>,
+    #[param(value_type = Option<String>)]
+    cursor: Option<CPagination>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BusinessListResponse {
+    data: Vec<Business>,
+    #[schema(value_type = Option<String>)]
+    next_cursor: Option<CPagination>,
+}
+
+/// Published business, texts in the requested locale or `en` when missing
+#[derive(Serialize, ToSchema, FromRow)]
+pub struct Business {
+    pub business_id: Uuid,
+    pub name: String,
+    #[sqlx(json)]
+    pub categories: Vec<BusinessCategory>,
+    #[sqlx(json)]
+    pub addresses: Vec<BusinessAddress>,
+    #[schema(value_type = String)]
+    pub created_at: Timestamptz,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BusinessCategory {
+    pub category_id: Uuid,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BusinessAddress {
+    pub address_id: Uuid,
+    pub line_1: String,
+    pub line_2: Option<String>,
+    pub location: Option<GeoPoint>,
+    pub area_id: Option<Uuid>,
+}
+
+/// Selects the published businesses as `Business`, filters are appended with `and`
+pub fn select_businesses(locale: Locale) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new("");
+    push_select_businesses(&mut query_builder, locale);
+
+    query_builder
+}
+
+/// Pushes the `select_businesses` query, e.g. as a subquery
+pub fn push_select_businesses(query_builder: &mut QueryBuilder<'static, Postgres>, locale: Locale) {
+    query_builder.push(
+        r#"
+            select
+                b.business_id,
+                coalesce(nullif(b.name->l.locale, ''), b.name->'en') as name,
+                coalesce(
+                    (
+                        select json_agg(
+                            json_build_object(
+                                'category_id', c.category_id,
+                                'name', coalesce(nullif(c.name->l.locale, ''), c.name->'en')
+                            )
+                            order by c.category_id
+                        )
+                        from business_category bc
+                            join category c on c.category_id = bc.category_id
+                        where bc.business_id = b.business_id
+                    ),
+                    '[]'
+                ) as categories,
+                coalesce(
+                    (
+                        select json_agg(
+                            json_build_object(
+                                'address_id', a.address_id,
+                                'line_1', coalesce(nullif(a.line_1->l.locale, ''), a.line_1->'en'),
+                                'line_2', coalesce(nullif(a.line_2->l.locale, ''), a.line_2->'en'),
+                                'location', case when a.geom is not null then
+                                    json_build_object('lat', st_y(a.geom), 'lng', st_x(a.geom))
+                                end,
+                                'area_id', a.area_id
+                            )
+                            order by a.created_at, a.address_id
+                        )
+                        from address a
+                        where a.business_id = b.business_id
+                    ),
+                    '[]'
+                ) as addresses,
+                b.created_at
+            from business b
+                cross join (select
+        "#,
+    );
+    query_builder.push_bind(locale.to_string());
+    query_builder.push("::text as locale) l where b.published_at is not null ");
+}
+
+/// Only businesses in the category or one of its subcategories
+pub fn push_category_filter(
+    query_builder: &mut QueryBuilder<'static, Postgres>,
+    category_id: Uuid,
+) {
+    query_builder.push(
+        r#"
+            and exists (
+                with recursive categories as (
+                    select category_id from category where category_id =
+        "#,
+    );
+    query_builder.push_bind(category_id);
+    query_builder.push(
+        r#"
+                    union all
+                    select c.category_id
+                    from category c
+                        join categories on c.parent_id = categories.category_id
+                )
+                select 1
+                from business_category bc
+                    join categories on categories.category_id = bc.category_id
+                where bc.business_id = b.business_id
+            )
+        "#,
+    );
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    tag = BUSINESS_TAG,
+    params(ListBusinessesInput),
+    responses(
+        (status = 200, description = "Published businesses, newest first", body = BusinessListResponse),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List businesses", skip_all, fields(req = ?req))]
+pub async fn list_businesses(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Query(req): Query<ListBusinessesInput>,
+) -> Result<Json<BusinessListResponse>, AppError> {
+    let page_size: usize = 25;
+    let cursor_size: i64 = (page_size + 1) as i64;
+
+    let mut query_builder = select_businesses(locale);
+
+    if let Some(category_id) = req.category_id {
+        push_category_filter(&mut query_builder, category_id);
+    }
+
+    if let Some(area_id) = req.area_id {
+        query_builder.push(
+            r#"
+                and exists (
+                    with recursive areas as (
+                        select area_id from address_area where area_id =
+            "#,
+        );
+        query_builder.push_bind(area_id);
+        query_builder.push(
+            r#"
+                        union all
+                        select aa.area_id
+                        from address_area aa
+                            join areas on aa.parent_id = areas.area_id
+                    )
+                    select 1
+                    from address a
+                        join areas on areas.area_id = a.area_id
+                    where a.business_id = b.business_id
+                )
+            "#,
+        );
+    }
+
+    if let Some(c) = req.cursor {
+        query_builder.push(" and (b.created_at, b.business_id) <= (");
+        let mut separated = query_builder.separated(", ");
+        separated.push_bind(c.created_at);
+        separated.push_bind(c.id);
+        separated.push_unseparated(") ");
+    }
+
+    query_builder.push(" order by b.created_at desc, b.business_id desc ");
+
+    query_builder.push(" limit ");
+    query_builder.push_bind(cursor_size);
+
+    let query = query_builder.build_query_as::<Business>();
+    let mut next_res = query.fetch_all(&*ctx.db_pool).await?;
+
+    let next_cursor: Option<CPagination> = if next_res.len() < page_size + 1 {
+        None
+    } else {
+        let next_item = next_res.pop();
+        next_item.map(|item| CPagination {
+            id: item.business_id,
+            created_at: item.created_at.clone(),
+        })
+    };
+
+    Ok(Json(BusinessListResponse {
+        data: next_res,
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    tag = BUSINESS_TAG,
+    params(
+        ("id" = String, Path, description = "Business database id")
+    ),
+    responses(
+        (status = 200, description = "Published business", body = Business),
+        (status = 404, description = "Business not found or not published"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Get published business", skip_all, fields(id = ?id))]
+pub async fn get_business(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Business>, AppError> {
+    let mut query_builder = select_businesses(locale);
+    query_builder.push(" and b.business_id = ");
+    query_builder.push_bind(id);
+
+    let business = query_builder
+        .build_query_as::<Business>()
+        .fetch_one(&*ctx.db_pool)
+        .await?;
+
+    Ok(Json(business))
+}
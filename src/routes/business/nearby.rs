@@ -0,0 +1,150 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::app::{
+    error::AppError, extrator::ExtractLocale, utils::types::ScorePagination, ApiContext,
+};
+
+use super::{push_category_filter, push_select_businesses, Business, BUSINESS_TAG};
+
+const MAX_RADIUS: f64 = 50_000.0;
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+pub struct NearbyBusinessesInput {
+    #[validate(range(min = -90.0, max = 90.0))]
+    lat: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    lng: f64,
+    /// Search radius in meters, 1000 by default
+    #[serde(default = "default_radius")]
+    #[validate(range(exclusive_min = 0.0, max = MAX_RADIUS))]
+    radius: f64,
+    /// Only businesses in the category or one of its subcategories
+    category_id: Option<Uuid>,
+    #[param(value_type = Option<String>)]
+    cursor: Option<ScorePagination>,
+}
+
+fn default_radius() -> f64 {
+    1000.0
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NearbyBusinessListResponse {
+    data: Vec<NearbyBusiness>,
+    #[schema(value_type = Option<String>)]
+    next_cursor: Option<ScorePagination>,
+}
+
+#[derive(Serialize, ToSchema, FromRow)]
+pub struct NearbyBusiness {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub business: Business,
+    /// Meters to the closest address of the business
+    pub distance: f64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/nearby",
+    tag = BUSINESS_TAG,
+    params(NearbyBusinessesInput),
+    responses(
+        (status = 200, description = "Published businesses within the radius, closest first", body = NearbyBusinessListResponse),
+        (status = 400, description = "Bad request"),
+        (status = 422, description = "Invalid coordinates or radius"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "List nearby businesses", skip_all, fields(req = ?req))]
+pub async fn list_nearby_businesses(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Query(req): Query<NearbyBusinessesInput>,
+) -> Result<Json<NearbyBusinessListResponse>, AppError> {
+    req.validate()?;
+
+    let page_size: usize = 25;
+    let cursor_size: i64 = (page_size + 1) as i64;
+
+    // Closest address of each business, `address_geom_geography_idx` covers the `st_dwithin`
+    let mut query_builder = QueryBuilder::new(
+        r#"
+            with origin as (
+                select st_setsrid(st_makepoint(
+        "#,
+    );
+    let mut separated = query_builder.separated(", ");
+    separated.push_bind(req.lng);
+    separated.push_bind(req.lat);
+    separated.push_unseparated(
+        r#"
+                ), 4326)::geography as point
+            ),
+            nearby as (
+                select distinct on (a.business_id)
+                    a.business_id,
+                    st_distance(a.geom::geography, origin.point) as distance
+                from address a
+                    cross join origin
+                where st_dwithin(a.geom::geography, origin.point,
+        "#,
+    );
+    query_builder.push_bind(req.radius);
+    query_builder.push(
+        r#"
+                )
+                order by a.business_id, distance
+            )
+            select b.*, n.distance
+            from (
+        "#,
+    );
+
+    push_select_businesses(&mut query_builder, locale);
+
+    if let Some(category_id) = req.category_id {
+        push_category_filter(&mut query_builder, category_id);
+    }
+
+    query_builder.push(") b join nearby n on n.business_id = b.business_id ");
+
+    if let Some(c) = req.cursor {
+        query_builder.push(" where (n.distance, b.business_id) >= (");
+        let mut separated = query_builder.separated(", ");
+        separated.push_bind(c.score);
+        separated.push_bind(c.id);
+        separated.push_unseparated(") ");
+    }
+
+    query_builder.push(" order by n.distance, b.business_id ");
+
+    query_builder.push(" limit ");
+    query_builder.push_bind(cursor_size);
+
+    let query = query_builder.build_query_as::<NearbyBusiness>();
+    let mut next_res = query.fetch_all(&*ctx.db_pool).await?;
+
+    let next_cursor: Option<ScorePagination> = if next_res.len() < page_size + 1 {
+        None
+    } else {
+        let next_item = next_res.pop();
+        next_item.map(|item| ScorePagination {
+            id: item.business.business_id,
+            score: item.distance,
+        })
+    };
+
+    Ok(Json(NearbyBusinessListResponse {
+        data: next_res,
+        next_cursor,
+    }))
+}
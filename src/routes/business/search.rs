@@ -0,0 +1,202 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::app::{
+    error::AppError,
+    extrator::ExtractLocale,
+    utils::{
+        transliterate::highlight,
+        types::{Locale, LocalizedText, ScorePagination},
+    },
+    ApiContext,
+};
+
+use super::{push_category_filter, push_select_businesses, Business, BUSINESS_TAG};
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+pub struct SearchBusinessesInput {
+    /// Words of the business or category name, in Latin or Cyrillic
+    #[validate(length(min = 2, max = 100))]
+    q: String,
+    /// Only businesses in the category or one of its subcategories
+    category_id: Option<Uuid>,
+    #[param(value_type = Option<String>)]
+    cursor: Option<ScorePagination>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchBusinessListResponse {
+    data: Vec<SearchBusiness>,
+    #[schema(value_type = Option<String>)]
+    next_cursor: Option<ScorePagination>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchBusiness {
+    #[serde(flatten)]
+    pub business: Business,
+    /// Higher is a better match
+    pub rank: f64,
+    /// Name in the locale that matched, `None` when only a category matched
+    pub highlight: Option<SearchHighlight>,
+}
+
+/// Matched words are wrapped in `<mark>`, the rest of the name is HTML escaped
+#[derive(Serialize, ToSchema)]
+pub struct SearchHighlight {
+    pub locale: Locale,
+    pub name: String,
+}
+
+#[derive(FromRow)]
+struct SearchRow {
+    #[sqlx(flatten)]
+    business: Business,
+    rank: f64,
+    #[sqlx(json)]
+    names: LocalizedText,
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = BUSINESS_TAG,
+    params(SearchBusinessesInput),
+    responses(
+        (status = 200, description = "Published businesses matching the query, best match first", body = SearchBusinessListResponse),
+        (status = 400, description = "Bad request"),
+        (status = 422, description = "Query too short or too long"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Search businesses", skip_all, fields(req = ?req))]
+pub async fn search_businesses(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    Query(req): Query<SearchBusinessesInput>,
+) -> Result<Json<SearchBusinessListResponse>, AppError> {
+    req.validate()?;
+
+    let page_size: usize = 25;
+    let cursor_size: i64 = (page_size + 1) as i64;
+
+    // Both sides are compared transliterated, the trigram and full-text indexes are on `search_name`
+    let mut query_builder = QueryBuilder::new(
+        r#"
+            with query as (
+                select mn_latin(q) as text, websearch_to_tsquery('simple', mn_latin(q)) as ts
+                from (select
+        "#,
+    );
+    query_builder.push_bind(req.q.trim().to_string());
+    query_builder.push(
+        r#"
+                ::text as q) input
+            ),
+            matched_categories as (
+                select c.category_id, word_similarity(query.text, c.search_name) as similarity
+                from category c
+                    cross join query
+                where query.text <% c.search_name
+            ),
+            candidates as (
+                select b.business_id
+                from business b
+                    cross join query
+                where to_tsvector('simple', b.search_name) @@ query.ts
+                    or query.text <% b.search_name
+                union
+                select bc.business_id
+                from business_category bc
+                    join matched_categories mc on mc.category_id = bc.category_id
+            ),
+            ranked as (
+                select
+                    b.business_id,
+                    (
+                        ts_rank(to_tsvector('simple', b.search_name), query.ts)
+                        + word_similarity(query.text, b.search_name)
+                        + 0.5 * coalesce(
+                            (
+                                select max(mc.similarity)
+                                from business_category bc
+                                    join matched_categories mc on mc.category_id = bc.category_id
+                                where bc.business_id = b.business_id
+                            ),
+                            0
+                        )
+                    )::float8 as rank,
+                    hstore_to_json(b.name) as names
+                from candidates
+                    join business b on b.business_id = candidates.business_id
+                    cross join query
+            )
+            select b.*, r.rank, r.names
+            from (
+        "#,
+    );
+
+    push_select_businesses(&mut query_builder, locale);
+
+    if let Some(category_id) = req.category_id {
+        push_category_filter(&mut query_builder, category_id);
+    }
+
+    query_builder.push(") b join ranked r on r.business_id = b.business_id ");
+
+    if let Some(c) = req.cursor {
+        query_builder.push(" where (r.rank, b.business_id) <= (");
+        let mut separated = query_builder.separated(", ");
+        separated.push_bind(c.score);
+        separated.push_bind(c.id);
+        separated.push_unseparated(") ");
+    }
+
+    query_builder.push(" order by r.rank desc, b.business_id desc ");
+
+    query_builder.push(" limit ");
+    query_builder.push_bind(cursor_size);
+
+    let query = query_builder.build_query_as::<SearchRow>();
+    let mut next_res = query.fetch_all(&*ctx.db_pool).await?;
+
+    let next_cursor: Option<ScorePagination> = if next_res.len() < page_size + 1 {
+        None
+    } else {
+        let next_item = next_res.pop();
+        next_item.map(|item| ScorePagination {
+            id: item.business.business_id,
+            score: item.rank,
+        })
+    };
+
+    // The requested locale is highlighted when it matches, otherwise the first one that does
+    let locales: Vec<Locale> = std::iter::once(locale)
+        .chain(Locale::ALL.into_iter().filter(|l| *l != locale))
+        .collect();
+
+    let data = next_res
+        .into_iter()
+        .map(|row| {
+            let highlight = locales.iter().find_map(|&locale| {
+                let name = highlight(row.names.get(locale)?, &req.q)?;
+                Some(SearchHighlight { locale, name })
+            });
+
+            SearchBusiness {
+                business: row.business,
+                rank: row.rank,
+                highlight,
+            }
+        })
+        .collect();
+
+    Ok(Json(SearchBusinessListResponse { data, next_cursor }))
+}
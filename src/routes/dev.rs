@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::app::{
+    email::template::EmailTemplates, error::AppError, utils::types::Locale, ApiContext,
+};
+
+/// Only mounted in the `dev` stage
+pub fn router() -> Router<ApiContext> {
+    Router::new().route("/dev/emails/{template}", get(preview_email))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewFormat {
+    #[default]
+    Json,
+    Html,
+    Text,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewEmailQuery {
+    #[serde(default)]
+    locale: Locale,
+    #[serde(default)]
+    format: PreviewFormat,
+}
+
+/// Renders an email template with its sample data
+#[tracing::instrument(name = "Preview email", skip_all, fields(template = ?template))]
+pub async fn preview_email(
+    ctx: State<ApiContext>,
+    Path(template): Path<String>,
+    Query(query): Query<PreviewEmailQuery>,
+) -> Result<Response, AppError> {
+    let template: EmailTemplates = template.parse().map_err(|_| AppError::NotFound)?;
+    let rendered = ctx
+        .email_renderer
+        .render(&template, query.locale, &template.sample_data())?;
+
+    let res = match query.format {
+        PreviewFormat::Json => Json(rendered).into_response(),
+        PreviewFormat::Html => Html(rendered.html).into_response(),
+        PreviewFormat::Text => rendered.text.into_response(),
+    };
+
+    Ok(res)
+}
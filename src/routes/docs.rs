@@ -1,5 +1,8 @@
 use crate::app::ApiContext;
-use crate::routes::{admin::AdminApi, auth::AuthApi, oauth::OAuthApi, upload::UploadApi};
+use crate::routes::{
+    admin::AdminApi, area::AreaApi, auth::AuthApi, business::BusinessApi, oauth::OAuthApi,
+    upload::UploadApi, webhooks::WebhookApi,
+};
 use axum::{routing::get, Json, Router};
 use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
 use utoipa::openapi::Components;
@@ -11,9 +14,14 @@ pub fn router() -> Router<ApiContext> {
 
 pub const AUTH_TAG: &str = "auth";
 pub const EMAIL_TAG: &str = "email";
+pub const PHONE_TAG: &str = "phone";
 pub const SESSION_TAG: &str = "session";
+pub const SOCIAL_TAG: &str = "social";
 pub const UPLOAD_TAG: &str = "upload";
 pub const ADMIN_TAG: &str = "admin";
+pub const BUSINESS_TAG: &str = "business";
+pub const AREA_TAG: &str = "area";
+pub const WEBHOOK_TAG: &str = "webhook";
 
 #[derive(OpenApi)]
 #[openapi(
@@ -29,6 +37,15 @@ pub const ADMIN_TAG: &str = "admin";
         ),
         (
             path = "/admin", api = AdminApi
+        ),
+        (
+            path = "/businesses", api = BusinessApi
+        ),
+        (
+            path = "/areas", api = AreaApi
+        ),
+        (
+            path = "/webhooks", api = WebhookApi
         )
     ),
     modifiers(&SecurityAddon)
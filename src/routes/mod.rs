@@ -1,6 +1,11 @@
 pub mod admin;
+pub mod area;
 pub mod auth;
+pub mod business;
+pub mod dev;
 pub mod docs;
 pub mod health_check;
 pub mod oauth;
+pub mod storage;
 pub mod upload;
+pub mod webhooks;
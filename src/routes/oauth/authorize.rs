@@ -0,0 +1,77 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    app::{
+        error::AppError,
+        oauth::{state::OAuthState, OAuthProvider, SocialProvider},
+        ApiContext,
+    },
+    routes::docs::AUTH_TAG,
+};
+
+use super::AssertionProvider;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AuthorizeQuery {
+    /// Config key of the provider, required when `provider` is `oidc`
+    oidc_provider: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuthorizeResponse {
+    /// Provider url to redirect the user to
+    url: String,
+    /// Must be sent back with the code, the frontend should bind it to the browser
+    state: String,
+}
+
+pub fn router() -> Router<ApiContext> {
+    Router::new().route("/oauth/{provider}/authorize", get(oauth_authorize))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{provider}/authorize",
+    tag = AUTH_TAG,
+    params(
+        ("provider" = AssertionProvider, Path, description = "Social login provider"),
+        AuthorizeQuery
+    ),
+    responses(
+        (status = 200, description = "Authorization started", body = AuthorizeResponse),
+        (status = 400, description = "Bad request"),
+        (status = 404, description = "Unimplemented or inactive provider"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "OAuth authorize", skip_all, fields(provider = ?provider))]
+pub async fn oauth_authorize(
+    ctx: State<ApiContext>,
+    Path(provider): Path<AssertionProvider>,
+    Query(query): Query<AuthorizeQuery>,
+) -> Result<Json<AuthorizeResponse>, AppError> {
+    if provider == AssertionProvider::Oidc && query.oidc_provider.is_none() {
+        return Err(AppError::unprocessable_entity([(
+            "oidc_provider",
+            "missing",
+        )]));
+    }
+
+    let social_provider =
+        SocialProvider::build(&provider, query.oidc_provider.as_deref(), &ctx).await?;
+
+    let state = OAuthState::generate_state();
+    let auth = OAuthState::new(provider, query.oidc_provider);
+    let url = social_provider.authorize_url(&state, &auth)?;
+
+    auth.store(&state, &ctx.redis_client).await?;
+
+    Ok(Json(AuthorizeResponse { url, state }))
+}
@@ -0,0 +1,71 @@
+use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    app::{
+        error::AppError,
+        extrator::ValidatedJson,
+        oauth::{confirm_pending_link, link::PendingSocialLink, signup::otp_subject},
+        otp::store::{OtpPurpose, OtpStore},
+        ApiContext,
+    },
+    routes::docs::AUTH_TAG,
+};
+
+use super::token::{grant_session, session_metadata, GrantResponse};
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SocialLinkVerifyInput {
+    link_token: String,
+    code: String,
+}
+
+pub fn router() -> Router<ApiContext> {
+    Router::new().route("/oauth/link/verify", post(social_link_verify))
+}
+
+#[utoipa::path(
+    post,
+    path = "/link/verify",
+    tag = AUTH_TAG,
+    request_body = SocialLinkVerifyInput,
+    responses(
+        (status = 200, description = "Successful grant", body = GrantResponse),
+        (status = 400, description = "Bad request"),
+        (status = 404, description = "Link token expired/missing"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Social link verify", skip_all, fields(user_id = tracing::field::Empty))]
+pub async fn social_link_verify(
+    ctx: State<ApiContext>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<SocialLinkVerifyInput>,
+) -> Result<Json<GrantResponse>, AppError> {
+    let pending = PendingSocialLink::get(&req.link_token, &ctx.redis_client)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .consume(
+            OtpPurpose::SocialLink,
+            &otp_subject(&req.link_token),
+            &req.code.to_uppercase(),
+        )
+        .await?
+        .ok_or(AppError::unprocessable_entity([("code", "invalid")]))?;
+
+    let mut tx = ctx.db_pool.begin().await?;
+    let user_id = confirm_pending_link(pending, &mut tx).await?;
+    tx.commit().await?;
+
+    PendingSocialLink::delete(&req.link_token, &ctx.redis_client).await?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let res = grant_session(user_id, session_metadata(&headers), &ctx).await?;
+
+    Ok(Json(res))
+}
@@ -1,8 +1,29 @@
+use axum::Router;
 use utoipa::OpenApi;
+
+use crate::app::ApiContext;
+
+mod authorize;
+mod link;
+mod signup;
 mod token;
 
 #[derive(OpenApi)]
-#[openapi(paths(token::oauth_token))]
+#[openapi(paths(
+    authorize::oauth_authorize,
+    token::oauth_token,
+    signup::social_signup_email,
+    signup::social_signup_verify,
+    link::social_link_verify
+))]
 pub struct OAuthApi;
 
 pub use token::*;
+
+pub fn router() -> Router<ApiContext> {
+    Router::new()
+        .merge(authorize::router())
+        .merge(token::router())
+        .merge(signup::router())
+        .merge(link::router())
+}
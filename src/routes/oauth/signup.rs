@@ -0,0 +1,144 @@
+use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Json, Router};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    app::{
+        error::AppError,
+        extrator::{ExtractLocale, ValidatedJson},
+        oauth::{
+            signup::{otp_subject, PendingSocialSignup},
+            upsert_social_user, ProviderUser,
+        },
+        otp::store::{OtpPurpose, OtpStore},
+        storage::avatar::set_default_avatar,
+        ApiContext,
+    },
+    routes::docs::AUTH_TAG,
+};
+
+use super::token::{grant_session, session_metadata, GrantResponse};
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SocialSignupEmailInput {
+    signup_token: String,
+    #[validate(email)]
+    email: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SocialSignupVerifyInput {
+    signup_token: String,
+    code: String,
+}
+
+pub fn router() -> Router<ApiContext> {
+    Router::new()
+        .route("/oauth/signup/email", post(social_signup_email))
+        .route("/oauth/signup/verify", post(social_signup_verify))
+}
+
+#[utoipa::path(
+    post,
+    path = "/signup/email",
+    tag = AUTH_TAG,
+    request_body = SocialSignupEmailInput,
+    responses(
+        (status = 204, description = "Verification code sent"),
+        (status = 400, description = "Bad request"),
+        (status = 404, description = "Signup token expired/missing"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 429, description = "Sent recently, see Retry-After"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Social signup email", skip_all, fields(email = ?req.email))]
+pub async fn social_signup_email(
+    ctx: State<ApiContext>,
+    ExtractLocale(locale): ExtractLocale,
+    ValidatedJson(req): ValidatedJson<SocialSignupEmailInput>,
+) -> Result<StatusCode, AppError> {
+    PendingSocialSignup::get(&req.signup_token, &ctx.redis_client)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let purpose = OtpPurpose::SocialSignup;
+    let code = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .issue(purpose, &otp_subject(&req.signup_token), &req.email)
+        .await?;
+
+    let email_content = ctx
+        .email_client
+        .build_email_confirmation(&code, purpose.ttl().whole_hours())
+        .await?;
+
+    ctx.email_client
+        .queue_email(&req.email, locale, email_content, &*ctx.db_pool)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/signup/verify",
+    tag = AUTH_TAG,
+    request_body = SocialSignupVerifyInput,
+    responses(
+        (status = 200, description = "Successful grant", body = GrantResponse),
+        (status = 400, description = "Bad request"),
+        (status = 404, description = "Signup token expired/missing"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Social signup verify", skip_all, fields(user_id = tracing::field::Empty))]
+pub async fn social_signup_verify(
+    ctx: State<ApiContext>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<SocialSignupVerifyInput>,
+) -> Result<Json<GrantResponse>, AppError> {
+    let pending = PendingSocialSignup::get(&req.signup_token, &ctx.redis_client)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    // The code proves ownership of the email it was sent to
+    let email = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .consume(
+            OtpPurpose::SocialSignup,
+            &otp_subject(&req.signup_token),
+            &req.code.to_uppercase(),
+        )
+        .await?
+        .ok_or(AppError::unprocessable_entity([("code", "invalid")]))?;
+
+    let mut tx = ctx.db_pool.begin().await?;
+
+    let user_id = upsert_social_user(
+        pending.provider,
+        pending.oidc_provider.as_deref(),
+        &email,
+        ProviderUser {
+            id: pending.provider_user_id,
+            email: Some(email.clone()),
+            email_verified: true,
+            bio: pending.bio,
+            image: pending.image,
+        },
+        &mut tx,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    // Without a provider image new users get a generated one
+    set_default_avatar(&user_id, &*ctx.storage, &ctx.db_pool).await;
+
+    PendingSocialSignup::delete(&req.signup_token, &ctx.redis_client).await?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let res = grant_session(user_id, session_metadata(&headers), &ctx).await?;
+
+    Ok(Json(res))
+}
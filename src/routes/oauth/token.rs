@@ -17,10 +17,11 @@ use crate::{
         },
         error::AppError,
         extrator::ValidatedJson,
-        oauth::{discord::handle_discord_assertion, github::handle_github_assertion},
+        oauth::{handle_assertion, state::OAuthState, SocialProvider},
+        otp::store::{OtpPurpose, OtpStore},
+        utils::validation::PHONE_REGEX,
         ApiContext,
     },
-    config::AppConfig,
     routes::docs::AUTH_TAG,
 };
 
@@ -37,13 +38,21 @@ struct GrantTokenInput {
     #[schema(value_type = Option<String>)]
     password: Option<SecretString>,
 
+    // Phone grant inputs, also takes `code`
+    #[validate(regex(path = *PHONE_REGEX))]
+    phone: Option<String>,
+
     // Assertion grant inputs
     code: Option<String>,
+    /// State returned by `/oauth/{provider}/authorize`
+    state: Option<String>,
     provider: Option<AssertionProvider>,
+    /// Config key of the provider, required when `provider` is `oidc`
+    oidc_provider: Option<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
-struct GrantResponse {
+pub(super) struct GrantResponse {
     access_token: String,
     refresh_token: String,
     expires_in: u64,
@@ -59,6 +68,8 @@ enum GrantType {
     RefreshToken,
     #[serde(rename = "assertion")]
     Assertion,
+    #[serde(rename = "phone")]
+    Phone,
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
@@ -79,10 +90,11 @@ pub fn router() -> Router<ApiContext> {
     responses(
         (status = 200, description = "Successful grant", body = GrantResponse),
         (status = 400, description = "Bad request"),
-        (status = 401, description = "Refresh token expired"),
+        (status = 401, description = "Refresh token expired, or invalid credentials or code"),
         (status = 403, description = "Reset password required"),
-        (status = 404, description = "Unimplemented or inactive provider"),
-        (status = 422, description = "Invalid input", body = AppError),
+        (status = 404, description = "Unimplemented or inactive provider, or phone login is disabled"),
+        (status = 409, description = "Social login must be confirmed by the email owner", body = AppError),
+        (status = 422, description = "Invalid input, or email required to finish social signup", body = AppError),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -94,19 +106,7 @@ async fn oauth_token(
 ) -> Result<Json<GrantResponse>, AppError> {
     tracing::Span::current().record("grant", tracing::field::display(&req.grant_type));
 
-    let metadata = SessionMetadata {
-        device_name: headers
-            .get("X-User-Agent")
-            .and_then(|hv| hv.to_str().ok())
-            .map(|s| s.to_string()),
-        ip: headers
-            .get("X-Forwarded-For")
-            .and_then(|hv| hv.to_str().ok())
-            .map(|s| s.to_string()),
-        last_accessed: OffsetDateTime::now_utc()
-            .format(&time::format_description::well_known::Iso8601::DEFAULT)
-            .unwrap(),
-    };
+    let metadata = session_metadata(&headers);
 
     match req.grant_type {
         GrantType::Password => {
@@ -137,16 +137,13 @@ async fn oauth_token(
         }
         GrantType::Assertion => {
             let assertion_input = AssertionFlowInput::try_from(req)?;
-            let res = assertion_flow(
-                assertion_input,
-                metadata,
-                &ctx.db_pool,
-                &ctx.redis_client,
-                &ctx.token_manager,
-                &ctx.config,
-                &ctx.http_client,
-            )
-            .await?;
+            let res = assertion_flow(assertion_input, metadata, &ctx).await?;
+
+            Ok(Json(res))
+        }
+        GrantType::Phone => {
+            let phone_input = PhoneFlowInput::try_from(req)?;
+            let res = phone_flow(phone_input, metadata, &ctx).await?;
 
             Ok(Json(res))
         }
@@ -198,6 +195,67 @@ async fn owner_password_flow(
     }
 }
 
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+struct PhoneFlowInput {
+    phone: String,
+    /// Code sent by `/auth/phones/login-code`
+    code: String,
+}
+
+#[tracing::instrument(name = "Phone flow", skip_all)]
+async fn phone_flow(
+    req: PhoneFlowInput,
+    metadata: SessionMetadata,
+    ctx: &ApiContext,
+) -> Result<GrantResponse, AppError> {
+    // Codes logged instead of sent could be used by anyone reading the logs
+    if !ctx.config.sms_delivers() {
+        return Err(AppError::NotFound);
+    }
+
+    let user_id = OtpStore::new(&ctx.redis_client, &ctx.config.otp)
+        .consume(OtpPurpose::PhoneLogin, &req.phone, &req.code)
+        .await?
+        .and_then(|user_id| user_id.parse::<Uuid>().ok())
+        .ok_or(AppError::Unauthorized)?;
+
+    // The phone could have been removed since the code was sent.
+    // No password is involved, so users who never set one can log in too.
+    sqlx::query_scalar!(
+        r#"
+            select p.user_id
+            from phone p
+            where p.phone = $1 and p.user_id = $2 and p.verified = true
+        "#,
+        req.phone,
+        user_id
+    )
+    .fetch_optional(&*ctx.db_pool)
+    .await?
+    .ok_or(AppError::Unauthorized)?;
+
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let scopes = get_scopes(user_id, &ctx.db_pool).await?;
+    let session = Session::new(user_id);
+    let tokens = session
+        .insert(
+            metadata,
+            &ctx.redis_client,
+            &ctx.token_manager,
+            &scopes.to_string(),
+        )
+        .await?;
+
+    Ok(GrantResponse {
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
+        token_type: TokenType::Bearer,
+        scope: scopes.to_string(),
+    })
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 struct RefreshTokenInput {
     refresh_token: String,
@@ -249,48 +307,88 @@ async fn refresh_token_flow(
 #[derive(Debug, Deserialize, Validate, ToSchema)]
 struct AssertionFlowInput {
     code: String,
+    state: String,
     provider: AssertionProvider,
+    oidc_provider: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, ToSchema, sqlx::Type)]
+#[derive(Debug, PartialEq, Deserialize, Serialize, ToSchema, sqlx::Type)]
 #[sqlx(type_name = "social_provider", rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum AssertionProvider {
     Github,
     Discord,
+    /// Generic OpenID Connect provider configured in `AppConfig::oidc`
+    Oidc,
+    Facebook,
     #[serde(skip)]
     Google,
-    #[serde(skip)]
-    Facebook,
 }
 
 #[tracing::instrument(name = "Assertion flow", skip_all, fields(req = ?req))]
 async fn assertion_flow(
     req: AssertionFlowInput,
     metadata: SessionMetadata,
-    pool: &PgPool,
-    client: &redis::Client,
-    token_manager: &TokenManager,
-    config: &AppConfig,
-    http_client: &reqwest::Client,
+    ctx: &ApiContext,
 ) -> Result<GrantResponse, AppError> {
-    let user_id: Uuid = match req.provider {
-        AssertionProvider::Github => {
-            handle_github_assertion(pool, config, http_client, &req.code).await?
-        }
-        AssertionProvider::Discord => {
-            handle_discord_assertion(pool, config, http_client, &req.code).await?
-        }
-        AssertionProvider::Google => return Err(AppError::NotFound),
-        AssertionProvider::Facebook => return Err(AppError::NotFound),
-    };
+    let auth =
+        take_oauth_state(&req.state, &req.provider, req.oidc_provider.as_deref(), ctx).await?;
+
+    let provider = SocialProvider::build(&req.provider, req.oidc_provider.as_deref(), ctx).await?;
+    let user_id: Uuid = handle_assertion(&provider, &req.code, &auth, ctx).await?;
 
     tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
+    grant_session(user_id, metadata, ctx).await
+}
+
+/// Consumes the state issued by `/oauth/{provider}/authorize`.
+///
+/// Unknown, reused or expired states and states issued for another provider are rejected.
+pub(crate) async fn take_oauth_state(
+    state: &str,
+    provider: &AssertionProvider,
+    oidc_provider: Option<&str>,
+    ctx: &ApiContext,
+) -> Result<OAuthState, AppError> {
+    match OAuthState::take(state, &ctx.redis_client).await? {
+        Some(auth) if auth.matches(provider, oidc_provider) => Ok(auth),
+        _ => Err(AppError::unprocessable_entity([("state", "invalid")])),
+    }
+}
+
+/// Session metadata from the headers forwarded by the frontend server
+pub(super) fn session_metadata(headers: &HeaderMap) -> SessionMetadata {
+    SessionMetadata {
+        device_name: headers
+            .get("X-User-Agent")
+            .and_then(|hv| hv.to_str().ok())
+            .map(|s| s.to_string()),
+        ip: headers
+            .get("X-Forwarded-For")
+            .and_then(|hv| hv.to_str().ok())
+            .map(|s| s.to_string()),
+        last_accessed: OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Iso8601::DEFAULT)
+            .unwrap(),
+    }
+}
+
+/// Starts a new session for an already authenticated user
+pub(super) async fn grant_session(
+    user_id: Uuid,
+    metadata: SessionMetadata,
+    ctx: &ApiContext,
+) -> Result<GrantResponse, AppError> {
     let session = Session::new(user_id);
-    let scopes = get_scopes(user_id, pool).await?;
+    let scopes = get_scopes(user_id, &ctx.db_pool).await?;
     let tokens = session
-        .insert(metadata, client, token_manager, &scopes.to_string())
+        .insert(
+            metadata,
+            &ctx.redis_client,
+            &ctx.token_manager,
+            &scopes.to_string(),
+        )
         .await?;
 
     Ok(GrantResponse {
@@ -308,6 +406,7 @@ impl std::fmt::Display for GrantType {
             GrantType::Password => write!(f, "password"),
             GrantType::RefreshToken => write!(f, "refresh_token"),
             GrantType::Assertion => write!(f, "assertion"),
+            GrantType::Phone => write!(f, "phone"),
         }
     }
 }
@@ -330,6 +429,21 @@ impl TryFrom<GrantTokenInput> for OwnerPasswordFlowInput {
     }
 }
 
+impl TryFrom<GrantTokenInput> for PhoneFlowInput {
+    type Error = AppError;
+    fn try_from(value: GrantTokenInput) -> Result<Self, Self::Error> {
+        let phone = value
+            .phone
+            .ok_or(AppError::unprocessable_entity([("phone", "missing")]))?;
+
+        let code = value
+            .code
+            .ok_or(AppError::unprocessable_entity([("code", "missing")]))?;
+
+        Ok(PhoneFlowInput { phone, code })
+    }
+}
+
 impl TryFrom<GrantTokenInput> for RefreshTokenInput {
     type Error = AppError;
     fn try_from(value: GrantTokenInput) -> Result<Self, Self::Error> {
@@ -351,11 +465,27 @@ impl TryFrom<GrantTokenInput> for AssertionFlowInput {
             .code
             .ok_or(AppError::unprocessable_entity([("code", "missing")]))?;
 
+        let state = value
+            .state
+            .ok_or(AppError::unprocessable_entity([("state", "missing")]))?;
+
         let provider = value
             .provider
             .ok_or(AppError::unprocessable_entity([("provider", "missing")]))?;
 
-        let input = AssertionFlowInput { code, provider };
+        if provider == AssertionProvider::Oidc && value.oidc_provider.is_none() {
+            return Err(AppError::unprocessable_entity([(
+                "oidc_provider",
+                "missing",
+            )]));
+        }
+
+        let input = AssertionFlowInput {
+            code,
+            state,
+            provider,
+            oidc_provider: value.oidc_provider,
+        };
 
         Ok(input)
     }
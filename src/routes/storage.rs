@@ -0,0 +1,122 @@
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{Path, Query, State},
+    http::{
+        header::{CONTENT_TYPE, ETAG},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::put,
+    Router,
+};
+
+use crate::app::{
+    error::AppError,
+    storage::{
+        is_private_key,
+        local::{LocalDownloadQuery, LocalPartQuery, LocalStorage, LocalUploadQuery},
+    },
+    ApiContext,
+};
+
+/// Only mounted when `storage` is `local`
+pub fn router() -> Router<ApiContext> {
+    Router::new()
+        .route(
+            "/storage/local/{*key}",
+            put(upload_local_object).get(get_local_object),
+        )
+        .route(
+            "/storage/multipart/{multipart_id}/{part_number}",
+            put(upload_local_part),
+        )
+}
+
+fn local_storage(ctx: &ApiContext) -> Result<&LocalStorage, AppError> {
+    ctx.local_storage.as_deref().ok_or(AppError::NotFound)
+}
+
+/// Reads at most the signed length, so only signed requests are buffered
+async fn read_signed_body(body: Body, content_length: i64) -> Result<Bytes, AppError> {
+    let limit = usize::try_from(content_length).map_err(|_| AppError::Forbidden)?;
+
+    let bytes = body::to_bytes(body, limit)
+        .await
+        .map_err(|_| AppError::Forbidden)?;
+
+    if bytes.len() != limit {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(bytes)
+}
+
+/// Stores the body when the request matches the signed upload URL
+#[tracing::instrument(name = "Upload local object", skip_all, fields(key = ?key))]
+pub async fn upload_local_object(
+    ctx: State<ApiContext>,
+    Path(key): Path<String>,
+    Query(query): Query<LocalUploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<StatusCode, AppError> {
+    let storage = local_storage(&ctx)?;
+
+    // Same as S3, the signature only covers the requested type and size
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if !storage.verify_upload(&key, &query) || content_type != Some(query.content_type.as_str()) {
+        return Err(AppError::Forbidden);
+    }
+
+    let body = read_signed_body(body, query.content_length).await?;
+
+    storage.write(&key, &query.content_type, &body).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Stores a part of a multipart upload, responds with its `ETag` like S3
+#[tracing::instrument(name = "Upload local part", skip_all, fields(multipart_id = ?multipart_id))]
+pub async fn upload_local_part(
+    ctx: State<ApiContext>,
+    Path((multipart_id, part_number)): Path<(String, i32)>,
+    Query(query): Query<LocalPartQuery>,
+    body: Body,
+) -> Result<Response, AppError> {
+    let storage = local_storage(&ctx)?;
+
+    if !storage.verify_part(&multipart_id, part_number, &query) {
+        return Err(AppError::Forbidden);
+    }
+
+    let body = read_signed_body(body, query.content_length).await?;
+
+    let etag = storage
+        .write_part(&multipart_id, part_number, &body)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(([(ETAG, etag)], StatusCode::OK).into_response())
+}
+
+/// Private objects need a signed download URL
+#[tracing::instrument(name = "Get local object", skip_all, fields(key = ?key))]
+pub async fn get_local_object(
+    ctx: State<ApiContext>,
+    Path(key): Path<String>,
+    Query(query): Query<LocalDownloadQuery>,
+) -> Result<Response, AppError> {
+    let storage = local_storage(&ctx)?;
+
+    if is_private_key(&key) && !storage.verify_download(&key, &query) {
+        return Err(AppError::Forbidden);
+    }
+
+    let (bytes, meta) = storage.read(&key).await?.ok_or(AppError::NotFound)?;
+
+    let content_type = meta
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    Ok(([(CONTENT_TYPE, content_type)], bytes).into_response())
+}
@@ -0,0 +1,480 @@
+use std::str::FromStr;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use mime2::Mime;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidateArgs, ValidationError};
+
+use crate::app::{
+    auth::scope::AppPermission,
+    business::member::find_business_role,
+    error::AppError,
+    extrator::AuthUser,
+    storage::{
+        image::delete_variants,
+        path::{PathScope, S3Path},
+        quota::{quota_bytes, storage_usage},
+        upload::UploadStatus,
+        PresignedUpload, DOWNLOAD_EXPIRES_IN,
+    },
+    utils::types::Timestamptz,
+    ApiContext,
+};
+
+use multipart::{
+    abort_multipart_upload, complete_multipart_upload, create_multipart_upload,
+    presign_multipart_parts,
+};
+
+use super::docs::UPLOAD_TAG;
+
+pub mod multipart;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    handle_upload,
+    confirm_upload,
+    download_upload,
+    delete_s3_object,
+    multipart::create_multipart_upload,
+    multipart::presign_multipart_parts,
+    multipart::complete_multipart_upload,
+    multipart::abort_multipart_upload
+))]
+pub struct UploadApi;
+
+#[derive(Debug, Validate, Deserialize, ToSchema)]
+#[validate(context = S3Path)]
+pub struct UploadFile {
+    pub path: S3Path,
+    /// Required for the business paths, the caller has to be a member
+    pub business_id: Option<Uuid>,
+    pub file_name: String,
+    #[validate(custom(function = "validate_mime_type", use_context))]
+    pub file_type: String,
+
+    #[validate(custom(function = "validate_file_size", use_context))]
+    pub file_size: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UploadResponse {
+    /// Confirm with it once the file is uploaded
+    upload_id: Uuid,
+    /// Reference to the file, e.g. the `image` of the profile
+    key: String,
+    #[serde(flatten)]
+    presigned: PresignedUpload,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConfirmedUpload {
+    upload_id: Uuid,
+    key: String,
+    /// Not set for private paths, see `/upload/{id}/download`
+    url: Option<String>,
+    status: UploadStatus,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    confirmed_at: Option<Timestamptz>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DownloadResponse {
+    url: String,
+    #[schema(value_type = String, format = DateTime)]
+    expires_at: Timestamptz,
+}
+
+#[derive(Debug, Validate, Deserialize, ToSchema)]
+pub struct DeleteInput {
+    pub path: S3Path,
+    pub business_id: Option<Uuid>,
+    pub file_name: String,
+}
+
+pub fn router() -> Router<ApiContext> {
+    Router::new()
+        .route("/upload", post(handle_upload).delete(delete_s3_object))
+        .route("/upload/{id}/confirm", post(confirm_upload))
+        .route("/upload/{id}/download", get(download_upload))
+        .route("/upload/multipart", post(create_multipart_upload))
+        .route("/upload/{id}/parts", post(presign_multipart_parts))
+        .route("/upload/{id}/complete", post(complete_multipart_upload))
+        .route("/upload/{id}/abort", post(abort_multipart_upload))
+}
+
+#[utoipa::path(
+    post,
+    path = "",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = UploadFile,
+    responses(
+        (status = 200, description = "Successful created presigned result", body = UploadResponse),
+        (status = 400, description = "Bad request"),
+        (status = 403, description = "Not a member of the business"),
+        (status = 422, description = "Invalid input or storage quota exceeded", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Generate presigned", skip_all, fields(req = ?req))]
+async fn handle_upload(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Json(req): Json<UploadFile>,
+) -> Result<Json<UploadResponse>, AppError> {
+    req.validate_with_args(&req.path)?;
+
+    if req.file_size > ctx.config.multipart.part_size {
+        return Err(AppError::unprocessable_entity([(
+            "file_size",
+            "multipart_required",
+        )]));
+    }
+
+    let owner_id = authorize_path(&auth_user, req.path, req.business_id, &ctx).await?;
+    let key = req.path.key(&owner_id, &req.file_name);
+    check_quota(&ctx, req.path, &owner_id, &key, req.file_size).await?;
+
+    let presigned = ctx
+        .storage
+        .presign_upload(&key, &req.file_type, req.file_size)
+        .await?;
+
+    // Uploading to the same key again has to be confirmed again
+    let upload_id = sqlx::query_scalar!(
+        r#"
+            insert into upload (user_id, business_id, path, key, mime_type, size)
+            values ($1, $2, $3, $4, $5, $6)
+            on conflict (key) do update
+            set user_id = excluded.user_id,
+                mime_type = excluded.mime_type,
+                size = excluded.size,
+                status = 'pending',
+                confirmed_at = null,
+                processed_at = null,
+                processing_error = null,
+                processing_started_at = null,
+                processing_attempts = 0,
+                variants_size = 0,
+                multipart_id = null,
+                multipart_started_at = null
+            returning upload_id
+        "#,
+        auth_user.user_id,
+        req.business_id
+            .filter(|_| req.path.scope() == PathScope::Business),
+        req.path as S3Path,
+        key,
+        req.file_type,
+        req.file_size
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    Ok(Json(UploadResponse {
+        upload_id,
+        key,
+        presigned,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/confirm",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Upload database id")
+    ),
+    responses(
+        (status = 200, description = "Successful confirmed", body = ConfirmedUpload),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Upload not found"),
+        (status = 422, description = "File missing or not as requested", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Confirm upload", skip_all, fields(id = ?id))]
+async fn confirm_upload(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ConfirmedUpload>, AppError> {
+    let upload = sqlx::query!(
+        r#"
+            select key, path as "path: S3Path", mime_type, size,
+                status as "status: UploadStatus", confirmed_at
+            from upload
+            where upload_id = $1 and user_id = $2
+        "#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    if upload.status == UploadStatus::Confirmed {
+        return Ok(Json(ConfirmedUpload {
+            upload_id: id,
+            url: public_url(&ctx, upload.path, &upload.key),
+            key: upload.key,
+            status: upload.status,
+            confirmed_at: upload.confirmed_at.map(Timestamptz),
+        }));
+    }
+
+    let confirmed = confirm_stored_object(
+        &ctx,
+        id,
+        upload.key,
+        upload.path,
+        &upload.mime_type,
+        upload.size,
+    )
+    .await?;
+
+    Ok(Json(confirmed))
+}
+
+/// Confirms the upload once the stored object matches the requested type and size
+async fn confirm_stored_object(
+    ctx: &ApiContext,
+    upload_id: Uuid,
+    key: String,
+    path: S3Path,
+    mime_type: &str,
+    size: i64,
+) -> Result<ConfirmedUpload, AppError> {
+    let meta = ctx
+        .storage
+        .head(&key)
+        .await?
+        .ok_or_else(|| AppError::unprocessable_entity([("upload", "missing")]))?;
+
+    if meta.size != size {
+        return Err(AppError::unprocessable_entity([("file_size", "mismatch")]));
+    }
+
+    if meta.content_type.as_deref() != Some(mime_type) {
+        return Err(AppError::unprocessable_entity([("file_type", "mismatch")]));
+    }
+
+    let confirmed_at = sqlx::query_scalar!(
+        r#"
+            update upload
+            set status = 'confirmed', confirmed_at = now()
+            where upload_id = $1
+            returning confirmed_at
+        "#,
+        upload_id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    Ok(ConfirmedUpload {
+        upload_id,
+        url: public_url(ctx, path, &key),
+        key,
+        status: UploadStatus::Confirmed,
+        confirmed_at: confirmed_at.map(Timestamptz),
+    })
+}
+
+fn public_url(ctx: &ApiContext, path: S3Path, key: &str) -> Option<String> {
+    (!path.is_private()).then(|| ctx.storage.public_url(key))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/download",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Upload database id")
+    ),
+    responses(
+        (status = 200, description = "Short-lived download URL", body = DownloadResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Upload not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Download upload", skip_all, fields(id = ?id))]
+async fn download_upload(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DownloadResponse>, AppError> {
+    let upload = sqlx::query!(
+        r#"
+            select key, user_id, business_id
+            from upload
+            where upload_id = $1 and status = 'confirmed'
+        "#,
+        id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    let has_access = upload.user_id == auth_user.user_id
+        || match upload.business_id {
+            Some(business_id) => {
+                auth_user.has_permission(&AppPermission::BusinessVerify)
+                    || find_business_role(&business_id, &auth_user.user_id, &*ctx.db_pool)
+                        .await?
+                        .is_some()
+            }
+            None => false,
+        };
+
+    // Same as missing, ids of other uploads are not revealed
+    if !has_access {
+        return Err(AppError::NotFound);
+    }
+
+    let expires_at = OffsetDateTime::now_utc() + DOWNLOAD_EXPIRES_IN;
+    let url = ctx.storage.presign_download(&upload.key).await?;
+
+    sqlx::query!(
+        r#"
+            insert into file_access_log (upload_id, key, user_id)
+            values ($1, $2, $3)
+        "#,
+        id,
+        upload.key,
+        auth_user.user_id
+    )
+    .execute(&*ctx.db_pool)
+    .await?;
+
+    Ok(Json(DownloadResponse {
+        url,
+        expires_at: Timestamptz(expires_at),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = DeleteInput,
+    responses(
+        (status = 204, description = "Successfully deleted"),
+        (status = 400, description = "Bad request"),
+        (status = 403, description = "Not a member of the business"),
+        (status = 422, description = "Invalid input or storage quota exceeded", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Delete S3 object", skip_all, fields(req = ?req))]
+async fn delete_s3_object(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Json(req): Json<DeleteInput>,
+) -> Result<StatusCode, AppError> {
+    let owner_id = authorize_path(&auth_user, req.path, req.business_id, &ctx).await?;
+    let full_path = req.path.key(&owner_id, &req.file_name);
+    ctx.storage.delete(&full_path).await?;
+    if req.path.has_variants() {
+        delete_variants(&*ctx.storage, &full_path).await?;
+    }
+
+    let multipart_id = sqlx::query_scalar!(
+        r#"
+            delete from upload
+            where key = $1
+            returning multipart_id
+        "#,
+        full_path
+    )
+    .fetch_optional(&*ctx.db_pool)
+    .await?
+    .flatten();
+
+    if let Some(multipart_id) = multipart_id {
+        ctx.storage
+            .abort_multipart(&full_path, &multipart_id)
+            .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Owner of the files under the path, the user itself or a business it is a member of
+async fn authorize_path(
+    auth_user: &AuthUser,
+    path: S3Path,
+    business_id: Option<Uuid>,
+    ctx: &ApiContext,
+) -> Result<Uuid, AppError> {
+    match path.scope() {
+        PathScope::User => Ok(auth_user.user_id),
+        PathScope::Business => {
+            let business_id = business_id
+                .ok_or_else(|| AppError::unprocessable_entity([("business_id", "required")]))?;
+
+            find_business_role(&business_id, &auth_user.user_id, &*ctx.db_pool)
+                .await?
+                .ok_or(AppError::Forbidden)?;
+
+            Ok(business_id)
+        }
+    }
+}
+
+/// Fails when the file would take the owner over the quota of its scope
+async fn check_quota(
+    ctx: &ApiContext,
+    path: S3Path,
+    owner_id: &Uuid,
+    key: &str,
+    file_size: i64,
+) -> Result<(), AppError> {
+    let usage = storage_usage(owner_id, key, &*ctx.db_pool).await?;
+
+    if usage + file_size > quota_bytes(path.scope(), &ctx.config.quota) {
+        return Err(AppError::unprocessable_entity([(
+            "file_size",
+            "quota_exceeded",
+        )]));
+    }
+
+    Ok(())
+}
+
+fn validate_file_size(file_size: i64, path: &S3Path) -> Result<(), ValidationError> {
+    // A negative size would lower the usage counted against the quota
+    if file_size < 1 || file_size > path.get_max_size() {
+        return Err(ValidationError::new("file_size"));
+    }
+
+    Ok(())
+}
+
+fn validate_mime_type(file_type: &str, path: &S3Path) -> Result<(), ValidationError> {
+    let mime_type = Mime::from_str(file_type).map_err(|_| ValidationError::new("file_type"))?;
+
+    if !path.is_allowed_type(&mime_type) {
+        return Err(ValidationError::new("file_type"));
+    }
+
+    Ok(())
+}
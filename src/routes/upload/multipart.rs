@@ -0,0 +1,356 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidateArgs};
+
+use crate::{
+    app::{
+        error::AppError,
+        extrator::{AuthUser, ValidatedJson},
+        storage::{
+            multipart::split_parts,
+            path::{PathScope, S3Path},
+            CompletedPart, PresignedUpload,
+        },
+        ApiContext,
+    },
+    routes::docs::UPLOAD_TAG,
+};
+
+use super::{authorize_path, check_quota, confirm_stored_object, ConfirmedUpload, UploadFile};
+
+/// Part URLs handed out per request
+const MAX_PARTS_PER_REQUEST: u64 = 100;
+
+#[derive(Serialize, ToSchema)]
+pub struct MultipartUploadResponse {
+    upload_id: Uuid,
+    key: String,
+    /// Size of every part but the last
+    part_size: i64,
+    part_count: i32,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct PresignPartsInput {
+    #[validate(length(min = 1, max = MAX_PARTS_PER_REQUEST))]
+    part_numbers: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PresignedPart {
+    part_number: i32,
+    #[serde(flatten)]
+    presigned: PresignedUpload,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct CompleteMultipartInput {
+    /// Every part with the `ETag` header returned by its upload
+    #[validate(length(min = 1))]
+    parts: Vec<CompletedPart>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/multipart",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    request_body = UploadFile,
+    responses(
+        (status = 200, description = "Multipart upload started", body = MultipartUploadResponse),
+        (status = 400, description = "Bad request"),
+        (status = 403, description = "Not a member of the business"),
+        (status = 422, description = "Invalid input or storage quota exceeded", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Create multipart upload", skip_all, fields(req = ?req))]
+pub async fn create_multipart_upload(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Json(req): Json<UploadFile>,
+) -> Result<Json<MultipartUploadResponse>, AppError> {
+    req.validate_with_args(&req.path)?;
+
+    let owner_id = authorize_path(&auth_user, req.path, req.business_id, &ctx).await?;
+    let key = req.path.key(&owner_id, &req.file_name);
+    check_quota(&ctx, req.path, &owner_id, &key, req.file_size).await?;
+
+    let part_size = ctx.config.multipart.part_size;
+    let (part_count, _) = split_parts(req.file_size, part_size);
+
+    let multipart_id = ctx.storage.create_multipart(&key, &req.file_type).await?;
+
+    // Starting again drops the previous upload to the key
+    let previous = sqlx::query!(
+        r#"
+            select multipart_id
+            from upload
+            where key = $1 and multipart_id is not null
+        "#,
+        key
+    )
+    .fetch_optional(&*ctx.db_pool)
+    .await?;
+
+    let upload_id = sqlx::query_scalar!(
+        r#"
+            insert into upload (
+                user_id, business_id, path, key, mime_type, size,
+                multipart_id, multipart_started_at
+            )
+            values ($1, $2, $3, $4, $5, $6, $7, now())
+            on conflict (key) do update
+            set user_id = excluded.user_id,
+                mime_type = excluded.mime_type,
+                size = excluded.size,
+                status = 'pending',
+                confirmed_at = null,
+                processed_at = null,
+                processing_error = null,
+                processing_started_at = null,
+                processing_attempts = 0,
+                variants_size = 0,
+                multipart_id = excluded.multipart_id,
+                multipart_started_at = excluded.multipart_started_at
+            returning upload_id
+        "#,
+        auth_user.user_id,
+        req.business_id
+            .filter(|_| req.path.scope() == PathScope::Business),
+        req.path as S3Path,
+        key,
+        req.file_type,
+        req.file_size,
+        multipart_id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    if let Some(multipart_id) = previous.and_then(|row| row.multipart_id) {
+        if let Err(e) = ctx.storage.abort_multipart(&key, &multipart_id).await {
+            tracing::error!("failed to abort previous multipart upload: {:?}", e);
+        }
+    }
+
+    Ok(Json(MultipartUploadResponse {
+        upload_id,
+        key,
+        part_size,
+        part_count,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/parts",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Upload database id")
+    ),
+    request_body = PresignPartsInput,
+    responses(
+        (status = 200, description = "Presigned part uploads", body = Vec<PresignedPart>),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Multipart upload not found"),
+        (status = 422, description = "Invalid input", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Presign multipart parts", skip_all, fields(id = ?id))]
+pub async fn presign_multipart_parts(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(req): ValidatedJson<PresignPartsInput>,
+) -> Result<Json<Vec<PresignedPart>>, AppError> {
+    let upload = find_multipart_upload(&id, &auth_user, &ctx).await?;
+
+    let part_size = ctx.config.multipart.part_size;
+    let (part_count, last_size) = split_parts(upload.size, part_size);
+
+    let mut parts = Vec::with_capacity(req.part_numbers.len());
+    for part_number in req.part_numbers {
+        if !(1..=part_count).contains(&part_number) {
+            return Err(AppError::unprocessable_entity([(
+                "part_numbers",
+                "out_of_range",
+            )]));
+        }
+
+        let content_length = if part_number == part_count {
+            last_size
+        } else {
+            part_size
+        };
+
+        let presigned = ctx
+            .storage
+            .presign_part(
+                &upload.key,
+                &upload.multipart_id,
+                part_number,
+                content_length,
+            )
+            .await?;
+
+        parts.push(PresignedPart {
+            part_number,
+            presigned,
+        });
+    }
+
+    Ok(Json(parts))
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/complete",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Upload database id")
+    ),
+    request_body = CompleteMultipartInput,
+    responses(
+        (status = 200, description = "Parts joined and the upload confirmed", body = ConfirmedUpload),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Multipart upload not found"),
+        (status = 422, description = "Parts missing or not as requested", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Complete multipart upload", skip_all, fields(id = ?id))]
+pub async fn complete_multipart_upload(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(req): ValidatedJson<CompleteMultipartInput>,
+) -> Result<Json<ConfirmedUpload>, AppError> {
+    let upload = find_multipart_upload(&id, &auth_user, &ctx).await?;
+
+    let (part_count, _) = split_parts(upload.size, ctx.config.multipart.part_size);
+    let mut part_numbers = req
+        .parts
+        .iter()
+        .map(|part| part.part_number)
+        .collect::<Vec<_>>();
+    part_numbers.sort_unstable();
+
+    if part_numbers != (1..=part_count).collect::<Vec<_>>() {
+        return Err(AppError::unprocessable_entity([("parts", "incomplete")]));
+    }
+
+    if let Err(e) = ctx
+        .storage
+        .complete_multipart(&upload.key, &upload.multipart_id, &req.parts)
+        .await
+    {
+        // Mostly parts that were not uploaded or an outdated `ETag`
+        tracing::warn!("failed to complete multipart upload: {:?}", e);
+        return Err(AppError::unprocessable_entity([("parts", "invalid")]));
+    }
+
+    sqlx::query!(
+        r#"
+            update upload
+            set multipart_id = null, multipart_started_at = null
+            where upload_id = $1
+        "#,
+        id
+    )
+    .execute(&*ctx.db_pool)
+    .await?;
+
+    let confirmed = confirm_stored_object(
+        &ctx,
+        id,
+        upload.key,
+        upload.path,
+        &upload.mime_type,
+        upload.size,
+    )
+    .await?;
+
+    Ok(Json(confirmed))
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/abort",
+    tag = UPLOAD_TAG,
+    security(
+        ("bearerAuth" = [])
+    ),
+    params(
+        ("id" = String, Path, description = "Upload database id")
+    ),
+    responses(
+        (status = 204, description = "Uploaded parts dropped"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Multipart upload not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "Abort multipart upload", skip_all, fields(id = ?id))]
+pub async fn abort_multipart_upload(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let upload = find_multipart_upload(&id, &auth_user, &ctx).await?;
+
+    ctx.storage
+        .abort_multipart(&upload.key, &upload.multipart_id)
+        .await?;
+
+    sqlx::query!("delete from upload where upload_id = $1", id)
+        .execute(&*ctx.db_pool)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+struct MultipartUpload {
+    key: String,
+    path: S3Path,
+    mime_type: String,
+    size: i64,
+    multipart_id: String,
+}
+
+/// Multipart upload in progress that the user started
+async fn find_multipart_upload(
+    id: &Uuid,
+    auth_user: &AuthUser,
+    ctx: &ApiContext,
+) -> Result<MultipartUpload, AppError> {
+    let upload = sqlx::query_as!(
+        MultipartUpload,
+        r#"
+            select key, path as "path: S3Path", mime_type, size,
+                multipart_id as "multipart_id!"
+            from upload
+            where upload_id = $1 and user_id = $2 and multipart_id is not null
+        "#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_one(&*ctx.db_pool)
+    .await?;
+
+    Ok(upload)
+}
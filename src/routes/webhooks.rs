@@ -0,0 +1,99 @@
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode, routing::post, Router};
+use utoipa::OpenApi;
+
+use crate::app::{
+    email::{
+        sns::{SnsMessage, SnsMessageType},
+        suppression::{suppress_email, SesNotification},
+    },
+    error::AppError,
+    ApiContext,
+};
+
+use super::docs::WEBHOOK_TAG;
+
+#[derive(OpenApi)]
+#[openapi(paths(ses_webhook))]
+pub struct WebhookApi;
+
+pub fn router() -> Router<ApiContext> {
+    Router::new().route("/webhooks/ses", post(ses_webhook))
+}
+
+#[utoipa::path(
+    post,
+    path = "/ses",
+    tag = WEBHOOK_TAG,
+    request_body(content = String, description = "SNS message, posted as text/plain"),
+    responses(
+        (status = 204, description = "Handled"),
+        (status = 403, description = "Invalid signature, unknown topic or stale message"),
+        (status = 422, description = "Malformed message", body = AppError),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[tracing::instrument(name = "SES webhook", skip_all)]
+pub async fn ses_webhook(ctx: State<ApiContext>, body: String) -> Result<StatusCode, AppError> {
+    // SNS posts JSON with a text/plain content type
+    let message: SnsMessage = serde_json::from_str(&body)
+        .map_err(|_| AppError::unprocessable_entity([("body", "invalid")]))?;
+
+    // Any AWS account can sign messages, only the configured topics are trusted
+    let topics = &ctx.config.email.webhook.topic_arns;
+    if !topics.contains(&message.topic_arn) {
+        tracing::warn!("SNS message from unknown topic {}", message.topic_arn);
+        return Err(AppError::Forbidden);
+    }
+
+    if let Err(e) = ctx.sns_verifier.verify(&message).await {
+        tracing::warn!("invalid SNS message: {:?}", e);
+        return Err(AppError::Forbidden);
+    }
+
+    let max_age = time::Duration::seconds(ctx.config.email.webhook.max_message_age_secs);
+    if let Err(e) = message.check_timestamp(max_age) {
+        tracing::warn!("stale SNS message: {:?}", e);
+        return Err(AppError::Forbidden);
+    }
+
+    match message.kind {
+        SnsMessageType::SubscriptionConfirmation => {
+            let subscribe_url = message
+                .subscribe_url
+                .as_deref()
+                .context("missing SubscribeURL")?;
+
+            ctx.http_client
+                .get(subscribe_url)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status())
+                .context("failed to confirm SNS subscription")?;
+
+            tracing::info!("Confirmed SNS subscription to {}", message.topic_arn);
+        }
+        SnsMessageType::UnsubscribeConfirmation => {
+            tracing::info!("Unsubscribed from {}", message.topic_arn);
+        }
+        SnsMessageType::Notification => {
+            let notification: SesNotification = serde_json::from_str(&message.message)
+                .map_err(|_| AppError::unprocessable_entity([("message", "invalid")]))?;
+
+            let soft_bounce_ttl =
+                time::Duration::seconds(ctx.config.email.webhook.soft_bounce_suppression_secs);
+            let suppressions = notification.suppressions(soft_bounce_ttl);
+            if suppressions.is_empty() {
+                return Ok(StatusCode::NO_CONTENT);
+            }
+
+            let mut tx = ctx.db_pool.begin().await?;
+            for suppression in &suppressions {
+                suppress_email(suppression, &mut tx).await?;
+            }
+            tx.commit().await?;
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
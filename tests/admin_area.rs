@@ -0,0 +1,316 @@
+pub mod common;
+
+use common::helpers::{spawn_app, TestApp};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+async fn area_id(app: &TestApp, code: &str) -> Uuid {
+    sqlx::query_scalar!("select area_id from address_area where code = $1", code)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("failed to find area")
+}
+
+async fn insert_address_at(app: &TestApp, lat: f64, lng: f64) -> Uuid {
+    sqlx::query_scalar!(
+        r#"
+            insert into address (line_1, geom)
+            values (hstore('en', 'Street 1'), st_setsrid(st_makepoint($1, $2), 4326))
+            returning address_id
+        "#,
+        lng,
+        lat
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("failed to add address")
+}
+
+async fn address_area_id(app: &TestApp, address_id: Uuid) -> Option<Uuid> {
+    sqlx::query_scalar!(
+        "select area_id from address where address_id = $1",
+        address_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+fn square(lng: f64, lat: f64, size: f64) -> Value {
+    json!({
+        "type": "Polygon",
+        "coordinates": [[
+            [lng, lat],
+            [lng + size, lat],
+            [lng + size, lat + size],
+            [lng, lat + size],
+            [lng, lat]
+        ]]
+    })
+}
+
+async fn post_area(app: &TestApp, token: &str, body: &Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/area", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(body)
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn import_areas(
+    app: &TestApp,
+    token: &str,
+    content_type: &str,
+    body: String,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/area/import", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .header("Content-Type", content_type)
+        .body(body)
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+#[tokio::test]
+async fn create_area_works_and_assigns_addresses() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+    let address_id = insert_address_at(&app, 47.91, 106.85).await;
+
+    let res = post_area(
+        &app,
+        &token,
+        &json!({
+            "code": "MN-1-test",
+            "name": { "en": "Test district", "mn": "Туршилтын дүүрэг" },
+            "type": "district",
+            "parent_id": area_id(&app, "MN-1").await,
+            "boundary": square(106.8, 47.88, 0.1)
+        }),
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let area = res.json::<Value>().await.unwrap();
+    assert_eq!(area["name"], "Test district");
+    assert_eq!(area["localized_name"]["mn"], "Туршилтын дүүрэг");
+    assert_eq!(area["type"], "district");
+    assert_eq!(area["boundary"]["type"], "MultiPolygon");
+
+    let area_id: Uuid = area["area_id"].as_str().unwrap().parse().unwrap();
+    assert_eq!(address_area_id(&app, address_id).await, Some(area_id));
+}
+
+#[tokio::test]
+async fn create_area_fails_for_invalid_parent() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    // A province can't be inside a district
+    let res = post_area(
+        &app,
+        &token,
+        &json!({
+            "name": { "en": "Test province" },
+            "type": "province",
+            "parent_id": area_id(&app, "MN-1-bayangol").await
+        }),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = res.json::<Value>().await.unwrap();
+    assert_eq!(body["errors"]["parent_id"][0], "invalid");
+
+    let res = post_area(
+        &app,
+        &token,
+        &json!({ "name": { "en": "Test district" }, "type": "district" }),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn update_area_cannot_become_narrower_than_its_children() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+    let ulaanbaatar = area_id(&app, "MN-1").await;
+
+    let res = app
+        .api_client
+        .put(format!("{}/admin/area/{}", &app.address, ulaanbaatar))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&json!({
+            "code": "MN-1",
+            "name": { "en": "Ulaanbaatar" },
+            "type": "district",
+            "parent_id": area_id(&app, "MN").await
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = res.json::<Value>().await.unwrap();
+    assert_eq!(body["errors"]["type"][0], "invalid_for_children");
+}
+
+#[tokio::test]
+async fn delete_area_with_children_is_rejected() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    let url = |id: Uuid| format!("{}/admin/area/{}", &app.address, id);
+
+    let res = app
+        .api_client
+        .delete(url(area_id(&app, "MN-1").await))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app
+        .api_client
+        .delete(url(area_id(&app, "MN-1-bayangol").await))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn import_geojson_creates_and_updates_areas() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+    let address_id = insert_address_at(&app, 49.03, 104.05).await;
+
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": {
+                    "code": "MN-035",
+                    "parent_code": "MN",
+                    "type": "province",
+                    "name_en": "Orkhon",
+                    "name_mn": "Орхон"
+                },
+                "geometry": square(103.8, 48.9, 0.5)
+            },
+            {
+                "type": "Feature",
+                "properties": {
+                    "code": "MN-1-bayangol",
+                    "parent_code": "MN-1",
+                    "type": "district",
+                    "name_en": "Bayangol",
+                    "name_mn": "Баянгол дүүрэг"
+                },
+                "geometry": square(106.8, 47.88, 0.1)
+            }
+        ]
+    });
+
+    let res = import_areas(&app, &token, "application/geo+json", collection.to_string()).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let summary = res.json::<Value>().await.unwrap();
+    assert_eq!(summary["created"], 1);
+    assert_eq!(summary["updated"], 1);
+    assert_eq!(summary["assigned_addresses"], 1);
+
+    assert_eq!(
+        address_area_id(&app, address_id).await,
+        Some(area_id(&app, "MN-035").await)
+    );
+
+    let name =
+        sqlx::query_scalar!("select name->'mn' from address_area where code = 'MN-1-bayangol'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(name.as_deref(), Some("Баянгол дүүрэг"));
+}
+
+#[tokio::test]
+async fn import_csv_works() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    let boundary = square(103.8, 48.9, 0.5).to_string().replace('"', "\"\"");
+    let csv = format!(
+        "code,parent_code,type,name_en,name_mn,boundary\n\
+         MN-035,MN,province,Orkhon,Орхон,\"{}\"\n\
+         MN-035-bayan-undur,MN-035,district,Bayan-Undur,Баян-Өндөр,\n",
+        boundary
+    );
+
+    let res = import_areas(&app, &token, "text/csv", csv).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let summary = res.json::<Value>().await.unwrap();
+    assert_eq!(summary["created"], 2);
+
+    let parent_id =
+        sqlx::query_scalar!("select parent_id from address_area where code = 'MN-035-bayan-undur'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(parent_id, Some(area_id(&app, "MN-035").await));
+}
+
+#[tokio::test]
+async fn import_with_unknown_parent_imports_nothing() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    let csv = "code,parent_code,type,name_en,name_mn,boundary\n\
+               MN-035,MN,province,Orkhon,Орхон,\n\
+               MN-099-test,MN-099,district,Test,,\n"
+        .to_string();
+
+    let res = import_areas(&app, &token, "text/csv", csv).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = res.json::<Value>().await.unwrap();
+    assert_eq!(body["errors"]["rows[1]"][0], "parent_not_found");
+
+    let count = sqlx::query_scalar!(
+        r#"select count(*) as "count!" from address_area where code = 'MN-035'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn area_admin_requires_permission() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = post_area(
+        &app,
+        &token,
+        &json!({ "name": { "en": "Mongolia" }, "type": "country" }),
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,218 @@
+pub mod common;
+
+use common::helpers::{spawn_app, TestApp};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+async fn create_category(app: &TestApp, name: &str) -> Uuid {
+    sqlx::query_scalar!(
+        r#"
+            insert into category (name)
+            values (hstore('en', $1))
+            returning category_id
+        "#,
+        name
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("failed to create category")
+}
+
+async fn post_business(app: &TestApp, token: &str, body: &Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/business", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(body)
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+#[tokio::test]
+async fn create_business_works() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+    let category_id = create_category(&app, "Cafe").await;
+
+    let res = post_business(
+        &app,
+        &token,
+        &json!({
+            "name": { "en": "Sakura Cafe", "mn": "Сакура Кафе" },
+            "category_ids": [category_id],
+            "addresses": [{
+                "line_1": { "en": "Peace avenue 1", "mn": "Энхтайвны өргөн чөлөө 1" },
+                "location": { "lat": 47.918, "lng": 106.917 }
+            }],
+            "published": true
+        }),
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let business = res.json::<Value>().await.unwrap();
+    assert_eq!(business["name"], "Sakura Cafe");
+    assert_eq!(business["localized_name"]["mn"], "Сакура Кафе");
+    assert_eq!(business["category_ids"], json!([category_id]));
+    assert_eq!(business["addresses"][0]["line_1"]["en"], "Peace avenue 1");
+    assert!(business["published_at"].is_string());
+}
+
+#[tokio::test]
+async fn create_business_fails_for_invalid_name() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    let res = post_business(&app, &token, &json!({ "name": { "en": "Сакура Кафе" } })).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = res.json::<Value>().await.unwrap();
+    assert_eq!(body["errors"]["name"][0], "invalid_en");
+}
+
+#[tokio::test]
+async fn create_business_fails_for_unknown_category() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    let res = post_business(
+        &app,
+        &token,
+        &json!({
+            "name": { "en": "Sakura Cafe" },
+            "category_ids": [Uuid::new_v4()]
+        }),
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Nothing is left behind by the failed transaction
+    let count = sqlx::query_scalar!(r#"select count(*) as "count!" from business"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn update_business_replaces_categories_and_addresses() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+    let cafe = create_category(&app, "Cafe").await;
+    let bakery = create_category(&app, "Bakery").await;
+
+    let res = post_business(
+        &app,
+        &token,
+        &json!({
+            "name": { "en": "Sakura Cafe" },
+            "category_ids": [cafe],
+            "addresses": [{ "line_1": { "en": "Peace avenue 1" } }]
+        }),
+    )
+    .await;
+    let business = res.json::<Value>().await.unwrap();
+    let business_id = business["business_id"].as_str().unwrap();
+
+    let res = app
+        .api_client
+        .put(format!("{}/admin/business/{}", &app.address, business_id))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&json!({
+            "name": { "en": "Sakura Bakery" },
+            "category_ids": [bakery],
+            "addresses": []
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let business = res.json::<Value>().await.unwrap();
+    assert_eq!(business["name"], "Sakura Bakery");
+    assert_eq!(business["category_ids"], json!([bakery]));
+    assert_eq!(business["addresses"], json!([]));
+}
+
+#[tokio::test]
+async fn list_businesses_paginates() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    for i in 0..30 {
+        let res = post_business(
+            &app,
+            &token,
+            &json!({ "name": { "en": format!("Business {}", i) } }),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    let res = app
+        .api_client
+        .get(format!("{}/admin/business", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    let first = res.json::<Value>().await.unwrap();
+    assert_eq!(first["data"].as_array().unwrap().len(), 25);
+
+    let res = app
+        .api_client
+        .get(format!("{}/admin/business", &app.address))
+        .query(&[("cursor", first["next_cursor"].as_str().unwrap())])
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    let second = res.json::<Value>().await.unwrap();
+    assert_eq!(second["data"].as_array().unwrap().len(), 5);
+    assert!(second["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn delete_business_works() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let url = format!("{}/admin/business/{}", &app.address, business_id);
+
+    let res = app
+        .api_client
+        .delete(&url)
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = app
+        .api_client
+        .get(&url)
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn business_admin_requires_permission() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = post_business(&app, &token, &json!({ "name": { "en": "Sakura Cafe" } })).await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
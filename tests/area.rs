@@ -0,0 +1,200 @@
+pub mod common;
+
+use common::helpers::{spawn_app, TestApp};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+async fn area_id(app: &TestApp, code: &str) -> Uuid {
+    sqlx::query_scalar!("select area_id from address_area where code = $1", code)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("failed to find area")
+}
+
+async fn get_areas(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/areas{}", &app.address, path))
+        .header("Accept-Language", "mn")
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+fn area_names(areas: &Value) -> Vec<&str> {
+    areas
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|area| area["name"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn root_areas_are_countries() {
+    let app = spawn_app().await;
+
+    let res = get_areas(&app, "").await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let areas = res.json::<Value>().await.unwrap();
+    assert_eq!(area_names(&areas), vec!["Монгол"]);
+    assert_eq!(areas[0]["code"], "MN");
+    assert_eq!(areas[0]["type"], "country");
+}
+
+#[tokio::test]
+async fn child_areas_are_listed() {
+    let app = spawn_app().await;
+    let ulaanbaatar = area_id(&app, "MN-1").await;
+
+    let res = get_areas(&app, &format!("/{}/children", ulaanbaatar)).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let areas = res.json::<Value>().await.unwrap();
+    assert_eq!(areas.as_array().unwrap().len(), 9);
+    assert!(area_names(&areas).contains(&"Баянгол"));
+    assert_eq!(areas[0]["parent_id"], json!(ulaanbaatar));
+    assert_eq!(areas[0]["type"], "district");
+}
+
+#[tokio::test]
+async fn area_ancestors_start_from_the_country() {
+    let app = spawn_app().await;
+    let bayangol = area_id(&app, "MN-1-bayangol").await;
+
+    let res = get_areas(&app, &format!("/{}/ancestors", bayangol)).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let areas = res.json::<Value>().await.unwrap();
+    assert_eq!(area_names(&areas), vec!["Монгол", "Улаанбаатар", "Баянгол"]);
+}
+
+#[tokio::test]
+async fn unknown_area_is_not_found() {
+    let app = spawn_app().await;
+    let unknown = Uuid::new_v4();
+
+    for path in ["", "/children", "/ancestors"] {
+        let res = get_areas(&app, &format!("/{}{}", unknown, path)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+}
+
+/// Ulaanbaatar around Bayangol, the district is the more specific match
+async fn set_ulaanbaatar_boundaries(app: &TestApp) {
+    for (code, boundary) in [
+        (
+            "MN-1",
+            json!({
+                "type": "Polygon",
+                "coordinates": [[[106.5, 47.7], [107.3, 47.7], [107.3, 48.1], [106.5, 48.1], [106.5, 47.7]]]
+            }),
+        ),
+        (
+            "MN-1-bayangol",
+            json!({
+                "type": "Polygon",
+                "coordinates": [[[106.8, 47.88], [106.9, 47.88], [106.9, 47.93], [106.8, 47.93], [106.8, 47.88]]]
+            }),
+        ),
+    ] {
+        sqlx::query!(
+            r#"
+                update address_area
+                set boundary = st_multi(st_setsrid(st_geomfromgeojson($1), 4326))
+                where code = $2
+            "#,
+            boundary.to_string(),
+            code
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn address_is_assigned_to_the_district_it_is_in() {
+    let app = spawn_app().await;
+    let bayangol = area_id(&app, "MN-1-bayangol").await;
+    set_ulaanbaatar_boundaries(&app).await;
+
+    let area_of = |lat: f64, lng: f64| {
+        sqlx::query_scalar!(
+            r#"
+                insert into address (line_1, geom)
+                values (hstore('en', 'Street 1'), st_setsrid(st_makepoint($1, $2), 4326))
+                returning area_id
+            "#,
+            lng,
+            lat
+        )
+        .fetch_one(&app.db_pool)
+    };
+
+    assert_eq!(area_of(47.91, 106.85).await.unwrap(), Some(bayangol));
+    assert_eq!(
+        area_of(47.95, 107.1).await.unwrap(),
+        Some(area_id(&app, "MN-1").await)
+    );
+    assert_eq!(area_of(46.0, 105.0).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn moved_address_is_assigned_to_its_new_area() {
+    let app = spawn_app().await;
+    let bayangol = area_id(&app, "MN-1-bayangol").await;
+    let ulaanbaatar = area_id(&app, "MN-1").await;
+    set_ulaanbaatar_boundaries(&app).await;
+
+    let address_id = sqlx::query_scalar!(
+        r#"
+            insert into address (line_1, geom)
+            values (hstore('en', 'Street 1'), st_setsrid(st_makepoint(106.85, 47.91), 4326))
+            returning address_id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let move_to = |lat: f64, lng: f64| {
+        sqlx::query_scalar!(
+            r#"
+                update address
+                set geom = st_setsrid(st_makepoint($1, $2), 4326)
+                where address_id = $3
+                returning area_id
+            "#,
+            lng,
+            lat,
+            address_id
+        )
+        .fetch_one(&app.db_pool)
+    };
+
+    // Out of Bayangol, still in Ulaanbaatar
+    assert_eq!(move_to(47.95, 107.1).await.unwrap(), Some(ulaanbaatar));
+    assert_eq!(move_to(47.91, 106.85).await.unwrap(), Some(bayangol));
+
+    // Outside of every boundary the area is kept
+    assert_eq!(move_to(46.0, 105.0).await.unwrap(), Some(bayangol));
+
+    // An area set along with the location wins
+    let area = sqlx::query_scalar!(
+        r#"
+            update address
+            set geom = st_setsrid(st_makepoint(106.86, 47.91), 4326), area_id = $1
+            where address_id = $2
+            returning area_id
+        "#,
+        ulaanbaatar,
+        address_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(area, Some(ulaanbaatar));
+}
@@ -1,10 +1,11 @@
 use fake::{faker::internet::en::SafeEmail, Fake};
+use nevermind::app::{auth::email_change::EmailChangeStatus, email::template::EmailTemplates};
 use reqwest::StatusCode;
 use serde::Deserialize;
 use uuid::Uuid;
 
 pub mod common;
-use common::helpers::spawn_app;
+use common::helpers::{spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn add_email_works() {
@@ -96,69 +97,240 @@ async fn add_email_fails_when_existing() {
     assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY)
 }
 
-#[tokio::test]
-async fn make_email_primary_works() {
-    let app = spawn_app().await;
-
-    // insert new email
+async fn insert_secondary_email(app: &TestApp, verified: bool) -> (String, Uuid) {
     let new_email: String = SafeEmail().fake();
     let new_email_id = sqlx::query_scalar!(
         r#"
-            insert into email(user_id, email)
-            values ($1, $2)
+            insert into email(user_id, email, verified)
+            values ($1, $2, $3)
             returning email_id
         "#,
         app.test_user.user_id,
-        new_email
+        new_email,
+        verified
     )
     .fetch_one(&app.db_pool)
     .await
     .unwrap();
 
-    let token = app.login_and_get_token().await;
+    (new_email, new_email_id)
+}
 
-    let res = app
-        .api_client
-        .patch(&format!(
-            "{}/auth/emails/{}/primary",
-            &app.address, &new_email_id
-        ))
-        .header("Authorization", "Bearer ".to_owned() + &token)
+async fn request_primary(app: &TestApp, token: &str, email_id: &Uuid) -> reqwest::Response {
+    app.api_client
+        .patch(format!("{}/auth/emails/{}/primary", &app.address, email_id))
+        .header("Authorization", "Bearer ".to_owned() + token)
         .send()
         .await
-        .expect("failed to execute request");
-
-    assert!(res.status().is_success());
+        .expect("failed to execute request")
+}
 
-    // new email should be primary
-    let is_primary = sqlx::query_scalar!(
+async fn is_primary(app: &TestApp, email: &str) -> bool {
+    sqlx::query_scalar!(
         r#"
             select is_primary
             from email
             where email = $1
         "#,
-        new_email
+        email
     )
     .fetch_one(&app.db_pool)
     .await
-    .unwrap();
-
-    assert_eq!(is_primary, true);
+    .unwrap()
+}
 
-    // previous email should be not primary
-    let is_primary = sqlx::query_scalar!(
+/// Ends the grace period of the pending change right away
+async fn skip_grace_period(app: &TestApp) {
+    sqlx::query!(
         r#"
-            select is_primary
-            from email
-            where email = $1
+            update primary_email_change
+            set effective_at = now() - interval '1 second'
+            where user_id = $1 and status = 'pending'
         "#,
-        &app.test_user.email
+        app.test_user.user_id
     )
-    .fetch_one(&app.db_pool)
+    .execute(&app.db_pool)
     .await
     .unwrap();
+}
+
+async fn get_cancel_token(app: &TestApp) -> String {
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .expect("change notice was not sent");
+
+    assert_eq!(message.template, EmailTemplates::PrimaryEmailChange);
+
+    let cancel_link = message.data["cancel_link"].as_str().unwrap();
+    cancel_link.split("token=").last().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn make_email_primary_works() {
+    let app = spawn_app().await;
+    let (new_email, new_email_id) = insert_secondary_email(&app, true).await;
+
+    let token = app.login_and_get_token().await;
+
+    let res = request_primary(&app, &token, &new_email_id).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    // waits for the grace period
+    app.apply_email_changes().await;
+    assert!(!is_primary(&app, &new_email).await);
+    assert!(is_primary(&app, &app.test_user.email).await);
+
+    skip_grace_period(&app).await;
+    app.apply_email_changes().await;
+
+    // new email should be primary
+    assert!(is_primary(&app, &new_email).await);
+
+    // previous email should be not primary
+    assert!(!is_primary(&app, &app.test_user.email).await);
+}
+
+#[tokio::test]
+async fn make_email_primary_notifies_old_email() {
+    let app = spawn_app().await;
+    let (new_email, new_email_id) = insert_secondary_email(&app, true).await;
+
+    let token = app.login_and_get_token().await;
+
+    let res = request_primary(&app, &token, &new_email_id).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .expect("change notice was not sent");
+
+    assert_eq!(message.template, EmailTemplates::PrimaryEmailChange);
+    assert_eq!(message.data["new_email"], new_email);
+    assert!(app.email_outbox.last_to(&new_email).await.is_none());
+}
+
+#[tokio::test]
+async fn make_email_primary_notice_rounds_grace_period_up_to_hours() {
+    let app = spawn_app_with(|c| c.email.primary_change.grace_period_secs = 600).await;
+    let (_, new_email_id) = insert_secondary_email(&app, true).await;
+
+    let token = app.login_and_get_token().await;
+
+    let res = request_primary(&app, &token, &new_email_id).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .expect("change notice was not sent");
+
+    assert_eq!(message.data["effective_in_hours"], 1);
+}
+
+#[tokio::test]
+async fn make_email_primary_can_be_cancelled_from_old_email() {
+    let app = spawn_app().await;
+    let (new_email, new_email_id) = insert_secondary_email(&app, true).await;
+
+    let token = app.login_and_get_token().await;
+
+    let res = request_primary(&app, &token, &new_email_id).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    let cancel_token = get_cancel_token(&app).await;
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/emails/primary/cancel", &app.address))
+        .json(&serde_json::json!({ "token": cancel_token }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    skip_grace_period(&app).await;
+    app.apply_email_changes().await;
+
+    assert!(!is_primary(&app, &new_email).await);
+    assert!(is_primary(&app, &app.test_user.email).await);
+
+    // token can't be used again
+    let res = app
+        .api_client
+        .post(format!("{}/auth/emails/primary/cancel", &app.address))
+        .json(&serde_json::json!({ "token": cancel_token }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn make_email_primary_fails_for_unverified_email() {
+    let app = spawn_app().await;
+    let (_, new_email_id) = insert_secondary_email(&app, false).await;
+
+    let token = app.login_and_get_token().await;
+
+    let res = request_primary(&app, &token, &new_email_id).await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn list_primary_email_changes_keeps_history() {
+    let app = spawn_app().await;
+    let (first_email, first_email_id) = insert_secondary_email(&app, true).await;
+    let (second_email, second_email_id) = insert_secondary_email(&app, true).await;
+
+    let token = app.login_and_get_token().await;
+
+    // the second request replaces the first one
+    let res = request_primary(&app, &token, &first_email_id).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let res = request_primary(&app, &token, &second_email_id).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    skip_grace_period(&app).await;
+    app.apply_email_changes().await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/auth/emails/primary/changes", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    #[derive(Deserialize)]
+    struct EmailChange {
+        old_email: String,
+        new_email: String,
+        status: EmailChangeStatus,
+    }
+
+    let changes = res.json::<Vec<EmailChange>>().await.unwrap();
 
-    assert_eq!(is_primary, false)
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].new_email, second_email);
+    assert_eq!(changes[0].old_email, app.test_user.email);
+    assert_eq!(changes[0].status, EmailChangeStatus::Applied);
+    assert_eq!(changes[1].new_email, first_email);
+    assert_eq!(changes[1].status, EmailChangeStatus::Cancelled);
 }
 
 #[tokio::test]
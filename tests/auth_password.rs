@@ -1,17 +1,29 @@
-use core::panic;
-
 use fake::{faker::internet::en::Password, Fake};
-use redis::AsyncCommands;
+use nevermind::app::email::template::EmailTemplates;
 use reqwest::StatusCode;
 
 pub mod common;
-use common::helpers::{spawn_app, TestApp, TestUser};
+use common::helpers::{spawn_app, spawn_app_with, TestApp, TestUser};
 
 #[tokio::test]
 async fn forgot_password_works() {
     let app = spawn_app().await;
 
-    reset_password_send(&app).await;
+    let reset_res = reset_password_send(&app).await;
+
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .expect("reset email was not sent");
+
+    assert_eq!(message.template, EmailTemplates::PasswordReset);
+    assert!(message.data["reset_link"]
+        .as_str()
+        .unwrap()
+        .ends_with(&reset_res.otp));
 }
 
 #[tokio::test]
@@ -46,6 +58,35 @@ async fn reset_password_works() {
     assert!(res.status().is_success());
 }
 
+#[tokio::test]
+async fn reset_password_fails_for_replaced_token() {
+    let app = spawn_app_with(|c| c.otp.resend_cooldown_secs = 0).await;
+
+    let first = reset_password_send(&app).await;
+    let second = reset_password_send(&app).await;
+    assert_ne!(first.otp, second.otp);
+
+    for (token, status) in [
+        (first.otp, StatusCode::NOT_FOUND),
+        (second.otp, StatusCode::NO_CONTENT),
+    ] {
+        let body = serde_json::json!({
+            "token": token,
+            "new_password": Password(6..12).fake::<String>()
+        });
+
+        let res = app
+            .api_client
+            .post(format!("{}/auth/reset-password", &app.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("failed to execute request");
+
+        assert_eq!(res.status(), status);
+    }
+}
+
 #[tokio::test]
 async fn change_password_works() {
     let app = spawn_app().await;
@@ -97,52 +138,7 @@ async fn reset_password_send(app: &TestApp) -> ResetPasswordRes {
 
     assert!(res.status().is_success());
 
-    let mut conn = app
-        .redis_client
-        .get_multiplexed_tokio_connection()
-        .await
-        .unwrap();
-
-    let pattern = "reset:*";
-    let mut iter: redis::AsyncIter<String> = conn
-        .scan_match(pattern)
-        .await
-        .expect("failed to scan iterate to redis");
-
-    let mut current_otp: Option<String> = None; // Make this mutable
-    let mut otps: Vec<String> = Vec::new();
-
-    while let Some(otp) = iter.next_item().await {
-        otps.push(otp);
-    }
-
-    drop(iter);
-
-    for otp in otps {
-        let value: String = conn.get(&otp).await.expect("failed to get email using key");
-        if value == app.test_user.email {
-            current_otp = Some(otp);
-            break;
-        }
-    }
-
-    if let Some(otp) = current_otp {
-        let token = extract_token(&otp);
-        assert!(token.is_some(), "Expected a otp but found None");
-
-        ResetPasswordRes {
-            otp: token.unwrap(),
-        }
-    } else {
-        panic!("could not find otp")
-    }
-}
-
-fn extract_token(first: &str) -> Option<String> {
-    let parts: Vec<&str> = first.split(':').collect();
-    if !parts.is_empty() {
-        return Some(parts.last().unwrap().to_string());
+    ResetPasswordRes {
+        otp: app.last_email_code(&app.test_user.email).await,
     }
-
-    None
 }
@@ -0,0 +1,432 @@
+use nevermind::config::{SmsTransportConfig, Stage};
+use rand::Rng;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+pub mod common;
+use common::helpers::{register_new_user, spawn_app, spawn_app_with, GrantResponse, TestApp};
+
+fn generate_phone() -> String {
+    format!("+9769{:07}", rand::thread_rng().gen_range(0..10_000_000))
+}
+
+async fn add_phone(app: &TestApp, token: &str, phone: &str) -> reqwest::Response {
+    add_phone_with_password(app, token, &app.test_user.password, phone).await
+}
+
+async fn add_phone_with_password(
+    app: &TestApp,
+    token: &str,
+    password: &str,
+    phone: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/auth/phones", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&serde_json::json!({
+            "new_phone": phone,
+            "password": password,
+        }))
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn verify_phone(app: &TestApp, token: &str, code: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/auth/phones/verify", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&serde_json::json!({ "code": code }))
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+/// The code in the last SMS sent to `phone`
+async fn last_code(app: &TestApp, phone: &str) -> String {
+    let message = app
+        .sms_outbox
+        .last_to(phone)
+        .await
+        .expect("sms was not sent");
+
+    message
+        .text
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|part| part.len() == 6)
+        .expect("sms has no code")
+        .to_string()
+}
+
+async fn add_verified_phone(app: &TestApp, token: &str) -> String {
+    let phone = generate_phone();
+
+    let res = add_phone(app, token, &phone).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let code = last_code(app, &phone).await;
+    let res = verify_phone(app, token, &code).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    phone
+}
+
+#[tokio::test]
+async fn add_phone_sends_verification_code() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = generate_phone();
+
+    let res = add_phone(&app, &token, &phone).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let row = sqlx::query!(
+        r#"
+            select user_id, verified, is_primary
+            from phone
+            where phone = $1
+        "#,
+        phone
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(row.user_id, app.test_user.user_id);
+    assert!(!row.verified);
+    assert!(!row.is_primary);
+
+    last_code(&app, &phone).await;
+}
+
+#[tokio::test]
+async fn add_phone_rejects_invalid_number() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = add_phone(&app, &token, "99112233").await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn verify_phone_makes_first_phone_primary() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let phone = add_verified_phone(&app, &token).await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/auth/phones", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    #[derive(Deserialize)]
+    struct PhoneResponse {
+        phone: String,
+        verified: bool,
+        is_primary: bool,
+    }
+
+    let phones = res.json::<Vec<PhoneResponse>>().await.unwrap();
+
+    assert_eq!(phones.len(), 1);
+    assert_eq!(phones[0].phone, phone);
+    assert!(phones[0].verified);
+    assert!(phones[0].is_primary);
+}
+
+#[tokio::test]
+async fn verify_phone_fails_for_invalid_code() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = add_phone(&app, &token, &generate_phone()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/phones/verify", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({ "code": "000000x" }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn verify_phone_fails_for_removed_phone() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = generate_phone();
+
+    let res = add_phone(&app, &token, &phone).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let code = last_code(&app, &phone).await;
+
+    let phone_id = sqlx::query_scalar!("select phone_id from phone where phone = $1", phone)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let res = app
+        .api_client
+        .delete(format!("{}/auth/phones/{}", &app.address, phone_id))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = verify_phone(&app, &token, &code).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unverified_phone_does_not_block_other_users() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let other = register_new_user(&app).await;
+    let phone = generate_phone();
+
+    let res =
+        add_phone_with_password(&app, &other.access_token, &other.new_user.password, &phone).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let other_code = last_code(&app, &phone).await;
+
+    let res = add_phone(&app, &token, &phone).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let code = last_code(&app, &phone).await;
+
+    let res = verify_phone(&app, &token, &code).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    // Verified by the test user first
+    let res = verify_phone(&app, &other.access_token, &other_code).await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // And can't be added by anyone else anymore
+    let res =
+        add_phone_with_password(&app, &other.access_token, &other.new_user.password, &phone).await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn phone_login_works() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = add_verified_phone(&app, &token).await;
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/phones/login-code", &app.address))
+        .json(&serde_json::json!({ "phone": phone }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let code = last_code(&app, &phone).await;
+    let res = app
+        .post_login(&serde_json::json!({
+            "grant_type": "phone",
+            "phone": phone,
+            "code": code
+        }))
+        .await;
+
+    assert!(res.status().is_success());
+    res.json::<GrantResponse>().await.unwrap();
+
+    // codes are single use
+    let res = app
+        .post_login(&serde_json::json!({
+            "grant_type": "phone",
+            "phone": phone,
+            "code": code
+        }))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn phone_login_fails_with_wrong_code() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = add_verified_phone(&app, &token).await;
+
+    let res = app
+        .post_login(&serde_json::json!({
+            "grant_type": "phone",
+            "phone": phone,
+            "code": "123456"
+        }))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn phone_login_burns_code_after_max_attempts() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = add_verified_phone(&app, &token).await;
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/phones/login-code", &app.address))
+        .json(&serde_json::json!({ "phone": phone }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let code = last_code(&app, &phone).await;
+
+    for _ in 0..app.config.otp.max_attempts {
+        let res = app
+            .post_login(&serde_json::json!({
+                "grant_type": "phone",
+                "phone": phone,
+                "code": "12345x"
+            }))
+            .await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = app
+        .post_login(&serde_json::json!({
+            "grant_type": "phone",
+            "phone": phone,
+            "code": code
+        }))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn phone_login_code_is_not_resent_within_cooldown() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = add_verified_phone(&app, &token).await;
+
+    for _ in 0..2 {
+        let res = app
+            .api_client
+            .post(format!("{}/auth/phones/login-code", &app.address))
+            .json(&serde_json::json!({ "phone": phone }))
+            .send()
+            .await
+            .expect("failed to execute request");
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    let sent = app
+        .sms_outbox
+        .messages()
+        .await
+        .into_iter()
+        .filter(|message| message.to == phone)
+        .count();
+
+    // The verification code and a single login code
+    assert_eq!(sent, 2);
+}
+
+#[tokio::test]
+async fn phone_login_code_is_not_sent_to_unknown_phone() {
+    let app = spawn_app().await;
+    let phone = generate_phone();
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/phones/login-code", &app.address))
+        .json(&serde_json::json!({ "phone": phone }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert!(app.sms_outbox.last_to(&phone).await.is_none());
+}
+
+#[tokio::test]
+async fn phone_login_is_disabled_in_prod_when_sms_is_only_logged() {
+    let app = spawn_app_with(|c| {
+        c.stage = Stage::Prod;
+        c.sms.transport = SmsTransportConfig::Log;
+    })
+    .await;
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/phones/login-code", &app.address))
+        .json(&serde_json::json!({ "phone": generate_phone() }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = app
+        .post_login(&serde_json::json!({
+            "grant_type": "phone",
+            "phone": generate_phone(),
+            "code": "123456"
+        }))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn phone_login_works_without_a_password() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let phone = add_verified_phone(&app, &token).await;
+
+    // Same as a user who signed up with a social login
+    sqlx::query!(
+        r#"
+            update "user"
+            set reset_password = true
+            where user_id = $1
+        "#,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/phones/login-code", &app.address))
+        .json(&serde_json::json!({ "phone": phone }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let code = last_code(&app, &phone).await;
+    let res = app
+        .post_login(&serde_json::json!({
+            "grant_type": "phone",
+            "phone": phone,
+            "code": code
+        }))
+        .await;
+
+    assert!(res.status().is_success());
+}
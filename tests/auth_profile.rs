@@ -1,4 +1,6 @@
 use fake::{faker::company::en::CatchPhrase, Fake};
+use nevermind::app::utils::types::Locale;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 pub mod common;
@@ -161,7 +163,7 @@ async fn update_user_profile_works() {
 
     let new_input = NewInput {
         bio: CatchPhrase().fake(),
-        image: CatchPhrase().fake(),
+        image: app.upload_profile_image(&token).await,
     };
 
     let update_res = app
@@ -196,3 +198,59 @@ async fn update_user_profile_works() {
     assert_eq!(data.bio, new_input.bio);
     assert_eq!(data.image, new_input.image);
 }
+
+#[tokio::test]
+async fn update_user_profile_fails_for_unconfirmed_image() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let update_res = app
+        .api_client
+        .patch(format!("{}/auth/me", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({
+            "image": format!("profile/{}/never-uploaded.png", app.test_user.user_id)
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(update_res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn update_user_locale_changes_email_language() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let update_res = app
+        .api_client
+        .patch(format!("{}/auth/me", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({ "locale": "mn" }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(update_res.status().is_success());
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/forgot-password", &app.address))
+        .json(&serde_json::json!({ "email": &app.test_user.email }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .expect("reset email was not sent");
+
+    assert_eq!(message.locale, Locale::Mn);
+}
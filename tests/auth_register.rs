@@ -1,3 +1,4 @@
+use nevermind::app::{email::template::EmailTemplates, utils::types::Locale};
 use reqwest::StatusCode;
 
 pub mod common;
@@ -35,6 +36,91 @@ async fn register_works() {
     assert!(login_res.status().is_success());
 }
 
+#[tokio::test]
+async fn register_sends_verification_email() {
+    let app = spawn_app().await;
+
+    let new_user = TestUser::generate();
+
+    let register_body = serde_json::json!({
+        "email": &new_user.email,
+        "username": &new_user.username,
+        "password": &new_user.password
+    });
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/users", &app.address))
+        .json(&register_body)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&new_user.email)
+        .await
+        .expect("verification email was not sent");
+
+    assert_eq!(message.template, EmailTemplates::EmailVerify);
+    assert_eq!(message.locale, Locale::En);
+    assert_eq!(message.from, app.config.email.from_mail);
+    assert!(message.data["code"].is_string());
+}
+
+#[tokio::test]
+async fn register_uses_accept_language_for_emails() {
+    let app = spawn_app().await;
+
+    let new_user = TestUser::generate();
+
+    let register_body = serde_json::json!({
+        "email": &new_user.email,
+        "username": &new_user.username,
+        "password": &new_user.password
+    });
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/users", &app.address))
+        .header("Accept-Language", "mn")
+        .json(&register_body)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    app.dispatch_emails().await;
+
+    let message = app
+        .email_outbox
+        .last_to(&new_user.email)
+        .await
+        .expect("verification email was not sent");
+
+    assert_eq!(message.locale, Locale::Mn);
+
+    let locale = sqlx::query_scalar!(
+        r#"
+            select u.locale as "locale: Locale"
+            from "user" u
+            inner join email e using (user_id)
+            where e.email = $1
+        "#,
+        new_user.email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(locale, Locale::Mn);
+}
+
 #[tokio::test]
 async fn register_fails_for_already_registered_user() {
     let app = spawn_app().await;
@@ -57,3 +143,46 @@ async fn register_fails_for_already_registered_user() {
 
     assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
+
+#[tokio::test]
+async fn register_stores_generated_avatar() {
+    let app = spawn_app().await;
+
+    let new_user = TestUser::generate();
+
+    let register_body = serde_json::json!({
+        "email": &new_user.email,
+        "username": &new_user.username,
+        "password": &new_user.password
+    });
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/users", &app.address))
+        .json(&register_body)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    let user = sqlx::query!(
+        r#"
+            select u.user_id, u.image as "image!", up.status::text as "status!"
+            from "user" u
+                join upload up on up.key = u.image
+            where u.username = $1
+        "#,
+        new_user.username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        user.image,
+        format!("profile/{}/identicon.png", user.user_id)
+    );
+    assert_eq!(user.status, "confirmed");
+    assert!(app.stored_object_path(&user.image).exists());
+}
@@ -1,31 +1,26 @@
 use redis::AsyncCommands;
-use reqwest::StatusCode;
+use reqwest::{header::RETRY_AFTER, StatusCode};
 
 pub mod common;
-use common::helpers::{register_new_user, spawn_app};
-
-#[tokio::test]
-async fn verify_email_works_uses_previous_token() {
-    let app = spawn_app().await;
-    let register_res = register_new_user(&app).await;
-    let body = serde_json::json!({
-        "email": &register_res.new_user.email,
-    });
-
-    let res = app
-        .api_client
-        .post(&format!("{}/auth/emails/resend", &app.address))
-        .json(&body)
+use common::helpers::{register_new_user, spawn_app, RegisterNewUserRes, TestApp};
+
+async fn resend(app: &TestApp, register_res: &RegisterNewUserRes) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/auth/emails/resend", &app.address))
+        .json(&serde_json::json!({
+            "email": &register_res.new_user.email,
+        }))
         .header(
             "Authorization",
             "Bearer ".to_owned() + &register_res.access_token,
         )
         .send()
         .await
-        .expect("failed to execute request");
-
-    assert!(res.status().is_success());
+        .expect("failed to execute request")
+}
 
+/// Ends the resend cooldowns of the user right away
+async fn skip_cooldown(app: &TestApp, register_res: &RegisterNewUserRes) {
     let user_id = sqlx::query_scalar!(
         r#"
             select user_id
@@ -43,63 +38,62 @@ async fn verify_email_works_uses_previous_token() {
         .get_multiplexed_tokio_connection()
         .await
         .unwrap();
-    let key = format!("user:{}:email:*", user_id);
-    let otps: Vec<String> = conn
-        .keys(key)
+    let keys: Vec<String> = conn
+        .keys(format!("otp:email_verify:{}:cooldown:*", user_id))
         .await
         .expect("not error when connecting to redis");
 
-    assert_eq!(otps.len(), 1);
+    for key in keys {
+        let _: () = conn.del(&key).await.expect("failed to delete key");
+    }
 }
 
 #[tokio::test]
-async fn verify_email_works_create_new_token() {
+async fn resend_email_verification_is_rate_limited() {
     let app = spawn_app().await;
     let register_res = register_new_user(&app).await;
-    let body = serde_json::json!({
-        "email": &register_res.new_user.email,
-    });
 
-    let user_id = sqlx::query_scalar!(
-        r#"
-            select user_id
-            from "user"
-            where username = $1
-        "#,
-        register_res.new_user.username
-    )
-    .fetch_one(&app.db_pool)
-    .await
-    .unwrap();
+    let res = resend(&app, &register_res).await;
 
-    let mut conn = app
-        .redis_client
-        .get_multiplexed_tokio_connection()
-        .await
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = res.headers()[RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
         .unwrap();
-    let key = format!("user:{}:email:*", user_id);
-    let _: () = conn.del(&key).await.expect("failed to delete keys");
+    assert!(retry_after > 0 && retry_after <= app.config.otp.resend_cooldown_secs);
+}
 
-    let res = app
-        .api_client
-        .post(&format!("{}/auth/emails/resend", &app.address))
-        .json(&body)
-        .header(
-            "Authorization",
-            "Bearer ".to_owned() + &register_res.access_token,
-        )
-        .send()
-        .await
-        .expect("failed to execute request");
+#[tokio::test]
+async fn resend_email_verification_replaces_previous_code() {
+    let app = spawn_app().await;
+    let register_res = register_new_user(&app).await;
 
-    assert!(res.status().is_success());
+    skip_cooldown(&app, &register_res).await;
 
-    let otps: Vec<String> = conn
-        .keys(&key)
+    let res = resend(&app, &register_res).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let new_code = app.last_email_code(&register_res.new_user.email).await;
+    assert_ne!(new_code, register_res.otp);
+
+    let verify = |code: String| {
+        app.api_client
+            .post(format!("{}/auth/emails/verify/{}", &app.address, code))
+            .header(
+                "Authorization",
+                "Bearer ".to_owned() + &register_res.access_token,
+            )
+            .send()
+    };
+
+    let res = verify(register_res.otp.clone())
         .await
-        .expect("not error when connecting to redis");
+        .expect("failed to execute request");
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
 
-    assert_eq!(otps.len(), 1);
+    let res = verify(new_code).await.expect("failed to execute request");
+    assert!(res.status().is_success());
 }
 
 #[tokio::test]
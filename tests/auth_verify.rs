@@ -93,3 +93,31 @@ async fn verify_empty_otp() {
 
     assert_eq!(res.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn verify_burns_otp_after_max_attempts() {
+    let app = spawn_app().await;
+    let register_res = register_new_user(&app).await;
+
+    let verify = |code: &str| {
+        app.api_client
+            .post(format!("{}/auth/emails/verify/{}", &app.address, code))
+            .header(
+                "Authorization",
+                "Bearer ".to_owned() + &register_res.access_token,
+            )
+            .send()
+    };
+
+    for _ in 0..app.config.otp.max_attempts {
+        let res = verify("WRONG000").await.expect("failed to execute request");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    // the code sent is gone with the last attempt
+    let res = verify(&register_res.otp)
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
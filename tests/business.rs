@@ -0,0 +1,483 @@
+pub mod common;
+
+use common::helpers::{spawn_app, TestApp};
+use reqwest::StatusCode;
+use serde_json::Value;
+use sqlx::postgres::types::PgHstore;
+use uuid::Uuid;
+
+fn localized(en: &str, mn: &str) -> PgHstore {
+    PgHstore::from_iter([
+        ("en".to_string(), Some(en.to_string())),
+        ("mn".to_string(), Some(mn.to_string())),
+    ])
+}
+
+async fn insert_business(app: &TestApp, name: &str, published: bool) -> Uuid {
+    sqlx::query_scalar!(
+        r#"
+            insert into business (name, published_at)
+            values (hstore('en', $1), case when $2 then now() end)
+            returning business_id
+        "#,
+        name,
+        published
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("failed to add business")
+}
+
+async fn area_id(app: &TestApp, en: &str) -> Uuid {
+    sqlx::query_scalar!("select area_id from address_area where name->'en' = $1", en)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("failed to find area")
+}
+
+async fn list_businesses(app: &TestApp, query: &[(&str, String)]) -> Value {
+    let res = app
+        .api_client
+        .get(format!("{}/businesses", &app.address))
+        .query(query)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json::<Value>().await.unwrap()
+}
+
+async fn insert_address_at(app: &TestApp, business_id: Uuid, lat: f64, lng: f64) {
+    sqlx::query!(
+        r#"
+            insert into address (business_id, line_1, geom)
+            values ($1, hstore('en', 'Street 1'), st_setsrid(st_makepoint($2, $3), 4326))
+        "#,
+        business_id,
+        lng,
+        lat
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("failed to add address");
+}
+
+async fn list_nearby_businesses(app: &TestApp, query: &[(&str, String)]) -> Value {
+    let res = app
+        .api_client
+        .get(format!("{}/businesses/nearby", &app.address))
+        .query(query)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json::<Value>().await.unwrap()
+}
+
+/// Sukhbaatar square, a thousandth of a degree of latitude is about 111 meters
+const LAT: f64 = 47.9188;
+const LNG: f64 = 106.9176;
+
+fn origin() -> Vec<(&'static str, String)> {
+    vec![("lat", LAT.to_string()), ("lng", LNG.to_string())]
+}
+
+fn business_ids(list: &Value) -> Vec<&str> {
+    list["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|business| business["business_id"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn only_published_businesses_are_listed() {
+    let app = spawn_app().await;
+    let published = insert_business(&app, "Sakura Cafe", true).await;
+    insert_business(&app, "Draft Cafe", false).await;
+
+    let list = list_businesses(&app, &[]).await;
+
+    assert_eq!(business_ids(&list), vec![published.to_string()]);
+    assert!(list["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn business_detail_is_localized() {
+    let app = spawn_app().await;
+
+    let business_id = sqlx::query_scalar!(
+        r#"
+            insert into business (name, published_at)
+            values ($1, now())
+            returning business_id
+        "#,
+        localized("Sakura Cafe", "Сакура Кафе") as _
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    sqlx::query!(
+        r#"
+            insert into address (business_id, line_1, area_id)
+            values ($1, $2, $3)
+        "#,
+        business_id,
+        localized("Peace avenue 1", "Энхтайвны өргөн чөлөө 1") as _,
+        area_id(&app, "Bayangol").await
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let res = app
+        .api_client
+        .get(format!("{}/businesses/{}", &app.address, business_id))
+        .header("Accept-Language", "mn")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let business = res.json::<Value>().await.unwrap();
+    assert_eq!(business["name"], "Сакура Кафе");
+    assert_eq!(
+        business["addresses"][0]["line_1"],
+        "Энхтайвны өргөн чөлөө 1"
+    );
+    assert!(business["addresses"][0]["line_2"].is_null());
+}
+
+#[tokio::test]
+async fn unpublished_business_detail_is_not_found() {
+    let app = spawn_app().await;
+    let business_id = insert_business(&app, "Draft Cafe", false).await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/businesses/{}", &app.address, business_id))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn businesses_are_filtered_by_category_and_subcategories() {
+    let app = spawn_app().await;
+    let food = sqlx::query_scalar!(
+        "insert into category (name) values (hstore('en', 'Food')) returning category_id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    let cafe = sqlx::query_scalar!(
+        r#"
+            insert into category (name, parent_id)
+            values (hstore('en', 'Cafe'), $1)
+            returning category_id
+        "#,
+        food
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let in_cafe = insert_business(&app, "Sakura Cafe", true).await;
+    insert_business(&app, "Other Shop", true).await;
+
+    sqlx::query!(
+        "insert into business_category (business_id, category_id) values ($1, $2)",
+        in_cafe,
+        cafe
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let list = list_businesses(&app, &[("category_id", food.to_string())]).await;
+
+    assert_eq!(business_ids(&list), vec![in_cafe.to_string()]);
+    assert_eq!(list["data"][0]["categories"][0]["name"], "Cafe");
+}
+
+#[tokio::test]
+async fn businesses_are_filtered_by_area_and_areas_inside_it() {
+    let app = spawn_app().await;
+    let in_bayangol = insert_business(&app, "Sakura Cafe", true).await;
+    let in_khan_uul = insert_business(&app, "Other Cafe", true).await;
+
+    for (business_id, area) in [(in_bayangol, "Bayangol"), (in_khan_uul, "Khan Uul")] {
+        sqlx::query!(
+            r#"
+                insert into address (business_id, line_1, area_id)
+                values ($1, hstore('en', 'Street 1'), $2)
+            "#,
+            business_id,
+            area_id(&app, area).await
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    let list = list_businesses(
+        &app,
+        &[("area_id", area_id(&app, "Bayangol").await.to_string())],
+    )
+    .await;
+    assert_eq!(business_ids(&list), vec![in_bayangol.to_string()]);
+
+    let list = list_businesses(
+        &app,
+        &[("area_id", area_id(&app, "Ulaanbaatar").await.to_string())],
+    )
+    .await;
+    assert_eq!(business_ids(&list).len(), 2);
+}
+
+#[tokio::test]
+async fn list_businesses_paginates() {
+    let app = spawn_app().await;
+
+    for i in 0..30 {
+        insert_business(&app, &format!("Business {}", i), true).await;
+    }
+
+    let first = list_businesses(&app, &[]).await;
+    assert_eq!(business_ids(&first).len(), 25);
+
+    let cursor = first["next_cursor"].as_str().unwrap().to_string();
+    let second = list_businesses(&app, &[("cursor", cursor)]).await;
+    assert_eq!(business_ids(&second).len(), 5);
+    assert!(second["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn nearby_businesses_are_sorted_by_distance() {
+    let app = spawn_app().await;
+    let far = insert_business(&app, "Far Cafe", true).await;
+    let close = insert_business(&app, "Close Cafe", true).await;
+    let outside = insert_business(&app, "Outside Cafe", true).await;
+    let draft = insert_business(&app, "Draft Cafe", false).await;
+
+    insert_address_at(&app, far, LAT + 0.005, LNG).await;
+    insert_address_at(&app, close, LAT + 0.001, LNG).await;
+    // Only the closest address of a business counts
+    insert_address_at(&app, close, LAT + 0.004, LNG).await;
+    insert_address_at(&app, outside, LAT + 0.05, LNG).await;
+    insert_address_at(&app, draft, LAT, LNG).await;
+
+    let list = list_nearby_businesses(&app, &origin()).await;
+
+    assert_eq!(
+        business_ids(&list),
+        vec![close.to_string(), far.to_string()]
+    );
+
+    let distance = list["data"][0]["distance"].as_f64().unwrap();
+    assert!((100.0..120.0).contains(&distance), "distance {}", distance);
+    assert!(list["data"][1]["distance"].as_f64().unwrap() > distance);
+    assert!(list["next_cursor"].is_null());
+
+    let mut query = origin();
+    query.push(("radius", "10000".to_string()));
+    let list = list_nearby_businesses(&app, &query).await;
+    assert_eq!(business_ids(&list).len(), 3);
+}
+
+#[tokio::test]
+async fn nearby_businesses_are_filtered_by_category() {
+    let app = spawn_app().await;
+    let cafe = sqlx::query_scalar!(
+        "insert into category (name) values (hstore('en', 'Cafe')) returning category_id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let in_cafe = insert_business(&app, "Sakura Cafe", true).await;
+    let other = insert_business(&app, "Other Shop", true).await;
+    insert_address_at(&app, in_cafe, LAT + 0.002, LNG).await;
+    insert_address_at(&app, other, LAT + 0.001, LNG).await;
+
+    sqlx::query!(
+        "insert into business_category (business_id, category_id) values ($1, $2)",
+        in_cafe,
+        cafe
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let mut query = origin();
+    query.push(("category_id", cafe.to_string()));
+    let list = list_nearby_businesses(&app, &query).await;
+
+    assert_eq!(business_ids(&list), vec![in_cafe.to_string()]);
+}
+
+#[tokio::test]
+async fn nearby_businesses_paginate_by_distance() {
+    let app = spawn_app().await;
+
+    let mut expected = Vec::new();
+    for i in 0..30 {
+        let business_id = insert_business(&app, &format!("Business {}", i), true).await;
+        insert_address_at(&app, business_id, LAT + 0.0001 * i as f64, LNG).await;
+        expected.push(business_id.to_string());
+    }
+
+    let first = list_nearby_businesses(&app, &origin()).await;
+    assert_eq!(business_ids(&first), expected[..25]);
+
+    let mut query = origin();
+    query.push(("cursor", first["next_cursor"].as_str().unwrap().to_string()));
+    let second = list_nearby_businesses(&app, &query).await;
+    assert_eq!(business_ids(&second), expected[25..]);
+    assert!(second["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn nearby_businesses_reject_invalid_location_and_radius() {
+    let app = spawn_app().await;
+
+    for query in [
+        [("lat", "91"), ("lng", "106.9"), ("radius", "1000")],
+        [("lat", "47.9"), ("lng", "-181"), ("radius", "1000")],
+        [("lat", "47.9"), ("lng", "106.9"), ("radius", "0")],
+        [("lat", "47.9"), ("lng", "106.9"), ("radius", "50001")],
+    ] {
+        let res = app
+            .api_client
+            .get(format!("{}/businesses/nearby", &app.address))
+            .query(&query)
+            .send()
+            .await
+            .expect("failed to execute request");
+
+        assert_eq!(
+            res.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{:?}",
+            query
+        );
+    }
+}
+
+async fn search_businesses(app: &TestApp, query: &[(&str, &str)]) -> Value {
+    let res = app
+        .api_client
+        .get(format!("{}/businesses/search", &app.address))
+        .query(query)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json::<Value>().await.unwrap()
+}
+
+async fn insert_localized_business(app: &TestApp, en: &str, mn: &str) -> Uuid {
+    sqlx::query_scalar!(
+        r#"
+            insert into business (name, published_at)
+            values ($1, now())
+            returning business_id
+        "#,
+        localized(en, mn) as _
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("failed to add business")
+}
+
+#[tokio::test]
+async fn search_matches_across_scripts_and_highlights_the_locale() {
+    let app = spawn_app().await;
+    let business_id = insert_localized_business(&app, "Cherry Blossom", "Сакура Кафе").await;
+    insert_business(&app, "Draft Sakura", false).await;
+
+    let list = search_businesses(&app, &[("q", "Sakura")]).await;
+
+    assert_eq!(business_ids(&list), vec![business_id.to_string()]);
+    assert_eq!(list["data"][0]["highlight"]["locale"], "mn");
+    assert_eq!(
+        list["data"][0]["highlight"]["name"],
+        "<mark>Сакура</mark> Кафе"
+    );
+
+    let list = search_businesses(&app, &[("q", "черри")]).await;
+
+    assert_eq!(business_ids(&list), vec![business_id.to_string()]);
+    assert_eq!(list["data"][0]["highlight"]["locale"], "en");
+    assert_eq!(
+        list["data"][0]["highlight"]["name"],
+        "<mark>Cherry</mark> Blossom"
+    );
+}
+
+#[tokio::test]
+async fn search_ranks_closer_matches_first() {
+    let app = spawn_app().await;
+    let partial = insert_business(&app, "Sakura Sushi Cafe Bar", true).await;
+    let exact = insert_business(&app, "Sakura Cafe", true).await;
+    insert_business(&app, "Pizza Place", true).await;
+
+    let list = search_businesses(&app, &[("q", "sakura cafe")]).await;
+
+    assert_eq!(
+        business_ids(&list),
+        vec![exact.to_string(), partial.to_string()]
+    );
+    assert!(list["data"][0]["rank"].as_f64() > list["data"][1]["rank"].as_f64());
+}
+
+#[tokio::test]
+async fn search_matches_category_names() {
+    let app = spawn_app().await;
+    let coffee = sqlx::query_scalar!(
+        "insert into category (name) values ($1) returning category_id",
+        localized("Coffee shop", "Кофе шоп") as _
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let business_id = insert_business(&app, "Blue Door", true).await;
+    insert_business(&app, "Red Door", true).await;
+
+    sqlx::query!(
+        "insert into business_category (business_id, category_id) values ($1, $2)",
+        business_id,
+        coffee
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let list = search_businesses(&app, &[("q", "кофе")]).await;
+
+    assert_eq!(business_ids(&list), vec![business_id.to_string()]);
+    assert!(list["data"][0]["highlight"].is_null());
+}
+
+#[tokio::test]
+async fn search_rejects_short_query() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/businesses/search", &app.address))
+        .query(&[("q", "s")])
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
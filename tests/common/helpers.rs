@@ -3,15 +3,36 @@ use fake::{
     faker::internet::en::{Password, SafeEmail, Username},
     Fake,
 };
+use futures::future::BoxFuture;
 use nevermind::{
-    app::{get_db_connection_pool, get_redis_client, Application},
-    config::{get_configuration, AppConfig},
+    app::{
+        auth::email_change::EmailChangeWorker,
+        email::{
+            client::EmailClient,
+            queue::EmailWorker,
+            suppression::clear_expired_suppressions,
+            transport::{outbox::MemoryOutbox, EmailMessage, EmailTransport},
+        },
+        get_db_connection_pool, get_redis_client,
+        sms::transport::outbox::MemorySmsOutbox,
+        storage::{
+            avatar::AvatarMigrator,
+            image::ImageWorker,
+            multipart::MultipartCleaner,
+            orphan::{OrphanCleaner, OrphanReport},
+        },
+        Application,
+    },
+    config::{
+        get_configuration, AppConfig, EmailTransportConfig, OidcClaimMapping, OidcProviderConfig,
+        SmsTransportConfig, SnsVerifierConfig, StorageConfig,
+    },
     telemetry::{build_telemetry, register_telemetry},
 };
-use redis::AsyncCommands;
+use secrecy::SecretString;
 use serde::Deserialize;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use time::{format_description, OffsetDateTime};
 use uuid::Uuid;
 use wiremock::MockServer;
@@ -34,6 +55,15 @@ pub struct GrantResponse {
     pub access_token: String,
 }
 
+/// Fails every send, for testing retries
+struct FailingTransport;
+
+impl EmailTransport for FailingTransport {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move { anyhow::bail!("failed to send email to {}", message.to) })
+    }
+}
+
 pub struct TestApp {
     pub address: String,
     pub api_client: reqwest::Client,
@@ -42,6 +72,14 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub config: AppConfig,
     pub oauth_mock_server: MockServer,
+    pub email_outbox: Arc<MemoryOutbox>,
+    pub sms_outbox: Arc<MemorySmsOutbox>,
+    pub email_worker: EmailWorker,
+    pub email_change_worker: EmailChangeWorker,
+    pub image_worker: ImageWorker,
+    pub multipart_cleaner: MultipartCleaner,
+    pub orphan_cleaner: OrphanCleaner,
+    pub avatar_migrator: AvatarMigrator,
 }
 
 impl TestApp {
@@ -77,6 +115,173 @@ impl TestApp {
         user_tokens.access_token
     }
 
+    /// Runs the email worker once, sending every due email to `email_outbox`
+    pub async fn dispatch_emails(&self) {
+        self.email_worker
+            .process_batch()
+            .await
+            .expect("failed to process email outbox");
+    }
+
+    /// Runs the email worker once with a transport that fails every send
+    pub async fn dispatch_emails_failing(&self) {
+        let email_client = EmailClient::new(
+            Arc::new(FailingTransport),
+            &self.config.email.from_mail,
+            &self.config.frontend.url,
+        );
+
+        EmailWorker::new(
+            Arc::new(self.db_pool.clone()),
+            Arc::new(email_client),
+            self.config.email.outbox.clone(),
+        )
+        .process_batch()
+        .await
+        .expect("failed to process email outbox");
+    }
+
+    /// Deletes the sent and suppressed emails older than `email.outbox.retention_secs`
+    pub async fn purge_emails(&self) {
+        self.email_worker
+            .purge_expired()
+            .await
+            .expect("failed to purge email outbox");
+    }
+
+    /// Lifts the soft bounce suppressions past their expiry
+    pub async fn clear_expired_suppressions(&self) {
+        clear_expired_suppressions(&self.db_pool)
+            .await
+            .expect("failed to clear expired email suppressions");
+    }
+
+    /// Applies the primary email changes whose grace period is over
+    pub async fn apply_email_changes(&self) {
+        self.email_change_worker
+            .apply_due()
+            .await
+            .expect("failed to apply email changes");
+    }
+
+    /// Generates the variants of every confirmed image
+    pub async fn process_images(&self) {
+        self.image_worker
+            .process_batch()
+            .await
+            .expect("failed to process images");
+    }
+
+    /// Aborts the multipart uploads older than `multipart.abandon_after_secs`
+    pub async fn abort_abandoned_uploads(&self) {
+        self.multipart_cleaner
+            .abort_abandoned()
+            .await
+            .expect("failed to abort multipart uploads");
+    }
+
+    /// Deletes the orphaned objects older than `orphans.grace_period_secs`,
+    /// `dry_run` only reports them
+    pub async fn clean_orphans(&self, dry_run: bool) -> OrphanReport {
+        self.orphan_cleaner
+            .clean(dry_run)
+            .await
+            .expect("failed to clean up orphaned objects")
+    }
+
+    /// Replaces a batch of DiceBear avatars with generated ones
+    pub async fn migrate_avatars(&self) {
+        self.avatar_migrator
+            .migrate_batch()
+            .await
+            .expect("failed to migrate avatars");
+    }
+
+    /// Where the local storage keeps the object
+    pub fn stored_object_path(&self, key: &str) -> std::path::PathBuf {
+        let StorageConfig::Local { dir, .. } = &self.config.storage else {
+            panic!("tests use the local storage");
+        };
+
+        std::path::Path::new(dir).join("objects").join(key)
+    }
+
+    /// Moves the modification time of the object two days back, past the grace period
+    pub fn age_stored_object(&self, key: &str) {
+        let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 86400);
+
+        std::fs::File::options()
+            .write(true)
+            .open(self.stored_object_path(key))
+            .and_then(|file| file.set_modified(modified))
+            .expect("failed to age stored object");
+    }
+
+    /// The code in the last email sent to `email`, codes are only stored hashed
+    pub async fn last_email_code(&self, email: &str) -> String {
+        self.dispatch_emails().await;
+
+        let message = self
+            .email_outbox
+            .last_to(email)
+            .await
+            .expect("email was not sent");
+
+        message.data["code"]
+            .as_str()
+            .expect("email has no code")
+            .to_string()
+    }
+
+    /// Uploads a profile image through local storage and confirms it, returns the key
+    pub async fn upload_profile_image(&self, token: &str) -> String {
+        let bytes = test_png(640, 480);
+
+        let res = self
+            .api_client
+            .post(format!("{}/upload", &self.address))
+            .header("Authorization", "Bearer ".to_owned() + token)
+            .json(&serde_json::json!({
+                "path": "Profile",
+                "file_name": format!("{}.png", Uuid::new_v4()),
+                "file_type": "image/png",
+                "file_size": bytes.len()
+            }))
+            .send()
+            .await
+            .expect("failed to execute request");
+
+        assert!(res.status().is_success());
+        let upload = res.json::<serde_json::Value>().await.unwrap();
+
+        let res = self
+            .api_client
+            .put(upload["uri"].as_str().unwrap())
+            .header("content-type", "image/png")
+            .body(bytes)
+            .send()
+            .await
+            .expect("failed to execute request");
+
+        assert!(res.status().is_success());
+
+        let res = self
+            .api_client
+            .post(format!(
+                "{}/upload/{}/confirm",
+                &self.address,
+                upload["upload_id"].as_str().unwrap()
+            ))
+            .header("Authorization", "Bearer ".to_owned() + token)
+            .send()
+            .await
+            .expect("failed to execute request");
+
+        assert!(res.status().is_success());
+
+        upload["key"].as_str().unwrap().to_string()
+    }
+
     pub async fn add_role(&self, role: &str) {
         sqlx::query!(
             r#"
@@ -90,6 +295,35 @@ impl TestApp {
         .await
         .expect("failed to add role");
     }
+
+    /// A business with the test user as its owner
+    pub async fn create_business(&self) -> Uuid {
+        let business_id = sqlx::query_scalar!(
+            r#"
+                insert into business (name)
+                values (hstore('en', $1))
+                returning business_id
+            "#,
+            Uuid::new_v4().to_string()
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .expect("failed to create business");
+
+        sqlx::query!(
+            r#"
+                insert into business_member (business_id, user_id, role)
+                values ($1, $2, 'owner')
+            "#,
+            business_id,
+            &self.test_user.user_id
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("failed to add business member");
+
+        business_id
+    }
 }
 
 pub struct TestUser {
@@ -145,7 +379,26 @@ impl TestUser {
     }
 }
 
+/// A valid PNG, so the uploads can be processed
+pub fn test_png(width: u32, height: u32) -> Vec<u8> {
+    let image = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+    });
+
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, image::ImageFormat::Png)
+        .expect("failed to encode png");
+
+    bytes.into_inner()
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, `configure` can change the test configuration before the app is built
+pub async fn spawn_app_with(configure: impl FnOnce(&mut AppConfig)) -> TestApp {
     LazyLock::force(&TELEMETRY);
 
     // lauch github oauth mock
@@ -176,6 +429,42 @@ pub async fn spawn_app() -> TestApp {
         c.discord.api_base_url = oauth_mock_server.uri();
         c.discord.token_url = format!("{}/api/v10/oauth2/token", oauth_mock_server.uri());
 
+        c.facebook.api_base_url = oauth_mock_server.uri();
+        c.facebook.token_url = format!("{}/oauth/access_token", oauth_mock_server.uri());
+
+        c.oidc.insert(
+            "test".to_string(),
+            OidcProviderConfig {
+                issuer: format!("{}/oidc", oauth_mock_server.uri()),
+                id: "client-id".to_string(),
+                secret: SecretString::from("client-secret"),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+                claims: OidcClaimMapping::default(),
+            },
+        );
+
+        // Keep sent emails and text messages around for assertions
+        c.email.transport = EmailTransportConfig::Memory;
+        c.sms.transport = SmsTransportConfig::Memory;
+
+        // Uploads go to a fresh directory instead of S3
+        c.storage = StorageConfig::Local {
+            dir: std::env::temp_dir()
+                .join(format!("nevermind-storage-{}", Uuid::new_v4()))
+                .to_string_lossy()
+                .into_owned(),
+            public_url: None,
+        };
+
+        // Sign SNS notifications with the fixture key instead of AWS certificates
+        c.email.webhook.topic_arns =
+            vec!["arn:aws:sns:us-east-1:123456789012:ses-notifications".to_string()];
+        c.email.webhook.verifier = SnsVerifierConfig::Local {
+            public_key: include_str!("../fixtures/sns_test_key.pub.pem").into(),
+        };
+
+        configure(&mut c);
+
         c
     };
 
@@ -188,19 +477,27 @@ pub async fn spawn_app() -> TestApp {
 
     let db_pool = get_db_connection_pool(&app_config);
     let redis_client = get_redis_client(&app_config);
-    let mut test_app = TestApp {
-        address: "".to_string(),
+
+    let app = Application::build(app_config.clone()).await.unwrap();
+    let test_app = TestApp {
+        address: format!("http://localhost:{}", &app.port),
         api_client,
         db_pool,
         redis_client,
         test_user: TestUser::generate(),
-        config: app_config.clone(),
+        config: app_config,
         oauth_mock_server,
+        email_outbox: app.email_outbox.clone().expect("memory outbox"),
+        // Stays empty when `configure` picks another sms transport
+        sms_outbox: app.sms_outbox.clone().unwrap_or_default(),
+        email_worker: app.email_worker.clone(),
+        email_change_worker: app.email_change_worker.clone(),
+        image_worker: app.image_worker.clone(),
+        multipart_cleaner: app.multipart_cleaner.clone(),
+        orphan_cleaner: app.orphan_cleaner.clone(),
+        avatar_migrator: app.avatar_migrator.clone(),
     };
 
-    let app = Application::build(app_config).await.unwrap();
-    test_app.address = format!("http://localhost:{}", &app.port);
-
     _ = tokio::spawn(app.run_until_stopped());
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -273,47 +570,11 @@ pub async fn register_new_user(app: &TestApp) -> RegisterNewUserRes {
 
     let user_tokens = login_res.json::<GrantResponse>().await.unwrap();
 
-    let user_id = sqlx::query_scalar!(
-        r#"
-            select user_id
-            from "user"
-            where username = $1
-        "#,
-        new_user.username
-    )
-    .fetch_one(&app.db_pool)
-    .await
-    .unwrap();
-
-    let mut conn = app
-        .redis_client
-        .get_multiplexed_tokio_connection()
-        .await
-        .unwrap();
-    let key = format!("user:{}:email:*", user_id);
-    let otps: Vec<String> = conn
-        .keys(key)
-        .await
-        .expect("not error when connecting to redis");
-
-    let current_otp = get_first_otp(&otps);
-    assert!(current_otp.is_some(), "Expected a otp but found None");
-
-    let current_otp = current_otp.unwrap();
+    let otp = app.last_email_code(&new_user.email).await;
 
     RegisterNewUserRes {
         access_token: user_tokens.access_token,
-        otp: current_otp,
+        otp,
         new_user,
     }
 }
-
-fn get_first_otp(vec: &[String]) -> Option<String> {
-    if let Some(first) = vec.get(0) {
-        let parts: Vec<&str> = first.split(':').collect();
-        if !parts.is_empty() {
-            return Some(parts.last().unwrap().to_string());
-        }
-    }
-    None
-}
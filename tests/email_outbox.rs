@@ -0,0 +1,345 @@
+use nevermind::app::email::queue::EmailStatus;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub mod common;
+use common::helpers::{spawn_app, TestApp, TestUser};
+
+#[derive(Debug, Deserialize)]
+struct EmailListResponse {
+    data: Vec<OutboxEmail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutboxEmail {
+    email_outbox_id: Uuid,
+    recipient: String,
+    status: EmailStatus,
+    attempts: i32,
+    last_error: Option<String>,
+    data: Option<serde_json::Value>,
+}
+
+async fn forgot_password(app: &TestApp) {
+    let res = app
+        .api_client
+        .post(format!("{}/auth/forgot-password", &app.address))
+        .json(&serde_json::json!({ "email": &app.test_user.email }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+}
+
+async fn has_outbox_data(app: &TestApp, id: Uuid) -> bool {
+    sqlx::query_scalar!(
+        r#"select data is not null as "has_data!" from email_outbox where email_outbox_id = $1"#,
+        id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn outbox_row_exists(app: &TestApp, id: Uuid) -> bool {
+    sqlx::query_scalar!(
+        r#"select exists(select 1 from email_outbox where email_outbox_id = $1) as "exists!""#,
+        id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn get_outbox_row(app: &TestApp, recipient: &str) -> (Uuid, EmailStatus, i32) {
+    let row = sqlx::query!(
+        r#"
+            select email_outbox_id, status as "status: EmailStatus", attempts
+            from email_outbox
+            where recipient = $1
+            order by created_at desc
+            limit 1
+        "#,
+        recipient
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("email was not queued");
+
+    (row.email_outbox_id, row.status, row.attempts)
+}
+
+#[tokio::test]
+async fn register_queues_email_until_worker_runs() {
+    let app = spawn_app().await;
+
+    let new_user = TestUser::generate();
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/users", &app.address))
+        .json(&serde_json::json!({
+            "email": &new_user.email,
+            "username": &new_user.username,
+            "password": &new_user.password
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    let (_, status, _) = get_outbox_row(&app, &new_user.email).await;
+    assert_eq!(status, EmailStatus::Queued);
+    assert!(app.email_outbox.last_to(&new_user.email).await.is_none());
+
+    app.dispatch_emails().await;
+
+    let (id, status, attempts) = get_outbox_row(&app, &new_user.email).await;
+    assert_eq!(status, EmailStatus::Sent);
+    assert_eq!(attempts, 1);
+    assert!(app.email_outbox.last_to(&new_user.email).await.is_some());
+
+    // The verification code isn't kept once sent
+    assert!(!has_outbox_data(&app, id).await);
+}
+
+#[tokio::test]
+async fn sent_emails_are_purged_after_retention() {
+    let app = spawn_app().await;
+
+    forgot_password(&app).await;
+    app.dispatch_emails().await;
+
+    let (id, _, _) = get_outbox_row(&app, &app.test_user.email).await;
+
+    app.purge_emails().await;
+    assert!(outbox_row_exists(&app, id).await);
+
+    sqlx::query!(
+        "update email_outbox set created_at = now() - interval '30 days' where email_outbox_id = $1",
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.purge_emails().await;
+    assert!(!outbox_row_exists(&app, id).await);
+}
+
+#[tokio::test]
+async fn failed_email_is_retried_with_backoff() {
+    let app = spawn_app().await;
+
+    forgot_password(&app).await;
+    app.dispatch_emails_failing().await;
+
+    let row = sqlx::query!(
+        r#"
+            select status as "status: EmailStatus", attempts, last_error,
+                next_attempt_at > now() as "delayed!"
+            from email_outbox
+            where recipient = $1
+        "#,
+        app.test_user.email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(row.status, EmailStatus::Queued);
+    assert_eq!(row.attempts, 1);
+    assert!(row.last_error.is_some());
+    assert!(row.delayed);
+
+    // Not due yet, so nothing is attempted
+    app.dispatch_emails_failing().await;
+    let (_, _, attempts) = get_outbox_row(&app, &app.test_user.email).await;
+    assert_eq!(attempts, 1);
+}
+
+#[tokio::test]
+async fn email_fails_after_max_attempts() {
+    let app = spawn_app().await;
+
+    forgot_password(&app).await;
+
+    for _ in 0..app.config.email.outbox.max_attempts {
+        // Skip the backoff
+        sqlx::query!("update email_outbox set next_attempt_at = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+
+        app.dispatch_emails_failing().await;
+    }
+
+    let (id, status, attempts) = get_outbox_row(&app, &app.test_user.email).await;
+    assert_eq!(status, EmailStatus::Failed);
+    assert_eq!(attempts, app.config.email.outbox.max_attempts);
+
+    // Kept so an admin can queue it again
+    assert!(has_outbox_data(&app, id).await);
+}
+
+#[tokio::test]
+async fn email_claimed_by_another_worker_is_sent_after_its_lease() {
+    let app = spawn_app().await;
+
+    forgot_password(&app).await;
+
+    // Another worker is sending it
+    sqlx::query!(
+        r#"
+            update email_outbox
+            set status = 'sending', next_attempt_at = now() + interval '5 minutes'
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_emails().await;
+    assert!(app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .is_none());
+
+    // The worker stopped before it finished
+    sqlx::query!("update email_outbox set next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.dispatch_emails().await;
+
+    let (_, status, attempts) = get_outbox_row(&app, &app.test_user.email).await;
+    assert_eq!(status, EmailStatus::Sent);
+    assert_eq!(attempts, 1);
+}
+
+#[tokio::test]
+async fn email_whose_worker_never_finishes_runs_out_of_attempts() {
+    let app = spawn_app().await;
+
+    forgot_password(&app).await;
+
+    // Claimed for every attempt, and the lease of the last one ran out
+    sqlx::query!(
+        r#"
+            update email_outbox
+            set status = 'sending', attempts = $1, next_attempt_at = now()
+        "#,
+        app.config.email.outbox.max_attempts
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_emails().await;
+
+    let (_, status, _) = get_outbox_row(&app, &app.test_user.email).await;
+    assert_eq!(status, EmailStatus::Failed);
+    assert!(app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn admin_can_list_and_retry_failed_emails() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    forgot_password(&app).await;
+
+    for _ in 0..app.config.email.outbox.max_attempts {
+        // Skip the backoff
+        sqlx::query!("update email_outbox set next_attempt_at = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+
+        app.dispatch_emails_failing().await;
+    }
+
+    let (id, status, _) = get_outbox_row(&app, &app.test_user.email).await;
+    assert_eq!(status, EmailStatus::Failed);
+
+    let res = app
+        .api_client
+        .get(format!("{}/admin/emails", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .query(&[("status", "failed")])
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let data = res.json::<EmailListResponse>().await.unwrap().data;
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0].email_outbox_id, id);
+    assert_eq!(data[0].recipient, app.test_user.email);
+    assert_eq!(data[0].status, EmailStatus::Failed);
+    assert_eq!(data[0].attempts, app.config.email.outbox.max_attempts);
+    assert!(data[0].last_error.is_some());
+
+    // Admins can't use the reset token
+    let email_data = data[0].data.as_ref().expect("queued email has data");
+    assert_eq!(email_data["code"], "[redacted]");
+    assert_eq!(email_data["reset_link"], "[redacted]");
+
+    let res = app
+        .api_client
+        .post(format!("{}/admin/emails/{}/retry", &app.address, id))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    app.dispatch_emails().await;
+
+    let (_, status, _) = get_outbox_row(&app, &app.test_user.email).await;
+    assert_eq!(status, EmailStatus::Sent);
+    assert!(app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .is_some());
+
+    // Only failed emails can be retried
+    let res = app
+        .api_client
+        .post(format!("{}/admin/emails/{}/retry", &app.address, id))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn list_emails_requires_permission() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/admin/emails", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,68 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+pub mod common;
+use common::helpers::spawn_app;
+
+#[derive(Deserialize)]
+struct RenderedEmail {
+    subject: String,
+    html: String,
+    text: String,
+}
+
+#[tokio::test]
+async fn preview_email_renders_sample_data() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/dev/emails/EmailVerify", &app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let data = res.json::<RenderedEmail>().await.unwrap();
+
+    assert_eq!(data.subject, "Verify Your Email Address");
+    assert!(data.html.contains("A1B2C3D4"));
+    assert!(data.text.contains("A1B2C3D4"));
+}
+
+#[tokio::test]
+async fn preview_email_uses_locale_variant() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/dev/emails/PasswordReset", &app.address))
+        .query(&[("locale", "mn"), ("format", "html")])
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res
+        .headers()
+        .get("content-type")
+        .is_some_and(|v| v.to_str().unwrap().starts_with("text/html")));
+
+    let html = res.text().await.unwrap();
+    assert!(html.contains("Нууц үг сэргээх"));
+}
+
+#[tokio::test]
+async fn preview_email_returns_404_for_unknown_template() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(format!("{}/dev/emails/Unknown", &app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
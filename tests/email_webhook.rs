@@ -0,0 +1,431 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use nevermind::app::email::{queue::EmailStatus, suppression::SuppressionReason};
+use reqwest::StatusCode;
+use rsa::{
+    pkcs1v15::SigningKey,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+    RsaPrivateKey,
+};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+pub mod common;
+use common::helpers::{spawn_app, TestApp};
+
+const TOPIC_ARN: &str = "arn:aws:sns:us-east-1:123456789012:ses-notifications";
+
+/// Signs the message like SNS does with `SignatureVersion` 2
+fn sign(mut message: Value) -> Value {
+    let fields: &[&str] = if message["Type"] == "Notification" {
+        &[
+            "Message",
+            "MessageId",
+            "Subject",
+            "Timestamp",
+            "TopicArn",
+            "Type",
+        ]
+    } else {
+        &[
+            "Message",
+            "MessageId",
+            "SubscribeURL",
+            "Timestamp",
+            "Token",
+            "TopicArn",
+            "Type",
+        ]
+    };
+
+    let string_to_sign: String = fields
+        .iter()
+        .filter_map(|field| {
+            message[*field]
+                .as_str()
+                .map(|value| format!("{}\n{}\n", field, value))
+        })
+        .collect();
+
+    let key = RsaPrivateKey::from_pkcs8_pem(include_str!("fixtures/sns_test_key.pem")).unwrap();
+    let signature = SigningKey::<Sha256>::new(key).sign(string_to_sign.as_bytes());
+
+    message["SignatureVersion"] = json!("2");
+    message["Signature"] = json!(STANDARD.encode(signature.to_bytes()));
+    message
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc().format(&Rfc3339).unwrap()
+}
+
+fn notification(ses_message: Value) -> Value {
+    sign(json!({
+        "Type": "Notification",
+        "MessageId": Uuid::new_v4().to_string(),
+        "TopicArn": TOPIC_ARN,
+        "Message": ses_message.to_string(),
+        "Timestamp": now(),
+        "SigningCertURL": "https://sns.us-east-1.amazonaws.com/SimpleNotificationService.pem",
+    }))
+}
+
+fn bounce(email: &str, bounce_type: &str) -> Value {
+    notification(json!({
+        "notificationType": "Bounce",
+        "bounce": {
+            "bounceType": bounce_type,
+            "bounceSubType": "General",
+            "bouncedRecipients": [{ "emailAddress": email }]
+        },
+        "mail": { "destination": [email] }
+    }))
+}
+
+async fn post_webhook(app: &TestApp, body: &Value) -> reqwest::Response {
+    // SNS posts the JSON as text/plain
+    app.api_client
+        .post(format!("{}/webhooks/ses", &app.address))
+        .header("Content-Type", "text/plain; charset=UTF-8")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn get_suppression(app: &TestApp, email: &str) -> Option<SuppressionReason> {
+    sqlx::query_scalar!(
+        r#"
+            select reason as "reason: SuppressionReason"
+            from email_suppression
+            where email = $1
+        "#,
+        email
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn get_delivery_issue(app: &TestApp, email: &str) -> Option<SuppressionReason> {
+    sqlx::query_scalar!(
+        r#"
+            select delivery_issue as "delivery_issue: SuppressionReason"
+            from email
+            where email = $1
+        "#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn permanent_bounce_suppresses_address() {
+    let app = spawn_app().await;
+
+    let res = post_webhook(&app, &bounce(&app.test_user.email, "Permanent")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        get_suppression(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Bounce)
+    );
+    assert_eq!(
+        get_delivery_issue(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Bounce)
+    );
+
+    // user is prompted to replace the address
+    let token = app.login_and_get_token().await;
+    let me = app
+        .api_client
+        .get(format!("{}/auth/me", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json::<Value>()
+        .await
+        .unwrap();
+
+    assert_eq!(me["email_delivery_issue"], "bounce");
+}
+
+#[tokio::test]
+async fn complaint_suppresses_address() {
+    let app = spawn_app().await;
+
+    let body = notification(json!({
+        "notificationType": "Complaint",
+        "complaint": {
+            "complaintFeedbackType": "abuse",
+            "complainedRecipients": [{ "emailAddress": &app.test_user.email }]
+        },
+        "mail": { "destination": [&app.test_user.email] }
+    }));
+
+    let res = post_webhook(&app, &body).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        get_suppression(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Complaint)
+    );
+    assert_eq!(
+        get_delivery_issue(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Complaint)
+    );
+}
+
+#[tokio::test]
+async fn transient_bounce_suppresses_address_until_it_expires() {
+    let app = spawn_app().await;
+
+    let res = post_webhook(&app, &bounce(&app.test_user.email, "Transient")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        get_suppression(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Transient)
+    );
+    assert_eq!(
+        get_delivery_issue(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Transient)
+    );
+
+    // Not expired yet
+    app.clear_expired_suppressions().await;
+    assert_eq!(
+        get_suppression(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Transient)
+    );
+
+    sqlx::query!(
+        "update email_suppression set expires_at = now() - interval '1 second' where email = $1",
+        &app.test_user.email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.clear_expired_suppressions().await;
+
+    assert_eq!(get_suppression(&app, &app.test_user.email).await, None);
+    assert_eq!(get_delivery_issue(&app, &app.test_user.email).await, None);
+}
+
+#[tokio::test]
+async fn transient_bounce_does_not_replace_permanent_suppression() {
+    let app = spawn_app().await;
+
+    let res = post_webhook(&app, &bounce(&app.test_user.email, "Permanent")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = post_webhook(&app, &bounce(&app.test_user.email, "Transient")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        get_suppression(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Bounce)
+    );
+    assert_eq!(
+        get_delivery_issue(&app, &app.test_user.email).await,
+        Some(SuppressionReason::Bounce)
+    );
+}
+
+#[tokio::test]
+async fn admin_can_lift_suppression() {
+    let app = spawn_app().await;
+    app.add_role("root").await;
+    let token = app.login_and_get_token().await;
+
+    let res = post_webhook(&app, &bounce(&app.test_user.email, "Permanent")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let url = format!(
+        "{}/admin/emails/suppressions/{}",
+        &app.address, &app.test_user.email
+    );
+
+    let res = app
+        .api_client
+        .delete(&url)
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(get_suppression(&app, &app.test_user.email).await, None);
+    assert_eq!(get_delivery_issue(&app, &app.test_user.email).await, None);
+
+    // Nothing left to lift
+    let res = app
+        .api_client
+        .delete(&url)
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn lift_suppression_requires_permission() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = app
+        .api_client
+        .delete(format!(
+            "{}/admin/emails/suppressions/{}",
+            &app.address, &app.test_user.email
+        ))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn invalid_signature_is_rejected() {
+    let app = spawn_app().await;
+
+    let mut body = bounce(&app.test_user.email, "Permanent");
+    body["MessageId"] = json!(Uuid::new_v4().to_string());
+
+    let res = post_webhook(&app, &body).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    assert_eq!(get_suppression(&app, &app.test_user.email).await, None);
+}
+
+#[tokio::test]
+async fn message_from_unlisted_topic_is_rejected() {
+    let app = spawn_app().await;
+
+    Mock::given(method("GET"))
+        .and(path("/sns/confirm"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.oauth_mock_server)
+        .await;
+
+    // Validly signed, but published to a topic of another account
+    let topic_arn = "arn:aws:sns:us-east-1:210987654321:ses-notifications";
+
+    let mut body = bounce(&app.test_user.email, "Permanent");
+    body["TopicArn"] = json!(topic_arn);
+    let body = sign(body);
+
+    let res = post_webhook(&app, &body).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(get_suppression(&app, &app.test_user.email).await, None);
+
+    let body = sign(json!({
+        "Type": "SubscriptionConfirmation",
+        "MessageId": Uuid::new_v4().to_string(),
+        "Token": "token",
+        "TopicArn": topic_arn,
+        "Message": "You have chosen to subscribe to the topic",
+        "SubscribeURL": format!("{}/sns/confirm", app.oauth_mock_server.uri()),
+        "Timestamp": now(),
+        "SigningCertURL": "https://sns.us-east-1.amazonaws.com/SimpleNotificationService.pem",
+    }));
+
+    let res = post_webhook(&app, &body).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn suppressed_address_is_not_sent_to() {
+    let app = spawn_app().await;
+
+    let res = post_webhook(&app, &bounce(&app.test_user.email, "Permanent")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = app
+        .api_client
+        .post(format!("{}/auth/forgot-password", &app.address))
+        .json(&json!({ "email": &app.test_user.email }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(res.status().is_success());
+
+    app.dispatch_emails().await;
+
+    let status = sqlx::query_scalar!(
+        r#"
+            select status as "status: EmailStatus"
+            from email_outbox
+            where recipient = $1
+        "#,
+        &app.test_user.email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(status, EmailStatus::Suppressed);
+    assert!(app
+        .email_outbox
+        .last_to(&app.test_user.email)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn subscription_is_confirmed() {
+    let app = spawn_app().await;
+
+    Mock::given(method("GET"))
+        .and(path("/sns/confirm"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.oauth_mock_server)
+        .await;
+
+    let body = sign(json!({
+        "Type": "SubscriptionConfirmation",
+        "MessageId": Uuid::new_v4().to_string(),
+        "Token": "token",
+        "TopicArn": TOPIC_ARN,
+        "Message": "You have chosen to subscribe to the topic",
+        "SubscribeURL": format!("{}/sns/confirm", app.oauth_mock_server.uri()),
+        "Timestamp": now(),
+        "SigningCertURL": "https://sns.us-east-1.amazonaws.com/SimpleNotificationService.pem",
+    }));
+
+    let res = post_webhook(&app, &body).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn stale_notification_is_rejected() {
+    let app = spawn_app().await;
+
+    let sent_at = OffsetDateTime::now_utc()
+        - time::Duration::seconds(app.config.email.webhook.max_message_age_secs + 60);
+    // Signed with the old timestamp, like a captured message
+    let mut body = bounce(&app.test_user.email, "Permanent");
+    body["Timestamp"] = json!(sent_at.format(&Rfc3339).unwrap());
+    let body = sign(body);
+
+    let res = post_webhook(&app, &body).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(get_suppression(&app, &app.test_user.email).await, None);
+}
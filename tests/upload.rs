@@ -1,9 +1,13 @@
+use std::collections::HashMap;
+
 use fake::{faker::filesystem::en::FileName, Fake};
-use nevermind::app::storage::path::S3Path;
+use nevermind::app::{storage::path::S3Path, utils::avatar_generator};
 use reqwest::StatusCode;
+use serde::Deserialize;
+use uuid::Uuid;
 
 pub mod common;
-use common::helpers::spawn_app;
+use common::helpers::{register_new_user, spawn_app, TestApp};
 
 #[tokio::test]
 async fn upload_profile_works() {
@@ -79,3 +83,935 @@ async fn upload_profile_fails_for_wrong_size() {
 
     assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
+
+#[tokio::test]
+async fn upload_fails_for_non_positive_size() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    for file_size in [0, -1_000_000] {
+        for (endpoint, path, file_type) in [
+            ("upload", S3Path::Profile, "image/jpeg"),
+            ("upload/multipart", S3Path::BusinessMenu, "application/pdf"),
+        ] {
+            let res = app
+                .api_client
+                .post(format!("{}/{}", &app.address, endpoint))
+                .header("Authorization", "Bearer ".to_owned() + &token)
+                .json(&serde_json::json!({
+                    "path": path,
+                    "business_id": business_id,
+                    "file_name": "file",
+                    "file_type": file_type,
+                    "file_size": file_size
+                }))
+                .send()
+                .await
+                .expect("Failed to execute request.");
+
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PresignedUpload {
+    upload_id: String,
+    key: String,
+    uri: String,
+    method: String,
+    headers: HashMap<String, String>,
+}
+
+async fn presign_profile(app: &TestApp, token: &str, file_size: i64) -> PresignedUpload {
+    let img_body = serde_json::json!({
+        "path": S3Path::Profile,
+        "file_name": "avatar.png",
+        "file_type": "image/png",
+        "file_size": file_size
+    });
+
+    let res = app
+        .api_client
+        .post(format!("{}/upload", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&img_body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+
+    res.json().await.unwrap()
+}
+
+async fn send_upload(
+    app: &TestApp,
+    uri: &str,
+    presigned: &PresignedUpload,
+    body: Vec<u8>,
+) -> reqwest::Response {
+    let mut req = app
+        .api_client
+        .request(presigned.method.parse().unwrap(), uri)
+        .body(body);
+
+    for (key, value) in &presigned.headers {
+        if key != "content-length" {
+            req = req.header(key, value);
+        }
+    }
+
+    req.send().await.expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn upload_to_local_storage_works() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![1, 2, 3, 4]).await;
+
+    assert!(res.status().is_success());
+
+    // served back from the public url
+    let public_url = presigned.uri.split('?').next().unwrap();
+    let res = app
+        .api_client
+        .get(public_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    assert_eq!(res.headers()["content-type"], "image/png");
+    assert_eq!(res.bytes().await.unwrap().as_ref(), &[1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn upload_to_local_storage_fails_for_other_size() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![1, 2, 3, 4, 5]).await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn upload_to_local_storage_fails_for_tampered_url() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let tampered = presigned
+        .uri
+        .replace("content_length=4", "content_length=5");
+    let res = send_upload(&app, &tampered, &presigned, vec![1, 2, 3, 4, 5]).await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+async fn confirm(app: &TestApp, token: &str, upload_id: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/upload/{}/confirm", &app.address, upload_id))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn confirm_upload_works() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![1, 2, 3, 4]).await;
+    assert!(res.status().is_success());
+
+    let res = confirm(&app, &token, &presigned.upload_id).await;
+    assert!(res.status().is_success());
+
+    #[derive(Deserialize)]
+    struct ConfirmedUpload {
+        key: String,
+        url: String,
+        status: String,
+    }
+
+    let confirmed = res.json::<ConfirmedUpload>().await.unwrap();
+    assert_eq!(confirmed.key, presigned.key);
+    assert_eq!(confirmed.status, "confirmed");
+
+    let res = app
+        .api_client
+        .get(&confirmed.url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn confirm_upload_fails_before_upload() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = confirm(&app, &token, &presigned.upload_id).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let status = sqlx::query_scalar!(
+        r#"select status::text as "status!" from upload where key = $1"#,
+        presigned.key
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(status, "pending");
+}
+
+#[tokio::test]
+async fn confirm_upload_fails_for_other_user() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![1, 2, 3, 4]).await;
+    assert!(res.status().is_success());
+
+    let other = register_new_user(&app).await;
+    let res = confirm(&app, &other.access_token, &presigned.upload_id).await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn profile_image_variants_are_generated() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let key = app.upload_profile_image(&token).await;
+    let res = app
+        .api_client
+        .patch(format!("{}/auth/me", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({ "image": key }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+
+    app.process_images().await;
+
+    let me = app
+        .api_client
+        .get(format!("{}/auth/me", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+
+    let urls = &me["image_urls"];
+    for (variant, side) in [("thumbnail", 128), ("medium", 512)] {
+        let res = app
+            .api_client
+            .get(urls[variant].as_str().unwrap())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(res.headers()["content-type"], "image/webp");
+
+        let variant = image::load_from_memory(&res.bytes().await.unwrap()).unwrap();
+        assert_eq!(variant.width(), side);
+    }
+}
+
+#[tokio::test]
+async fn undecodable_image_is_marked_failed() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![1, 2, 3, 4]).await;
+    assert!(res.status().is_success());
+    let res = confirm(&app, &token, &presigned.upload_id).await;
+    assert!(res.status().is_success());
+
+    app.process_images().await;
+
+    let error = sqlx::query_scalar!(
+        "select processing_error from upload where key = $1",
+        presigned.key
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert!(error.is_some());
+}
+
+#[tokio::test]
+async fn image_whose_worker_never_finishes_runs_out_of_attempts() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let presigned = presign_profile(&app, &token, 4).await;
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![1, 2, 3, 4]).await;
+    assert!(res.status().is_success());
+    let res = confirm(&app, &token, &presigned.upload_id).await;
+    assert!(res.status().is_success());
+
+    // Claimed by another worker for every attempt
+    let claim = |started_secs_ago: f64| {
+        sqlx::query!(
+            r#"
+                update upload
+                set processing_started_at = now() - make_interval(secs => $1),
+                    processing_attempts = $2
+                where key = $3
+            "#,
+            started_secs_ago,
+            app.config.images.max_attempts,
+            presigned.key
+        )
+        .execute(&app.db_pool)
+    };
+    let processing = || {
+        sqlx::query!(
+            "select processed_at, processing_error from upload where key = $1",
+            presigned.key
+        )
+        .fetch_one(&app.db_pool)
+    };
+
+    // Left alone while the lease of the last attempt runs
+    claim(0.0).await.unwrap();
+    app.process_images().await;
+    assert!(processing().await.unwrap().processed_at.is_none());
+
+    claim(app.config.images.lease_secs as f64 + 1.0)
+        .await
+        .unwrap();
+    app.process_images().await;
+
+    let row = processing().await.unwrap();
+    assert!(row.processed_at.is_some());
+    assert_eq!(
+        row.processing_error.as_deref(),
+        Some("lease ran out on the last attempt")
+    );
+}
+
+async fn presign_business(
+    app: &TestApp,
+    token: &str,
+    path: S3Path,
+    business_id: Option<Uuid>,
+) -> reqwest::Response {
+    let body = serde_json::json!({
+        "path": path,
+        "business_id": business_id,
+        "file_name": "registration.pdf",
+        "file_type": "application/pdf",
+        "file_size": 2_000_000
+    });
+
+    app.api_client
+        .post(format!("{}/upload", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn upload_business_document_works_for_member() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let res = presign_business(&app, &token, S3Path::BusinessDocument, Some(business_id)).await;
+    assert!(res.status().is_success());
+
+    let presigned = res.json::<PresignedUpload>().await.unwrap();
+    assert_eq!(
+        presigned.key,
+        format!(
+            "private/business/{}/documents/registration.pdf",
+            business_id
+        )
+    );
+
+    let res = send_upload(&app, &presigned.uri, &presigned, vec![0; 2_000_000]).await;
+    assert!(res.status().is_success());
+
+    let res = confirm(&app, &token, &presigned.upload_id).await;
+    assert!(res.status().is_success());
+
+    // Private, only reachable through a download URL
+    let confirmed = res.json::<serde_json::Value>().await.unwrap();
+    assert!(confirmed["url"].is_null());
+}
+
+#[tokio::test]
+async fn business_document_uploaded_again_by_other_member_can_be_confirmed() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let presigned = upload_business_document(&app, &token).await;
+
+    let business_id = sqlx::query_scalar!(
+        "select business_id from upload where key = $1",
+        presigned.key
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .unwrap();
+
+    let other = register_new_user(&app).await;
+    sqlx::query!(
+        r#"
+            insert into business_member (business_id, user_id, role)
+            select $1, user_id, 'owner' from "user" where username = $2
+        "#,
+        business_id,
+        other.new_user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Same file name, so the same key as the first upload
+    let res = presign_business(
+        &app,
+        &other.access_token,
+        S3Path::BusinessDocument,
+        Some(business_id),
+    )
+    .await;
+    let again = res.json::<PresignedUpload>().await.unwrap();
+    assert_eq!(again.key, presigned.key);
+
+    let res = send_upload(&app, &again.uri, &again, vec![8; 2_000_000]).await;
+    assert!(res.status().is_success());
+
+    let res = confirm(&app, &other.access_token, &again.upload_id).await;
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn upload_business_file_fails_for_non_member() {
+    let app = spawn_app().await;
+    let business_id = app.create_business().await;
+
+    let other = register_new_user(&app).await;
+    let res = presign_business(
+        &app,
+        &other.access_token,
+        S3Path::BusinessDocument,
+        Some(business_id),
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn upload_business_file_fails_without_business() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let res = presign_business(&app, &token, S3Path::BusinessDocument, None).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn upload_business_file_uses_path_rules() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    // Logos are small images only
+    let res = presign_business(&app, &token, S3Path::BusinessLogo, Some(business_id)).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+/// Uploads and confirms a verification document of a new business
+async fn upload_business_document(app: &TestApp, token: &str) -> PresignedUpload {
+    let business_id = app.create_business().await;
+
+    let res = presign_business(app, token, S3Path::BusinessDocument, Some(business_id)).await;
+    let presigned = res.json::<PresignedUpload>().await.unwrap();
+
+    let res = send_upload(app, &presigned.uri, &presigned, vec![7; 2_000_000]).await;
+    assert!(res.status().is_success());
+    let res = confirm(app, token, &presigned.upload_id).await;
+    assert!(res.status().is_success());
+
+    presigned
+}
+
+async fn download(app: &TestApp, token: &str, upload_id: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/upload/{}/download", &app.address, upload_id))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn download_private_file_works_for_member() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let presigned = upload_business_document(&app, &token).await;
+
+    let res = download(&app, &token, &presigned.upload_id).await;
+    assert!(res.status().is_success());
+
+    let body = res.json::<serde_json::Value>().await.unwrap();
+    let res = app
+        .api_client
+        .get(body["url"].as_str().unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    assert_eq!(res.bytes().await.unwrap().len(), 2_000_000);
+
+    let logged = sqlx::query_scalar!(
+        r#"
+            select count(*) as "count!"
+            from file_access_log
+            where key = $1 and user_id = $2
+        "#,
+        presigned.key,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(logged, 1);
+}
+
+#[tokio::test]
+async fn download_private_file_fails_for_other_user() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let presigned = upload_business_document(&app, &token).await;
+
+    let other = register_new_user(&app).await;
+    let res = download(&app, &other.access_token, &presigned.upload_id).await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn private_file_is_not_served_without_signature() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let presigned = upload_business_document(&app, &token).await;
+
+    let res = download(&app, &token, &presigned.upload_id).await;
+    let body = res.json::<serde_json::Value>().await.unwrap();
+    let mut url = reqwest::Url::parse(body["url"].as_str().unwrap()).unwrap();
+    url.set_query(None);
+
+    let res = app
+        .api_client
+        .get(url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+async fn create_multipart(
+    app: &TestApp,
+    token: &str,
+    business_id: Uuid,
+    file_size: i64,
+) -> serde_json::Value {
+    let res = app
+        .api_client
+        .post(format!("{}/upload/multipart", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&serde_json::json!({
+            "path": S3Path::BusinessMenu,
+            "business_id": business_id,
+            "file_name": "menu.pdf",
+            "file_type": "application/pdf",
+            "file_size": file_size
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+
+    res.json().await.unwrap()
+}
+
+/// Uploads the parts with the presigned URLs, returns them with their `ETag`
+async fn upload_parts(
+    app: &TestApp,
+    token: &str,
+    upload_id: &str,
+    part_sizes: &[usize],
+) -> Vec<serde_json::Value> {
+    let part_numbers = (1..=part_sizes.len()).collect::<Vec<_>>();
+    let presigned = app
+        .api_client
+        .post(format!("{}/upload/{}/parts", &app.address, upload_id))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&serde_json::json!({ "part_numbers": part_numbers }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+
+    let mut parts = Vec::new();
+    for (part, size) in presigned.iter().zip(part_sizes) {
+        let res = app
+            .api_client
+            .put(part["uri"].as_str().unwrap())
+            .body(vec![1u8; *size])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert!(res.status().is_success());
+
+        parts.push(serde_json::json!({
+            "part_number": part["part_number"],
+            "etag": res.headers()["etag"].to_str().unwrap(),
+        }));
+    }
+
+    parts
+}
+
+async fn complete_multipart(
+    app: &TestApp,
+    token: &str,
+    upload_id: &str,
+    parts: Vec<serde_json::Value>,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/upload/{}/complete", &app.address, upload_id))
+        .header("Authorization", "Bearer ".to_owned() + token)
+        .json(&serde_json::json!({ "parts": parts }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn multipart_upload_works() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let part_size = app.config.multipart.part_size as usize;
+    let file_size = part_size + 1_000;
+
+    let upload = create_multipart(&app, &token, business_id, file_size as i64).await;
+    assert_eq!(upload["part_count"], 2);
+
+    let upload_id = upload["upload_id"].as_str().unwrap();
+    let parts = upload_parts(&app, &token, upload_id, &[part_size, 1_000]).await;
+
+    let res = complete_multipart(&app, &token, upload_id, parts).await;
+    assert!(res.status().is_success());
+
+    let confirmed = res.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(confirmed["status"], "confirmed");
+
+    let res = app
+        .api_client
+        .get(confirmed["url"].as_str().unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(res.bytes().await.unwrap().len(), file_size);
+}
+
+#[tokio::test]
+async fn multipart_complete_fails_for_missing_parts() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let part_size = app.config.multipart.part_size as usize;
+    let upload = create_multipart(&app, &token, business_id, part_size as i64 + 1_000).await;
+
+    let upload_id = upload["upload_id"].as_str().unwrap();
+    let parts = upload_parts(&app, &token, upload_id, &[part_size]).await;
+
+    let res = complete_multipart(&app, &token, upload_id, parts).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn single_upload_fails_above_part_size() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let res = app
+        .api_client
+        .post(format!("{}/upload", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({
+            "path": S3Path::BusinessMenu,
+            "business_id": business_id,
+            "file_name": "menu.pdf",
+            "file_type": "application/pdf",
+            "file_size": app.config.multipart.part_size + 1
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn abort_multipart_upload_works() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let upload = create_multipart(&app, &token, business_id, 1_000).await;
+    let upload_id = upload["upload_id"].as_str().unwrap();
+
+    let res = app
+        .api_client
+        .post(format!("{}/upload/{}/abort", &app.address, upload_id))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = app
+        .api_client
+        .post(format!("{}/upload/{}/parts", &app.address, upload_id))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({ "part_numbers": [1] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn abandoned_multipart_uploads_are_aborted() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+    let business_id = app.create_business().await;
+
+    let upload = create_multipart(&app, &token, business_id, 1_000).await;
+    let upload_id = upload["upload_id"]
+        .as_str()
+        .unwrap()
+        .parse::<Uuid>()
+        .unwrap();
+
+    sqlx::query!(
+        r#"
+            update upload
+            set multipart_started_at = now() - interval '2 days'
+            where upload_id = $1
+        "#,
+        upload_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.abort_abandoned_uploads().await;
+
+    let remaining = sqlx::query_scalar!(
+        r#"select count(*) as "count!" from upload where upload_id = $1"#,
+        upload_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(remaining, 0);
+}
+
+#[tokio::test]
+async fn upload_fails_above_storage_quota() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    // Everything the user can store is taken by an earlier upload
+    sqlx::query!(
+        r#"
+            insert into upload (user_id, path, key, mime_type, size)
+            values ($1, 'profile', $2, 'image/png', $3)
+        "#,
+        &app.test_user.user_id,
+        format!("profile/{}/full.png", app.test_user.user_id),
+        app.config.quota.user_bytes
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let res = app
+        .api_client
+        .post(format!("{}/upload", &app.address))
+        .header("Authorization", "Bearer ".to_owned() + &token)
+        .json(&serde_json::json!({
+            "path": S3Path::Profile,
+            "file_name": "avatar.png",
+            "file_type": "image/png",
+            "file_size": 1_000
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = res.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["errors"]["file_size"][0], "quota_exceeded");
+}
+
+#[tokio::test]
+async fn orphaned_objects_are_reported_and_deleted() {
+    let app = spawn_app().await;
+    let token = app.login_and_get_token().await;
+
+    let image = app.upload_profile_image(&token).await;
+    let replaced = app.upload_profile_image(&token).await;
+    let orphan = app.upload_profile_image(&token).await;
+
+    sqlx::query!(
+        r#"update "user" set image = $1 where user_id = $2"#,
+        image,
+        &app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Replaced before the grace period started
+    sqlx::query!(
+        "update upload set confirmed_at = now() - interval '2 days' where key = $1",
+        replaced
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    sqlx::query!("delete from upload where key = $1", orphan)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    for key in [&image, &replaced, &orphan] {
+        app.age_stored_object(key);
+    }
+
+    let report = app.clean_orphans(true).await;
+    let mut reported = report
+        .objects
+        .iter()
+        .map(|object| object.key.clone())
+        .collect::<Vec<_>>();
+    reported.sort();
+    let mut expected = vec![replaced.clone(), orphan.clone()];
+    expected.sort();
+
+    assert_eq!(reported, expected);
+    assert_eq!(report.stale_uploads, 1);
+    assert!(app.stored_object_path(&orphan).exists());
+
+    app.clean_orphans(false).await;
+
+    assert!(app.stored_object_path(&image).exists());
+    assert!(!app.stored_object_path(&replaced).exists());
+    assert!(!app.stored_object_path(&orphan).exists());
+
+    let remaining = sqlx::query_scalar!(
+        r#"select count(*) as "count!" from upload where key = $1"#,
+        replaced
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[tokio::test]
+async fn dicebear_avatars_are_migrated() {
+    let app = spawn_app().await;
+
+    sqlx::query!(
+        r#"update "user" set image = $1 where user_id = $2"#,
+        "https://api.dicebear.com/9.x/thumbs/svg?seed=test",
+        &app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.migrate_avatars().await;
+
+    let image = sqlx::query_scalar!(
+        r#"select image as "image!" from "user" where user_id = $1"#,
+        &app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        image,
+        format!("profile/{}/identicon.png", app.test_user.user_id)
+    );
+
+    // Same user, same avatar
+    let bytes = std::fs::read(app.stored_object_path(&image)).unwrap();
+    assert_eq!(
+        bytes,
+        avatar_generator::generate_avatar(&app.test_user.user_id.to_string()).unwrap()
+    );
+
+    app.process_images().await;
+
+    let processed = sqlx::query_scalar!(
+        r#"select processed_at is not null as "processed!" from upload where key = $1"#,
+        image
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(processed);
+}